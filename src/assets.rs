@@ -0,0 +1,272 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Fingerprinted static assets.
+//!
+//! Every file under the asset directories is hashed from disk at build time, so the
+//! digest always matches what gets deployed. Stylesheet templates (`*.css.in`) are
+//! rendered first, to the same path under `dirs.public` (`web/hack.css.in` →
+//! `pub/web/hack.css`), with their `url()` references to other assets (fonts)
+//! versioned. Generated pages then get `?v=<sha256>` appended to every quoted site
+//! path of a known asset outside the code samples of their markdown, and the
+//! path → URL map is written to `pub/assets.json`.
+
+use crate::cache::{BuildCache, CacheKey};
+use crate::config::Config;
+use crate::rend::normalize;
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+use walkdir::WalkDir;
+
+/// Directories (relative to the site root) whose files are versioned
+pub const ASSET_DIRS: [&str; 3] = ["css", "web", "favicon"];
+/// Asset manifest written to `dirs.public`
+pub const MANIFEST: &str = "assets.json";
+/// Suffix of stylesheet templates rendered to the file without it
+const TEMPLATE_SUFFIX: &str = ".in";
+
+/// `url(...)` in a stylesheet, optionally quoted
+static CSS_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"url\(\s*(['"]?)([^'")]+)['"]?\s*\)"#).expect("Invalid regex"));
+
+/// A quoted (or parenthesised) site path without query or fragment
+static SITE_PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(["'(])(/[^"'()?#\s]+)(["')])"#).expect("Invalid regex"));
+
+/// A `<pre>` or `<code>` element of rendered markdown, whose paths are sample text
+/// rather than links
+static CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<pre\b.*?</pre>|<code\b.*?</code>").expect("Invalid regex"));
+
+/// Versioned URLs of the site's static assets
+#[derive(Debug, Default, Clone)]
+pub struct Assets {
+    /// Site path (`/css/main.css`) → versioned URL (`/css/main.css?v=<sha256>`)
+    urls: BTreeMap<String, String>,
+}
+
+impl Assets {
+    /// Hash the assets under `root`, render stylesheet templates and write the manifest
+    pub fn build(
+        config: &Config,
+        root: &Path,
+        cache: &mut BuildCache,
+        generator: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let mut assets = Self::default();
+        let mut templates = Vec::new();
+
+        for dir in ASSET_DIRS {
+            let walk = WalkDir::new(root.join(dir)).sort_by_file_name();
+            for entry in walk.into_iter().filter_map(Result::ok) {
+                let path = entry.path();
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                if Self::template_output(config, root, path).is_some() {
+                    templates.push(path.to_path_buf());
+                } else {
+                    assets.add(root, path, &fs::read(path)?);
+                }
+            }
+        }
+
+        // Rendered after the walk, so every asset they reference already has its hash
+        for template in templates {
+            let output =
+                Self::template_output(config, root, &template).expect("collected as a template");
+            let css = assets.rewrite_css(root, &template, &fs::read_to_string(&template)?);
+
+            if let Some(dir) = output.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let key = CacheKey::new().with(generator).with(&css).finish();
+            if cache.write(&output, key, &css)? {
+                let display = output.strip_prefix(root).unwrap_or(&output);
+                eprintln!("Generated: {}", display.display());
+            }
+            assets.add(root, &output, css.as_bytes());
+        }
+
+        let manifest = PathBuf::from(&config.dirs.public).join(MANIFEST);
+        let content = serde_json::to_string_pretty(&assets.urls)?;
+        let key = CacheKey::new().with(generator).with(&content).finish();
+        if cache.write(&manifest, key, content)? {
+            eprintln!("Generated: {}", manifest.display());
+        }
+
+        Ok(assets)
+    }
+
+    /// `web/hack.css.in` → `pub/web/hack.css`: rendered stylesheets are outputs, kept
+    /// out of the source tree in `dirs.public`
+    fn template_output(config: &Config, root: &Path, path: &Path) -> Option<PathBuf> {
+        let name = path.file_name()?.to_str()?.strip_suffix(TEMPLATE_SUFFIX)?;
+        let relative = path.strip_prefix(root).unwrap_or(path).with_file_name(name);
+        Path::new(name)
+            .extension()
+            .is_some_and(|ext| ext == "css")
+            .then(|| root.join(&config.dirs.public).join(relative))
+    }
+
+    fn add(&mut self, root: &Path, path: &Path, content: &[u8]) {
+        let site_path = site_path(path.strip_prefix(root).unwrap_or(path));
+        let url = format!("{site_path}?v={:x}", Sha256::digest(content));
+        self.urls.insert(site_path, url);
+    }
+
+    /// Versioned URL of the asset at site path `path`, if it is one
+    pub fn url(&self, path: &str) -> Option<&str> {
+        self.urls.get(path).map(String::as_str)
+    }
+
+    /// Digest of every asset version, for cache keys of pages that link to them
+    pub fn fingerprint(&self) -> String {
+        let mut key = CacheKey::new();
+        for url in self.urls.values() {
+            key = key.with(url);
+        }
+        key.finish()
+    }
+
+    /// Version the quoted site paths of known assets in rendered markdown, leaving
+    /// code samples as they are
+    pub fn rewrite_markdown(&self, html: &str) -> String {
+        let mut rewritten = String::with_capacity(html.len());
+        let mut last = 0;
+        for code in CODE.find_iter(html) {
+            rewritten.push_str(&self.rewrite_html(&html[last..code.start()]));
+            rewritten.push_str(code.as_str());
+            last = code.end();
+        }
+        rewritten.push_str(&self.rewrite_html(&html[last..]));
+        rewritten
+    }
+
+    /// Version the quoted site paths of known assets in generated HTML
+    /// (attributes, inline script imports and inline style `url()`s)
+    pub fn rewrite_html(&self, html: &str) -> String {
+        SITE_PATH
+            .replace_all(html, |caps: &Captures| match self.url(&caps[2]) {
+                Some(url) => format!("{}{url}{}", &caps[1], &caps[3]),
+                None => caps[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    /// Version the `url()` references of the stylesheet template `template`.
+    /// Relative references are resolved against its directory and become absolute,
+    /// so they hold wherever it is rendered to; any query they had (a manual version)
+    /// is replaced.
+    fn rewrite_css(&self, root: &Path, template: &Path, css: &str) -> String {
+        let dir = template
+            .strip_prefix(root)
+            .unwrap_or(template)
+            .parent()
+            .unwrap_or(Path::new(""));
+
+        CSS_URL
+            .replace_all(css, |caps: &Captures| {
+                let reference = caps[2].trim();
+                let path = reference.split(['?', '#']).next().unwrap_or_default();
+                let site_path = match path.strip_prefix('/') {
+                    Some(_) => path.to_string(),
+                    None if path.contains(':') => return caps[0].to_string(),
+                    None => site_path(&normalize(&dir.join(path))),
+                };
+                match self.url(&site_path) {
+                    Some(url) => format!("url({0}{url}{0})", &caps[1]),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// `web/fonts/a.woff2` → `/web/fonts/a.woff2`
+fn site_path(path: &Path) -> String {
+    let parts = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_versions_assets_and_renders_stylesheets() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("web/fonts")).unwrap();
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::create_dir_all(root.join("pub")).unwrap();
+        fs::write(root.join("web/fonts/hack.woff2"), "font").unwrap();
+        fs::write(
+            root.join("web/hack.css.in"),
+            "src: url('fonts/hack.woff2?sha={{ ink }}') url(\"../css/none.png\") url(data:x)",
+        )
+        .unwrap();
+        fs::write(root.join("css/main.css"), "body {}").unwrap();
+
+        let config = Config::parse(&format!(
+            "[dirs]\npublic = \"{}\"",
+            root.join("pub").display()
+        ))
+        .unwrap();
+        let mut cache = BuildCache::load("/nonexistent/.enkronio-cache", false);
+        let assets = Assets::build(&config, root, &mut cache, "test").unwrap();
+
+        let font = format!("/web/fonts/hack.woff2?v={:x}", Sha256::digest("font"));
+        let css = fs::read_to_string(root.join("pub/web/hack.css")).unwrap();
+        assert_eq!(
+            css,
+            format!("src: url('{font}') url(\"../css/none.png\") url(data:x)")
+        );
+        assert_eq!(
+            assets.url("/pub/web/hack.css"),
+            Some(format!("/pub/web/hack.css?v={:x}", Sha256::digest(&css)).as_str())
+        );
+        assert!(assets.url("/web/hack.css.in").is_none());
+        assert!(!root.join("web/hack.css").exists());
+        assert!(assets.url("/css/main.css").is_some());
+
+        let manifest = fs::read_to_string(root.join("pub").join(MANIFEST)).unwrap();
+        assert!(manifest.contains(&font));
+    }
+
+    #[test]
+    fn test_rewrite_html() {
+        let mut assets = Assets::default();
+        assets.urls.insert(
+            "/web/pkg/enkronio.js".to_string(),
+            "/web/pkg/enkronio.js?v=1".to_string(),
+        );
+        assets
+            .urls
+            .insert("/css/main.css".to_string(), "/css/main.css?v=2".to_string());
+
+        let html = "<link href=\"/css/main.css\"><a href=\"/css/main.css?v=0\">\
+                    <a href=\"/cv.html\">import init from '/web/pkg/enkronio.js';";
+        assert_eq!(
+            assets.rewrite_html(html),
+            "<link href=\"/css/main.css?v=2\"><a href=\"/css/main.css?v=0\">\
+             <a href=\"/cv.html\">import init from '/web/pkg/enkronio.js?v=1';"
+        );
+
+        let html = "<pre><code>&lt;link href='/css/main.css'&gt;</code></pre>\
+                    <p><code>url('/css/main.css')</code> <a href='/css/main.css'></a></p>";
+        assert_eq!(
+            assets.rewrite_markdown(html),
+            "<pre><code>&lt;link href='/css/main.css'&gt;</code></pre>\
+             <p><code>url('/css/main.css')</code> <a href='/css/main.css?v=2'></a></p>"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Build metadata shown in the page footer (build number and commit).
+//!
+//! Metadata comes from a [`Provider`]: the CI environment (GitHub Actions, GitLab CI),
+//! the local `git` checkout, or nothing at all. With `provider = "auto"` under
+//! `[build]` the first one that has something to say wins; a build without any shows
+//! no build line rather than placeholder text. Nothing here depends on the wall
+//! clock, so two builds of the same commit render the same footer.
+
+use crate::config::{BuildProvider, Config};
+use std::process::Command;
+
+/// Build number and commit of the running build
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BuildInfo {
+    pub number: Option<String>,
+    pub sha: Option<String>,
+}
+
+/// A source of build metadata
+pub trait Provider {
+    /// Metadata of the current build, or `None` if this provider doesn't apply
+    fn detect(&self, env: &dyn Fn(&str) -> Option<String>) -> Option<BuildInfo>;
+}
+
+/// GitHub Actions: `GITHUB_RUN_NUMBER` and `GITHUB_SHA`
+pub struct GitHub;
+
+impl Provider for GitHub {
+    fn detect(&self, env: &dyn Fn(&str) -> Option<String>) -> Option<BuildInfo> {
+        Some(BuildInfo {
+            number: env("GITHUB_RUN_NUMBER"),
+            sha: Some(env("GITHUB_SHA")?),
+        })
+    }
+}
+
+/// GitLab CI: `CI_PIPELINE_IID` and `CI_COMMIT_SHA`
+pub struct GitLab;
+
+impl Provider for GitLab {
+    fn detect(&self, env: &dyn Fn(&str) -> Option<String>) -> Option<BuildInfo> {
+        Some(BuildInfo {
+            number: env("CI_PIPELINE_IID"),
+            sha: Some(env("CI_COMMIT_SHA")?),
+        })
+    }
+}
+
+/// Local checkout: `HEAD` and its commit count
+pub struct Git;
+
+impl Git {
+    fn run(args: &[&str]) -> Option<String> {
+        let output = Command::new("git").args(args).output().ok()?;
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        Some(stdout.trim().to_string()).filter(|out| output.status.success() && !out.is_empty())
+    }
+}
+
+impl Provider for Git {
+    fn detect(&self, _env: &dyn Fn(&str) -> Option<String>) -> Option<BuildInfo> {
+        Some(BuildInfo {
+            number: Self::run(&["rev-list", "--count", "HEAD"]),
+            sha: Some(Self::run(&["rev-parse", "HEAD"])?),
+        })
+    }
+}
+
+/// Metadata from the provider configured under `[build]`
+pub fn detect(config: &Config) -> BuildInfo {
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    detect_with(config.build.provider, &env)
+}
+
+fn detect_with(provider: BuildProvider, env: &dyn Fn(&str) -> Option<String>) -> BuildInfo {
+    let providers: &[&dyn Provider] = match provider {
+        BuildProvider::Auto => &[&GitHub, &GitLab, &Git],
+        BuildProvider::GitHub => &[&GitHub],
+        BuildProvider::GitLab => &[&GitLab],
+        BuildProvider::Git => &[&Git],
+        BuildProvider::None => &[],
+    };
+    providers
+        .iter()
+        .find_map(|provider| provider.detect(env))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value).to_string())
+        }
+    }
+
+    #[test]
+    fn test_ci_providers() {
+        let github = env(&[("GITHUB_RUN_NUMBER", "42"), ("GITHUB_SHA", "abc")]);
+        assert_eq!(
+            detect_with(BuildProvider::Auto, &github),
+            BuildInfo {
+                number: Some("42".to_string()),
+                sha: Some("abc".to_string()),
+            }
+        );
+
+        let gitlab = env(&[("CI_COMMIT_SHA", "def")]);
+        assert_eq!(
+            detect_with(BuildProvider::GitLab, &gitlab),
+            BuildInfo {
+                number: None,
+                sha: Some("def".to_string()),
+            }
+        );
+        assert_eq!(
+            detect_with(BuildProvider::GitHub, &gitlab),
+            BuildInfo::default()
+        );
+    }
+
+    #[test]
+    fn test_none_provider() {
+        let github = env(&[("GITHUB_SHA", "abc")]);
+        assert_eq!(
+            detect_with(BuildProvider::None, &github),
+            BuildInfo::default()
+        );
+    }
+}
@@ -0,0 +1,241 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Persistent build manifest for incremental site builds.
+//!
+//! Every generated output is recorded together with a key derived from all of its
+//! inputs (source bytes, layout/CSS hashes, navigation neighbours, generator binary).
+//! On the next build an output is regenerated only when its key changed or the file
+//! has disappeared from disk.
+//!
+//! The manifest doubles as the list of generated files: outputs recorded by an earlier
+//! build that the current one no longer produces are [`stale`](BuildCache::stale).
+
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const CACHE_VERSION: &str = "2";
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct BuildCache {
+    version: String,
+    outputs: BTreeMap<String, String>,
+    /// Digest of the source file each page was generated from, see `source`
+    #[serde(default)]
+    sources: BTreeMap<String, String>,
+    #[serde(skip)]
+    force: bool,
+    /// Outputs this build has looked up or written, see `stale`
+    #[serde(skip)]
+    claimed: Mutex<BTreeSet<String>>,
+}
+
+impl BuildCache {
+    /// Load the manifest from `path`.
+    ///
+    /// A missing, unreadable or outdated manifest yields an empty cache, so the worst
+    /// case is a full rebuild. With `force` every lookup misses.
+    pub fn load(path: &str, force: bool) -> Self {
+        let cache = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Self>(&content).ok())
+            .filter(|cache| cache.version == CACHE_VERSION);
+
+        Self {
+            force,
+            ..cache.unwrap_or_else(|| Self {
+                version: CACHE_VERSION.to_string(),
+                ..Self::default()
+            })
+        }
+    }
+
+    /// Write the manifest back to `path`
+    pub fn save(&self, path: &str) -> Result<(), anyhow::Error> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Check whether `output` exists and was generated from inputs matching `key`.
+    /// Either way `output` belongs to this build and won't be reported as stale.
+    pub fn is_fresh(&self, output: &Path, key: &str) -> bool {
+        let output = output.to_string_lossy().into_owned();
+        let recorded = self.outputs.get(&output);
+        self.claimed
+            .lock()
+            .expect("claimed outputs lock poisoned")
+            .insert(output.clone());
+
+        !self.force
+            && Path::new(&output).exists()
+            && recorded.is_some_and(|recorded| recorded == key)
+    }
+
+    /// Remember that `output` was generated from inputs matching `key`
+    pub fn record(&mut self, output: &Path, key: String) {
+        let output = output.to_string_lossy().into_owned();
+        self.claimed
+            .get_mut()
+            .expect("claimed outputs lock poisoned")
+            .insert(output.clone());
+        self.outputs.insert(output, key);
+    }
+
+    /// Remember the [`digest`] of the source file `output` was generated from
+    pub fn record_source(&mut self, output: &Path, digest: String) {
+        self.sources
+            .insert(output.to_string_lossy().into_owned(), digest);
+    }
+
+    /// Digest of the source file `output` was last generated from. A page whose key
+    /// is still fresh was generated from the same source, so this stays accurate
+    /// across builds that skip it.
+    pub fn source(&self, output: &Path) -> Option<&str> {
+        self.sources
+            .get(&*output.to_string_lossy())
+            .map(String::as_str)
+    }
+
+    /// Whether this build has already looked up or written `output`
+    pub fn is_claimed(&self, output: &Path) -> bool {
+        self.claimed
+            .lock()
+            .expect("claimed outputs lock poisoned")
+            .contains(&*output.to_string_lossy())
+    }
+
+    /// Outputs recorded by an earlier build that this one hasn't produced
+    pub fn stale(&self) -> Vec<PathBuf> {
+        let claimed = self.claimed.lock().expect("claimed outputs lock poisoned");
+        self.outputs
+            .keys()
+            .filter(|output| !claimed.contains(*output))
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Drop the record of `output`, once it has been removed
+    pub fn forget(&mut self, output: &Path) {
+        self.outputs.remove(&*output.to_string_lossy());
+        self.sources.remove(&*output.to_string_lossy());
+    }
+
+    /// Every recorded output, in path order
+    pub fn outputs(&self) -> impl Iterator<Item = &Path> {
+        self.outputs.keys().map(Path::new)
+    }
+
+    /// Write `content` to `output` and record it, unless the output for `key` is
+    /// already current. Returns whether the file was written.
+    pub fn write(
+        &mut self,
+        output: &Path,
+        key: String,
+        content: impl AsRef<[u8]>,
+    ) -> Result<bool, anyhow::Error> {
+        if self.is_fresh(output, &key) {
+            return Ok(false);
+        }
+        fs::write(output, content)?;
+        self.record(output, key);
+        Ok(true)
+    }
+}
+
+/// Incremental hasher for cache keys.
+///
+/// Each part is length-prefixed so that `("ab", "c")` and `("a", "bc")` differ.
+pub struct CacheKey(Sha256);
+
+impl CacheKey {
+    pub fn new() -> Self {
+        Self(Sha256::new())
+    }
+
+    pub fn with(mut self, part: impl AsRef<[u8]>) -> Self {
+        let part = part.as_ref();
+        self.0.update((part.len() as u64).to_le_bytes());
+        self.0.update(part);
+        self
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+/// Digest of a source file's bytes, as recorded by `record_source`
+pub fn digest(bytes: impl AsRef<[u8]>) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Fingerprint of the running generator.
+///
+/// Hashing the executable means any change to the renderer (templates, PDF layout,
+/// markdown options) invalidates the cache without a manual version bump.
+pub fn generator_fingerprint() -> String {
+    std::env::current_exe().and_then(fs::read).map_or_else(
+        |_| env!("CARGO_PKG_VERSION").to_string(),
+        |bytes| format!("{:x}", Sha256::digest(bytes)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key_is_length_prefixed() {
+        let a = CacheKey::new().with("ab").with("c").finish();
+        let b = CacheKey::new().with("a").with("bc").finish();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_is_fresh_requires_matching_key_and_existing_output() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let output = dir.path().join("page.html");
+        fs::write(&output, "x").unwrap();
+
+        let mut cache = BuildCache::load("/nonexistent/.enkronio-cache", false);
+        assert!(!cache.is_fresh(&output, "k1"));
+
+        cache.record(&output, "k1".to_string());
+        assert!(cache.is_fresh(&output, "k1"));
+        assert!(!cache.is_fresh(&output, "k2"));
+
+        fs::remove_file(&output).unwrap();
+        assert!(!cache.is_fresh(&output, "k1"));
+    }
+
+    #[test]
+    fn test_force_always_misses() {
+        let mut cache = BuildCache::load("/nonexistent/.enkronio-cache", true);
+        let output = Path::new("Cargo.toml");
+        cache.record(output, "k".to_string());
+        assert!(!cache.is_fresh(output, "k"));
+    }
+
+    #[test]
+    fn test_stale_outputs_are_those_not_claimed_again() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(".enkronio-cache");
+        let path = path.to_str().unwrap();
+
+        let mut cache = BuildCache::load(path, false);
+        cache.record(Path::new("pub/entries/1.html"), "k1".to_string());
+        cache.record(Path::new("pub/entries/2.html"), "k2".to_string());
+        assert!(cache.stale().is_empty());
+        cache.save(path).unwrap();
+
+        let mut cache = BuildCache::load(path, true);
+        cache.is_fresh(Path::new("pub/entries/1.html"), "k1");
+        assert_eq!(cache.stale(), [PathBuf::from("pub/entries/2.html")]);
+
+        cache.forget(Path::new("pub/entries/2.html"));
+        assert!(cache.stale().is_empty());
+        assert_eq!(cache.outputs().count(), 1);
+    }
+}
@@ -0,0 +1,236 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Internal link checker for the generated site.
+//!
+//! Every `href`/`src` of the generated HTML that points inside the site is resolved
+//! against the output tree, and `#fragment`s against the ids of the target page.
+//! Broken links are reported at the line of the markdown source that contains them,
+//! or at the generated file when the link comes from a template.
+
+use crate::rend::normalize;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use walkdir::WalkDir;
+
+static LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\s(?:href|src)="([^"]*)""#).expect("Invalid regex"));
+static ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\s(?:id|name)="([^"]*)""#).expect("Invalid regex"));
+/// `scheme:` of an absolute URL
+static SCHEME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").expect("Invalid regex"));
+
+/// A link that doesn't resolve
+#[derive(Debug, PartialEq)]
+pub struct Broken {
+    /// Markdown source or generated file containing the link
+    pub file: PathBuf,
+    pub line: usize,
+    pub link: String,
+    pub reason: &'static str,
+}
+
+/// Check every generated page under `root` and report broken links on stderr.
+/// `sources` maps generated pages (relative to `root`) to their markdown sources.
+pub fn run(
+    root: &Path,
+    dirs: &[&str],
+    sources: &HashMap<PathBuf, PathBuf>,
+) -> Result<(), anyhow::Error> {
+    let pages = pages(root, dirs);
+    let broken = find_broken(root, &pages, sources)?;
+
+    for link in &broken {
+        eprintln!(
+            "{}:{}: {} `{}`",
+            link.file.display(),
+            link.line,
+            link.reason,
+            link.link
+        );
+    }
+    if !broken.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} broken link(s) in {} page(s)",
+            broken.len(),
+            pages.len()
+        ));
+    }
+
+    eprintln!("Checked: {} page(s), no broken links", pages.len());
+    Ok(())
+}
+
+/// Generated HTML: the pages at the site root plus everything under `dirs`
+fn pages(root: &Path, dirs: &[&str]) -> Vec<PathBuf> {
+    let top_level = WalkDir::new(root).min_depth(1).max_depth(1);
+    let nested = dirs
+        .iter()
+        .map(|dir| WalkDir::new(root.join(dir)).min_depth(1));
+
+    let mut pages = std::iter::once(top_level)
+        .chain(nested)
+        .flat_map(IntoIterator::into_iter)
+        .filter_map(Result::ok)
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "html"))
+        .filter_map(|path| path.strip_prefix(root).ok().map(Path::to_path_buf))
+        .collect::<Vec<_>>();
+    pages.sort();
+    pages.dedup();
+    pages
+}
+
+fn find_broken(
+    root: &Path,
+    pages: &[PathBuf],
+    sources: &HashMap<PathBuf, PathBuf>,
+) -> Result<Vec<Broken>, anyhow::Error> {
+    let mut ids: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut broken = Vec::new();
+
+    for page in pages {
+        let html = fs::read_to_string(root.join(page))?;
+        for link in LINK.captures_iter(&html) {
+            let (whole, [target]) = link.extract();
+            let target = target.replace("&amp;", "&");
+            let Some(reason) = check_link(root, page, &target, &mut ids)? else {
+                continue;
+            };
+
+            let offset =
+                link.get(0).map_or(0, |m| m.start()) + whole.len() - whole.trim_start().len();
+            let html_line = html[..offset].matches('\n').count() + 1;
+            let (file, line) = sources
+                .get(page)
+                .and_then(|source| source_line(source, &target).map(|line| (source.clone(), line)))
+                .unwrap_or_else(|| (page.clone(), html_line));
+            broken.push(Broken {
+                file,
+                line,
+                link: target,
+                reason,
+            });
+        }
+    }
+
+    Ok(broken)
+}
+
+/// Why `link` on `page` doesn't resolve, or `None` if it does (or leaves the site)
+fn check_link(
+    root: &Path,
+    page: &Path,
+    link: &str,
+    ids: &mut HashMap<PathBuf, Vec<String>>,
+) -> Result<Option<&'static str>, anyhow::Error> {
+    if link.is_empty() || link.starts_with("//") || SCHEME.is_match(link) {
+        return Ok(None);
+    }
+
+    let (path, fragment) = link.split_once('#').unwrap_or((link, ""));
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+    let mut target = if path.is_empty() {
+        page.to_path_buf()
+    } else if let Some(absolute) = path.strip_prefix('/') {
+        normalize(Path::new(absolute))
+    } else {
+        normalize(&page.parent().unwrap_or(Path::new("")).join(path))
+    };
+    if path.ends_with('/') || root.join(&target).is_dir() {
+        target.push("index.html");
+    }
+
+    if !root.join(&target).is_file() {
+        return Ok(Some("broken link"));
+    }
+    if fragment.is_empty() || target.extension().is_none_or(|ext| ext != "html") {
+        return Ok(None);
+    }
+
+    if !ids.contains_key(&target) {
+        let html = fs::read_to_string(root.join(&target))?;
+        let found = ID
+            .captures_iter(&html)
+            .map(|id| id.extract::<1>().1[0].to_string())
+            .collect();
+        ids.insert(target.clone(), found);
+    }
+    let found = ids[&target].iter().any(|id| id == fragment);
+    Ok((!found).then_some("missing anchor"))
+}
+
+/// First line of `source` mentioning `link` as a whole URL (not as the prefix of a longer one)
+fn source_line(source: &Path, link: &str) -> Option<usize> {
+    let text = fs::read_to_string(source).ok()?;
+    let mentions = |line: &str| {
+        line.match_indices(link).any(|(start, _)| {
+            line[start + link.len()..]
+                .chars()
+                .next()
+                .is_none_or(|ch| !(ch.is_alphanumeric() || "/.-_#?%=&~".contains(ch)))
+        })
+    };
+    text.lines().position(mentions).map(|index| index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broken_links_and_anchors() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("pub/entries")).unwrap();
+        fs::write(
+            root.join("pub/entries/1.html"),
+            "<h2 id=\"setup\">x</h2>\n<a href=\"#setup\">ok</a>\n<a href=\"2.html\">missing</a>\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("index.html"),
+            "<a href=\"/pub/entries/1.html#setup\">ok</a> <a href=\"https://example.com/x\">ext</a>\n\
+             <link href=\"/pub/\">\n<a href=\"/pub/entries/1.html#nope\">x</a>\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("in")).unwrap();
+        fs::write(
+            root.join("in/index.md"),
+            "intro\n\n[x](/pub/entries/1.html#nope)\n",
+        )
+        .unwrap();
+
+        let pages = pages(root, &["pub"]);
+        assert_eq!(pages.len(), 2);
+        let sources = HashMap::from([(PathBuf::from("index.html"), root.join("in/index.md"))]);
+        let broken = find_broken(root, &pages, &sources).unwrap();
+
+        assert_eq!(
+            broken,
+            [
+                Broken {
+                    file: PathBuf::from("index.html"),
+                    line: 2,
+                    link: "/pub/".to_string(),
+                    reason: "broken link",
+                },
+                Broken {
+                    file: root.join("in/index.md"),
+                    line: 3,
+                    link: "/pub/entries/1.html#nope".to_string(),
+                    reason: "missing anchor",
+                },
+                Broken {
+                    file: PathBuf::from("pub/entries/1.html"),
+                    line: 3,
+                    link: "2.html".to_string(),
+                    reason: "broken link",
+                },
+            ]
+        );
+    }
+}
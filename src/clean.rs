@@ -0,0 +1,89 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Removal of generated outputs.
+//!
+//! The build cache records every file a build writes, so it is also the list of what
+//! is safe to delete. A build prunes the outputs an earlier build recorded but it no
+//! longer produces (a deleted or renumbered entry, an entry moved to shadow, a tag
+//! nobody uses anymore), and `clean` removes every recorded output and the cache.
+//! Nothing that isn't in the cache is ever touched.
+
+use crate::cache::BuildCache;
+use crate::config::Config;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Remove every output recorded in the build cache, then the cache itself.
+/// With `dry_run` only list them.
+pub fn run(config: &Config, dry_run: bool) -> Result<(), anyhow::Error> {
+    let cache_file = PathBuf::from(&config.dirs.cache);
+    let mut files = BuildCache::load(&config.dirs.cache, false)
+        .outputs()
+        .filter(|output| output.is_file())
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    if cache_file.is_file() {
+        files.push(cache_file);
+    }
+
+    if files.is_empty() {
+        eprintln!("Nothing to clean");
+        return Ok(());
+    }
+    for file in &files {
+        if dry_run {
+            eprintln!("Would remove: {}", file.display());
+        } else {
+            remove(file)?;
+            eprintln!("Removed: {}", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// Remove a generated file and the directories it leaves empty.
+/// Only directories of relative paths are removed, never the working directory.
+pub fn remove(file: &Path) -> Result<(), anyhow::Error> {
+    fs::remove_file(file)
+        .map_err(|e| anyhow::anyhow!("failed to remove {}: {e}", file.display()))?;
+    if file.is_absolute() {
+        return Ok(());
+    }
+    for dir in file.ancestors().skip(1) {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_removes_recorded_outputs_only() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        let entries = root.join("pub/entries");
+        fs::create_dir_all(&entries).unwrap();
+        fs::write(entries.join("1.html"), "generated").unwrap();
+        fs::write(root.join("pub/notes.txt"), "hand-written").unwrap();
+
+        let cache_path = root.join(".enkronio-cache");
+        let config =
+            Config::parse(&format!("[dirs]\ncache = \"{}\"\n", cache_path.display())).unwrap();
+        let mut cache = BuildCache::load(&config.dirs.cache, false);
+        cache.record(&entries.join("1.html"), "k".to_string());
+        cache.record(&entries.join("2.html"), "k".to_string());
+        cache.save(&config.dirs.cache).unwrap();
+
+        run(&config, true).unwrap();
+        assert!(entries.join("1.html").is_file());
+        assert!(cache_path.is_file());
+
+        run(&config, false).unwrap();
+        assert!(!entries.join("1.html").exists());
+        assert!(!cache_path.exists());
+        assert!(root.join("pub/notes.txt").is_file());
+    }
+}
@@ -0,0 +1,331 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Site configuration loaded from `enkronio.toml`.
+//!
+//! Every key is optional: a missing file or a missing key falls back to the layout
+//! this repository has always used (`in/` → `pub/`, `priv/`, `download/`), so the
+//! config only needs to list what differs.
+//!
+//! ```toml
+//! title = "enk junkyard"
+//! base_url = "https://enkron.github.io"
+//! author = "Sergei Belokon"
+//!
+//! [dirs]
+//! content = "in"
+//! entries = "in/entries"
+//!
+//! [[pdf]]
+//! source = "cv.md"
+//! output = "sbelokon"
+//! ```
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Config file looked up in the working directory when `--config` is not given
+pub const DEFAULT_CONFIG_PATH: &str = "enkronio.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Site title shown in the browser tab
+    pub title: String,
+    /// Absolute URL the site is deployed under, without a trailing slash
+    pub base_url: String,
+    /// Author name embedded in exported documents
+    pub author: String,
+    pub dirs: Dirs,
+    pub feeds: Feeds,
+    pub build: Build,
+    /// Pages exported to PDF, relative to `dirs.content`
+    pub pdf: Vec<PdfExport>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dirs {
+    /// Markdown sources (every `.md`/`.enc` below it is rendered)
+    pub content: String,
+    /// Generated PDFs
+    pub download: String,
+    /// Generated public pages
+    pub public: String,
+    /// Generated shadow pages
+    pub private: String,
+    /// Public entry sources
+    pub entries: String,
+    /// Shadow entry sources
+    pub shadow_entries: String,
+    /// Blog index page source
+    pub junkyard: String,
+    /// Tracks which entries are encrypted
+    pub lockfile: String,
+    /// Redirects from old URLs, see `redirects`
+    pub redirects: String,
+    /// Incremental build manifest
+    pub cache: String,
+    /// Page templates overriding the built-in ones
+    pub templates: String,
+    /// Files embedded into pages by the `include` shortcode
+    pub snippets: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Feeds {
+    /// Also write an Atom feed per tag (`pub/tags/<tag>.xml`)
+    pub per_tag: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Build {
+    /// Where the build number and commit in the footer come from
+    pub provider: BuildProvider,
+}
+
+/// Source of build metadata, see `build_info`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildProvider {
+    /// The first of GitHub, GitLab and git that has metadata
+    #[default]
+    Auto,
+    #[serde(rename = "github")]
+    GitHub,
+    #[serde(rename = "gitlab")]
+    GitLab,
+    Git,
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PdfExport {
+    /// Markdown source relative to `dirs.content`
+    pub source: String,
+    /// Output file name (without `.pdf`) inside `dirs.download`
+    pub output: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            title: "enk junkyard".to_string(),
+            base_url: "https://enkron.github.io".to_string(),
+            author: "Sergei Belokon".to_string(),
+            dirs: Dirs::default(),
+            feeds: Feeds::default(),
+            build: Build::default(),
+            pdf: vec![
+                PdfExport {
+                    source: "cv.md".to_string(),
+                    output: "sbelokon".to_string(),
+                },
+                PdfExport {
+                    source: "index.md".to_string(),
+                    output: "cover".to_string(),
+                },
+            ],
+        }
+    }
+}
+
+impl Default for Dirs {
+    fn default() -> Self {
+        Self {
+            content: "in".to_string(),
+            download: "download".to_string(),
+            public: "pub".to_string(),
+            private: "priv".to_string(),
+            entries: "in/entries".to_string(),
+            shadow_entries: "in/entries/shadow".to_string(),
+            junkyard: "in/junkyard.md".to_string(),
+            lockfile: ".enkronio-locks".to_string(),
+            redirects: "redirects.toml".to_string(),
+            cache: ".enkronio-cache".to_string(),
+            templates: "templates".to_string(),
+            snippets: "snippets".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from `path`, or from `enkronio.toml` if it exists.
+    ///
+    /// An explicitly requested file must exist; the default one is optional.
+    pub fn load(path: Option<&Path>) -> Result<Self, anyhow::Error> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => (Path::new(DEFAULT_CONFIG_PATH), false),
+        };
+
+        if !required && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Parse configuration from TOML text
+    pub fn parse(content: &str) -> Result<Self, anyhow::Error> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Source directory for public or shadow entries
+    pub fn entries_dir(&self, shadow: bool) -> &str {
+        if shadow {
+            &self.dirs.shadow_entries
+        } else {
+            &self.dirs.entries
+        }
+    }
+
+    /// Whether `dir` is the public or shadow entries directory, however either is spelled
+    /// (see `rend::same_path`)
+    pub fn is_entries_dir(&self, dir: &Path, shadow: bool) -> bool {
+        crate::rend::same_path(dir, Path::new(self.entries_dir(shadow)))
+    }
+
+    /// Output directory for public or shadow entry pages
+    pub fn entries_output_dir(&self, shadow: bool) -> PathBuf {
+        let root = if shadow {
+            &self.dirs.private
+        } else {
+            &self.dirs.public
+        };
+        PathBuf::from(root).join("entries")
+    }
+
+    /// URL prefix (with trailing slash) of public or shadow entry pages
+    pub fn entries_url(&self, shadow: bool) -> String {
+        format!("/{}/", self.entries_output_dir(shadow).display())
+    }
+
+    /// Output directory of tag listing pages
+    pub fn tags_output_dir(&self) -> PathBuf {
+        PathBuf::from(&self.dirs.public).join("tags")
+    }
+
+    /// URL prefix (with trailing slash) of tag listing pages
+    pub fn tags_url(&self) -> String {
+        format!("/{}/", self.tags_output_dir().display())
+    }
+
+    /// URL of a file generated at the top of `dirs.public`, e.g. `/pub/atom.xml`
+    pub fn public_url(&self, file: &str) -> String {
+        format!("/{}/{file}", self.dirs.public)
+    }
+
+    /// Absolute URL of a site path such as `/pub/atom.xml`
+    pub fn absolute_url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url.trim_end_matches('/'))
+    }
+
+    /// Output path of the generated junkyard page
+    pub fn junkyard_output(&self) -> PathBuf {
+        let mut path = PathBuf::from(&self.dirs.public).join(
+            Path::new(&self.dirs.junkyard)
+                .file_name()
+                .unwrap_or_else(|| "junkyard.md".as_ref()),
+        );
+        path.set_extension("html");
+        path
+    }
+
+    /// URL of the generated junkyard page
+    pub fn junkyard_url(&self) -> String {
+        format!("/{}", self.junkyard_output().display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.title, "enk junkyard");
+        assert_eq!(config.dirs.entries, "in/entries");
+        assert_eq!(config.dirs.lockfile, ".enkronio-locks");
+        assert_eq!(config.pdf.len(), 2);
+        assert_eq!(config.pdf[0].output, "sbelokon");
+    }
+
+    #[test]
+    fn test_partial_config_overrides_only_given_keys() {
+        let config = Config::parse(
+            r#"
+title = "notes"
+
+[dirs]
+public = "site"
+
+[[pdf]]
+source = "cv.md"
+output = "resume"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.title, "notes");
+        assert_eq!(config.dirs.public, "site");
+        assert_eq!(config.dirs.private, "priv");
+        assert_eq!(config.pdf.len(), 1);
+        assert_eq!(config.pdf[0].output, "resume");
+    }
+
+    #[test]
+    fn test_build_provider() {
+        assert_eq!(Config::default().build.provider, BuildProvider::Auto);
+        let config = Config::parse("[build]\nprovider = \"gitlab\"").unwrap();
+        assert_eq!(config.build.provider, BuildProvider::GitLab);
+        assert!(Config::parse("[build]\nprovider = \"jenkins\"").is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(Config::parse("titel = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn test_derived_urls() {
+        let config = Config::default();
+        assert_eq!(config.entries_url(false), "/pub/entries/");
+        assert_eq!(config.entries_url(true), "/priv/entries/");
+        assert_eq!(config.junkyard_url(), "/pub/junkyard.html");
+        assert_eq!(config.tags_url(), "/pub/tags/");
+        assert_eq!(
+            config.absolute_url("/pub/atom.xml"),
+            "https://enkron.github.io/pub/atom.xml"
+        );
+    }
+
+    #[test]
+    fn test_is_entries_dir_normalizes_paths() {
+        let config = Config::parse("[dirs]\nentries = \"./in/entries/\"").unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        for path in [
+            "./in/entries/3-x.md",
+            "in/entries/3-x.md",
+            "in/../in/entries/3-x.md",
+        ] {
+            assert!(
+                config.is_entries_dir(Path::new(path).parent().unwrap(), false),
+                "{path}"
+            );
+        }
+        assert!(config.is_entries_dir(&cwd.join("in/entries"), false));
+        assert!(config.is_entries_dir(Path::new("./in/entries/shadow"), true));
+        assert!(!config.is_entries_dir(Path::new("./in/entries/shadow"), false));
+    }
+
+    #[test]
+    fn test_missing_explicit_config_is_an_error() {
+        assert!(Config::load(Some(Path::new("/nonexistent/enkronio.toml"))).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Index of all blog entries with their metadata.
+//!
+//! Listings (the junkyard, tag pages and anything else that enumerates entries) are
+//! built from this. Plaintext entries contribute their front matter;
+//! locked entries can't be read at build time, so their title, date and tags come from
+//! the lockfile record written by `lock`, falling back to the file name.
+
+use crate::config::Config;
+use crate::front_matter::{self, FrontMatter, Page};
+use crate::shortcode::Registry;
+use crate::Lockfile;
+use chrono::{DateTime, FixedOffset};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+/// A public or shadow entry
+#[derive(Debug)]
+pub struct Entry {
+    pub number: u32,
+    pub shadow: bool,
+    pub locked: bool,
+    pub meta: FrontMatter,
+    /// Dated after the build's `--as-of` time: built and listed only from then on
+    pub scheduled: bool,
+    /// Markdown body without front matter; `None` for locked entries
+    pub markdown: Option<String>,
+}
+
+impl Entry {
+    /// URL of the generated entry page
+    pub fn url(&self, config: &Config) -> String {
+        format!("{}{}.html", config.entries_url(self.shadow), self.number)
+    }
+
+    /// Whether the entry may appear in public listings (junkyard, tag pages, feeds)
+    pub fn is_listed(&self) -> bool {
+        !self.shadow && !self.meta.draft && !self.scheduled
+    }
+
+    /// Title to show in listings, falling back to `entry N`
+    pub fn title(&self) -> String {
+        self.meta
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("entry {}", self.number))
+    }
+}
+
+/// Collect public and shadow entries, newest first (by date, then number).
+/// Entries dated after `as_of` are marked as scheduled.
+///
+/// Visibility comes from the directory. A public entry whose front matter says
+/// `shadow = true` is an error rather than a silently published private entry.
+///
+/// Entries whose front matter fails to parse are skipped here, and shortcodes that
+/// fail to expand are left as they are; the build reports both when rendering the
+/// page itself.
+pub fn collect(
+    config: &Config,
+    lockfile: &Lockfile,
+    shortcodes: &Registry,
+    as_of: DateTime<FixedOffset>,
+) -> Result<Vec<Entry>, anyhow::Error> {
+    let mut entries = Vec::new();
+
+    for shadow in [false, true] {
+        let dir = Path::new(config.entries_dir(shadow));
+        if !dir.is_dir() {
+            continue;
+        }
+
+        for dir_entry in fs::read_dir(dir)? {
+            let source = dir_entry?.path();
+            let Some(number) = number_from_path(&source) else {
+                continue;
+            };
+            let extension = source
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(str::to_ascii_lowercase);
+
+            let (locked, meta, markdown) = match extension.as_deref() {
+                Some("md") => {
+                    let text = fs::read_to_string(&source)?;
+                    let text = shortcodes.expand(&source, &text).unwrap_or(text);
+                    let Ok(page) = Page::parse(&text) else {
+                        continue;
+                    };
+                    if page.meta.shadow && !shadow {
+                        return Err(anyhow::anyhow!(
+                            "{} is marked `shadow = true` but is in {}, where it would be \
+                             published; move it with `enkronio unpublish {number}p`, or \
+                             remove the key",
+                            source.display(),
+                            config.entries_dir(false)
+                        ));
+                    }
+                    (false, page.meta, Some(page.body))
+                }
+                Some("enc") => (true, locked_meta(lockfile, number, shadow, &source), None),
+                _ => continue,
+            };
+
+            entries.push(Entry {
+                number,
+                shadow,
+                locked,
+                scheduled: meta.date.is_some_and(|date| date > as_of),
+                meta,
+                markdown,
+            });
+        }
+    }
+
+    entries.sort_by_key(|entry| Reverse((entry.meta.date, entry.number, entry.shadow)));
+    Ok(entries)
+}
+
+/// Entry number from a file name like `3-ipv6-local-networking.md`
+pub fn number_from_path(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    let (number, _) = name.split_once('-')?;
+    number.parse().ok()
+}
+
+/// Metadata of a locked entry as recorded in the lockfile
+fn locked_meta(lockfile: &Lockfile, number: u32, shadow: bool, source: &Path) -> FrontMatter {
+    let record = lockfile
+        .locked_entries
+        .iter()
+        .rev()
+        .find(|e| e.number == number && e.shadow == shadow);
+
+    FrontMatter {
+        title: record
+            .and_then(|e| e.title.clone())
+            .or_else(|| title_from_file_name(source)),
+        date: record
+            .and_then(|e| e.date.as_deref())
+            .and_then(|date| front_matter::parse_date(date).ok()),
+        tags: record.map(|e| e.tags.clone()).unwrap_or_default(),
+        draft: record.is_some_and(|e| e.draft),
+        shadow,
+        ..FrontMatter::default()
+    }
+}
+
+/// `4-secret-notes.enc` → `secret notes`
+fn title_from_file_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let (_, slug) = stem.split_once('-')?;
+    Some(slug.replace('-', " ")).filter(|title| !title.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LockedEntry;
+
+    #[test]
+    fn test_number_from_path() {
+        assert_eq!(number_from_path(Path::new("in/entries/3-ipv6.md")), Some(3));
+        assert_eq!(number_from_path(Path::new("12-notes.enc")), Some(12));
+        assert_eq!(number_from_path(Path::new("index.md")), None);
+    }
+
+    #[test]
+    fn test_locked_meta_prefers_lockfile_record() {
+        let lockfile = Lockfile {
+            version: "1.0".to_string(),
+            locked_entries: vec![LockedEntry {
+                number: 4,
+                shadow: false,
+                created: String::new(),
+                title: Some("Secret Notes".to_string()),
+                date: Some("2024-05-24".to_string()),
+                tags: vec!["private".to_string()],
+                draft: false,
+            }],
+        };
+
+        let meta = locked_meta(&lockfile, 4, false, Path::new("4-secret-notes.enc"));
+        assert_eq!(meta.title.as_deref(), Some("Secret Notes"));
+        assert_eq!(meta.tags, vec!["private"]);
+        assert!(meta.date.is_some());
+
+        let meta = locked_meta(&lockfile, 5, false, Path::new("5-other-notes.enc"));
+        assert_eq!(meta.title.as_deref(), Some("other notes"));
+        assert!(meta.tags.is_empty());
+    }
+
+    #[test]
+    fn test_collect_marks_scheduled_entries() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        fs::write(root.join("1-past.md"), "+++\ndate = 2024-05-24\n+++\n").unwrap();
+        fs::write(root.join("2-future.md"), "+++\ndate = 2024-06-01\n+++\n").unwrap();
+        let config = Config::parse(&format!(
+            "[dirs]\nentries = \"{0}\"\nshadow_entries = \"{0}/shadow\"\n",
+            root.display()
+        ))
+        .unwrap();
+        let as_of = front_matter::parse_date("2024-05-30").unwrap();
+
+        let entries = collect(
+            &config,
+            &Lockfile::default(),
+            &Registry::new(&config, as_of.date_naive()),
+            as_of,
+        )
+        .unwrap();
+        let listed = entries
+            .iter()
+            .map(|entry| (entry.number, entry.scheduled, entry.is_listed()))
+            .collect::<Vec<_>>();
+        assert_eq!(listed, [(2, true, false), (1, false, true)]);
+    }
+}
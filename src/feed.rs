@@ -0,0 +1,347 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Atom, RSS and JSON feeds of public entries.
+//!
+//! Feeds carry the rendered entry HTML with site-relative links made absolute.
+//! Shadow entries never appear; locked entries appear as their title plus an
+//! "encrypted entry" summary, never with ciphertext.
+
+use crate::cache::{BuildCache, CacheKey};
+use crate::config::Config;
+use crate::entries::Entry;
+use crate::rend::{escape_html, markdown_to_html};
+use crate::tags;
+use chrono::{DateTime, FixedOffset};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+/// Summary used in place of the content of locked entries
+const LOCKED_SUMMARY: &str = "encrypted entry";
+
+/// Feed files written to `dirs.public`
+pub const ATOM: &str = "atom.xml";
+pub const RSS: &str = "rss.xml";
+pub const JSON: &str = "feed.json";
+
+/// A feed to render: its own URL plus the entries in it, newest first
+struct Feed<'a> {
+    title: String,
+    /// Site path of the feed file, e.g. `/pub/atom.xml`
+    path: String,
+    /// Site path of the page the feed mirrors
+    home: String,
+    entries: Vec<&'a Entry>,
+}
+
+impl Feed<'_> {
+    /// Most recent entry date, or the Unix epoch for an empty feed (keeps output stable)
+    fn updated(&self) -> DateTime<FixedOffset> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry_updated(entry))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Write `atom.xml`, `rss.xml` and `feed.json`, plus per-tag Atom feeds if enabled
+pub fn generate(
+    config: &Config,
+    entries: &[Entry],
+    cache: &mut BuildCache,
+    generator: &str,
+) -> Result<(), anyhow::Error> {
+    let listed = entries
+        .iter()
+        .filter(|entry| entry.is_listed())
+        .collect::<Vec<_>>();
+    let public = PathBuf::from(&config.dirs.public);
+
+    let feed = |file: &str| Feed {
+        title: config.title.clone(),
+        path: config.public_url(file),
+        home: config.junkyard_url(),
+        entries: listed.clone(),
+    };
+    let mut outputs = vec![
+        (public.join(ATOM), atom(config, &feed(ATOM))),
+        (public.join(RSS), rss(config, &feed(RSS))),
+        (public.join(JSON), json(config, &feed(JSON))?),
+    ];
+
+    if config.feeds.per_tag {
+        fs::create_dir_all(config.tags_output_dir())?;
+        for tag in tags::collect(entries) {
+            let feed = Feed {
+                title: format!("{} #{}", config.title, tag.name),
+                path: format!("{}{}.xml", config.tags_url(), tag.slug),
+                home: tags::url(config, tag.name),
+                entries: tag.entries,
+            };
+            outputs.push((
+                config.tags_output_dir().join(format!("{}.xml", tag.slug)),
+                atom(config, &feed),
+            ));
+        }
+    }
+
+    for (path, content) in outputs {
+        let key = CacheKey::new().with(generator).with(&content).finish();
+        if cache.write(&path, key, content)? {
+            eprintln!("Generated: {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Atom 1.0 document
+fn atom(config: &Config, feed: &Feed) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    let _ = writeln!(xml, "<feed xmlns=\"http://www.w3.org/2005/Atom\">");
+    let _ = writeln!(xml, "  <title>{}</title>", escape_html(&feed.title));
+    let _ = writeln!(xml, "  <id>{}</id>", config.absolute_url(&feed.home));
+    let _ = writeln!(
+        xml,
+        "  <link rel=\"self\" href=\"{}\"/>",
+        config.absolute_url(&feed.path)
+    );
+    let _ = writeln!(
+        xml,
+        "  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>",
+        config.absolute_url(&feed.home)
+    );
+    let _ = writeln!(xml, "  <updated>{}</updated>", feed.updated().to_rfc3339());
+    let _ = writeln!(
+        xml,
+        "  <author><name>{}</name></author>",
+        escape_html(&config.author)
+    );
+
+    for entry in &feed.entries {
+        let url = config.absolute_url(&entry.url(config));
+        let updated = entry_updated(entry).unwrap_or_else(|| feed.updated());
+
+        let _ = writeln!(xml, "  <entry>");
+        let _ = writeln!(xml, "    <title>{}</title>", escape_html(&entry.title()));
+        let _ = writeln!(xml, "    <id>{url}</id>");
+        let _ = writeln!(xml, "    <link rel=\"alternate\" href=\"{url}\"/>");
+        let _ = writeln!(xml, "    <updated>{}</updated>", updated.to_rfc3339());
+        if let (false, Some(date)) = (entry.locked, entry.meta.date) {
+            let _ = writeln!(xml, "    <published>{}</published>", date.to_rfc3339());
+        }
+        for tag in categories(entry) {
+            let _ = writeln!(xml, "    <category term=\"{}\"/>", escape_html(tag));
+        }
+        match content_html(config, entry) {
+            Some(html) => {
+                if let Some(description) = &entry.meta.description {
+                    let _ = writeln!(xml, "    <summary>{}</summary>", escape_html(description));
+                }
+                let _ = writeln!(
+                    xml,
+                    "    <content type=\"html\">{}</content>",
+                    escape_html(&html)
+                );
+            }
+            None => {
+                let _ = writeln!(xml, "    <summary>{LOCKED_SUMMARY}</summary>");
+            }
+        }
+        let _ = writeln!(xml, "  </entry>");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// RSS 2.0 document
+fn rss(config: &Config, feed: &Feed) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">"
+    );
+    let _ = writeln!(xml, "<channel>");
+    let _ = writeln!(xml, "  <title>{}</title>", escape_html(&feed.title));
+    let _ = writeln!(xml, "  <link>{}</link>", config.absolute_url(&feed.home));
+    let _ = writeln!(
+        xml,
+        "  <description>{}</description>",
+        escape_html(&feed.title)
+    );
+    let _ = writeln!(
+        xml,
+        "  <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+        config.absolute_url(&feed.path)
+    );
+    let _ = writeln!(
+        xml,
+        "  <lastBuildDate>{}</lastBuildDate>",
+        feed.updated().to_rfc2822()
+    );
+
+    for entry in &feed.entries {
+        let url = config.absolute_url(&entry.url(config));
+
+        let _ = writeln!(xml, "  <item>");
+        let _ = writeln!(xml, "    <title>{}</title>", escape_html(&entry.title()));
+        let _ = writeln!(xml, "    <link>{url}</link>");
+        let _ = writeln!(xml, "    <guid isPermaLink=\"true\">{url}</guid>");
+        if let (false, Some(date)) = (entry.locked, entry.meta.date) {
+            let _ = writeln!(xml, "    <pubDate>{}</pubDate>", date.to_rfc2822());
+        }
+        for tag in categories(entry) {
+            let _ = writeln!(xml, "    <category>{}</category>", escape_html(tag));
+        }
+        let description = content_html(config, entry).unwrap_or_else(|| LOCKED_SUMMARY.into());
+        let _ = writeln!(
+            xml,
+            "    <description>{}</description>",
+            escape_html(&description)
+        );
+        let _ = writeln!(xml, "  </item>");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+/// JSON Feed 1.1 document
+fn json(config: &Config, feed: &Feed) -> Result<String, anyhow::Error> {
+    let items = feed
+        .entries
+        .iter()
+        .map(|entry| {
+            let url = config.absolute_url(&entry.url(config));
+            let mut item = serde_json::json!({
+                "id": url,
+                "url": url,
+                "title": entry.title(),
+            });
+            match content_html(config, entry) {
+                Some(html) => {
+                    item["content_html"] = html.into();
+                    if let Some(description) = &entry.meta.description {
+                        item["summary"] = description.as_str().into();
+                    }
+                    if let Some(date) = entry.meta.date {
+                        item["date_published"] = date.to_rfc3339().into();
+                    }
+                    if let Some(updated) = entry.meta.updated {
+                        item["date_modified"] = updated.to_rfc3339().into();
+                    }
+                }
+                None => item["summary"] = LOCKED_SUMMARY.into(),
+            }
+            let tags = categories(entry).collect::<Vec<_>>();
+            if !tags.is_empty() {
+                item["tags"] = tags.into();
+            }
+            item
+        })
+        .collect::<Vec<_>>();
+
+    let document = serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": feed.title,
+        "home_page_url": config.absolute_url(&feed.home),
+        "feed_url": config.absolute_url(&feed.path),
+        "authors": [{ "name": config.author }],
+        "items": items,
+    });
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
+
+/// Rendered entry body with site-relative links made absolute; `None` for locked entries
+fn content_html(config: &Config, entry: &Entry) -> Option<String> {
+    let html = markdown_to_html(entry.markdown.as_deref()?, false);
+    let base = config.absolute_url("/");
+    Some(
+        html.replace("href=\"/", &format!("href=\"{base}"))
+            .replace("src=\"/", &format!("src=\"{base}")),
+    )
+}
+
+/// Tags exposed in feeds; locked entries expose nothing beyond their title
+fn categories(entry: &Entry) -> impl Iterator<Item = &str> {
+    let tags: &[String] = if entry.locked { &[] } else { &entry.meta.tags };
+    tags.iter().map(String::as_str)
+}
+
+/// Last modification date, falling back to the publication date
+fn entry_updated(entry: &Entry) -> Option<DateTime<FixedOffset>> {
+    entry.meta.updated.or(entry.meta.date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::{self, FrontMatter};
+
+    fn entries() -> Vec<Entry> {
+        let entry = |number, shadow, locked| Entry {
+            number,
+            shadow,
+            locked,
+            scheduled: false,
+            meta: FrontMatter {
+                title: Some(format!("title {number}")),
+                date: front_matter::parse_date("2024-05-24").ok(),
+                tags: vec!["net".to_string()],
+                ..FrontMatter::default()
+            },
+            markdown: (!locked).then(|| format!("body {number} [link](/pub/entries/1.html)")),
+        };
+        vec![
+            entry(3, false, true),
+            entry(2, false, false),
+            entry(1, true, false),
+        ]
+    }
+
+    fn feed(entries: &[Entry]) -> Feed<'_> {
+        Feed {
+            title: "site".to_string(),
+            path: "/pub/atom.xml".to_string(),
+            home: "/pub/junkyard.html".to_string(),
+            entries: entries.iter().filter(|entry| entry.is_listed()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_atom_locked_entry_has_summary_only() {
+        let config = Config::default();
+        let entries = entries();
+        let xml = atom(&config, &feed(&entries));
+
+        assert!(xml.contains("<title>title 3</title>"));
+        assert!(xml.contains("<summary>encrypted entry</summary>"));
+        assert!(xml.contains("body 2"));
+        assert!(xml.contains("href=&quot;https://enkron.github.io/pub/entries/1.html"));
+        assert!(!xml.contains("title 1"));
+        assert_eq!(xml.matches("<content").count(), 1);
+    }
+
+    #[test]
+    fn test_rss_and_json_skip_shadow_entries() {
+        let config = Config::default();
+        let entries = entries();
+
+        let xml = rss(&config, &feed(&entries));
+        assert!(xml.contains("<description>encrypted entry</description>"));
+        assert!(xml.contains("<pubDate>Fri, 24 May 2024 00:00:00 +0000</pubDate>"));
+        assert!(!xml.contains("title 1"));
+
+        let document: serde_json::Value =
+            serde_json::from_str(&json(&config, &feed(&entries)).unwrap()).unwrap();
+        let items = document["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["summary"], "encrypted entry");
+        assert!(items[0].get("content_html").is_none());
+        assert!(items[1]["content_html"]
+            .as_str()
+            .unwrap()
+            .contains("body 2"));
+    }
+}
@@ -0,0 +1,615 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Entry front matter.
+//!
+//! An entry may start with a metadata block, either TOML between `+++` lines or a
+//! YAML subset between `---` lines:
+//!
+//! ```text
+//! +++
+//! title = "experiments with ipv6 local networking"
+//! date = 2024-05-24
+//! tags = ["networking", "ipv6"]
+//! +++
+//! ```
+//!
+//! Entries written before front matter existed carry their metadata in the markdown
+//! itself (`# Title` heading plus an `entry-timestamp` span); [`Page::parse`] falls
+//! back to those so both styles resolve to the same [`FrontMatter`].
+
+use crate::scalar::{self, Syntax};
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static TIMESTAMP_SPAN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<span class="entry-timestamp">([^<]*)</span>"#).expect("Invalid regex")
+});
+
+/// Entry metadata, from front matter or from the legacy heading/timestamp
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub date: Option<DateTime<FixedOffset>>,
+    pub updated: Option<DateTime<FixedOffset>>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub draft: bool,
+    pub shadow: bool,
+    /// Insert a table of contents before the first section
+    pub toc: bool,
+}
+
+/// A markdown source split into metadata and body
+#[derive(Debug)]
+pub struct Page {
+    pub meta: FrontMatter,
+    /// Markdown without the front matter block
+    pub body: String,
+    /// Whether the metadata came from a front matter block
+    pub has_front_matter: bool,
+}
+
+impl Page {
+    /// Split `source` into front matter and body, falling back to the legacy
+    /// `# Title` heading and `entry-timestamp` span when there is no front matter.
+    pub fn parse(source: &str) -> Result<Self, anyhow::Error> {
+        let (block, body) = split(source)?;
+
+        let mut meta = match block {
+            Some(Block::Toml(text)) => parse_toml(text).context("Invalid TOML front matter")?,
+            Some(Block::Yaml(text)) => parse_yaml(text).context("Invalid YAML front matter")?,
+            None => FrontMatter::default(),
+        };
+
+        if meta.title.is_none() {
+            meta.title = first_heading(body);
+        }
+        if meta.date.is_none() {
+            meta.date = TIMESTAMP_SPAN
+                .captures(body)
+                .and_then(|caps| parse_legacy_timestamp(&caps[1]));
+        }
+
+        Ok(Self {
+            meta,
+            body: body.to_string(),
+            has_front_matter: block.is_some(),
+        })
+    }
+
+    /// Markdown to render: the body, preceded by a heading and timestamp built from
+    /// the front matter when the body doesn't carry its own `# Title`.
+    pub fn markdown(&self) -> String {
+        match &self.meta.title {
+            Some(title) if self.has_front_matter && first_heading(&self.body).is_none() => {
+                let mut md = format!("# {}\n\n", escape_markdown(title));
+                if let Some(date) = self.meta.date {
+                    md.push_str(&timestamp_span(date));
+                    md.push_str("\n\n");
+                }
+                md.push_str(self.body.trim_start_matches('\n'));
+                md
+            }
+            _ => self.body.clone(),
+        }
+    }
+
+    /// Short description for `<meta>` tags: the front matter `description`, or the
+    /// first paragraph of the body cut to about 160 characters
+    pub fn summary(&self) -> Option<String> {
+        const MAX_LEN: usize = 160;
+
+        if let Some(description) = &self.meta.description {
+            return Some(description.clone());
+        }
+
+        let paragraph = first_paragraph(&self.body)?;
+        if paragraph.chars().count() <= MAX_LEN {
+            return Some(paragraph);
+        }
+        let cut = paragraph
+            .char_indices()
+            .take_while(|(i, _)| *i < MAX_LEN)
+            .filter(|(_, c)| c.is_whitespace())
+            .last()
+            .map_or(MAX_LEN, |(i, _)| i);
+        Some(format!("{}…", paragraph[..cut].trim_end()))
+    }
+}
+
+enum Block<'a> {
+    Toml(&'a str),
+    Yaml(&'a str),
+}
+
+/// Separate a leading `+++`/`---` block from the rest of the document
+fn split(source: &str) -> Result<(Option<Block<'_>>, &str), anyhow::Error> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let first_line_end = source.find('\n').unwrap_or(source.len());
+    let delimiter = source[..first_line_end].trim_end();
+
+    if delimiter != "+++" && delimiter != "---" {
+        return Ok((None, source));
+    }
+
+    let rest = &source[(first_line_end + 1).min(source.len())..];
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            let text = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            let block = if delimiter == "+++" {
+                Block::Toml(text)
+            } else {
+                Block::Yaml(text)
+            };
+            return Ok((Some(block), body));
+        }
+        offset += line.len();
+    }
+
+    bail!("Front matter opened with '{delimiter}' is never closed")
+}
+
+/// A value written back into front matter by `set_field`
+pub enum Field<'a> {
+    Str(&'a str),
+    Date(DateTime<FixedOffset>),
+    Bool(bool),
+}
+
+/// Set `key` in the front matter of `source`, replacing its line if present and adding
+/// one otherwise. A source without front matter gets a new TOML block.
+pub fn set_field(source: &str, key: &str, value: &Field) -> Result<String, anyhow::Error> {
+    let (block, body) = split(source)?;
+    let (delimiter, text) = match block {
+        Some(Block::Toml(text)) => ("+++", text),
+        Some(Block::Yaml(text)) => ("---", text),
+        None => ("+++", ""),
+    };
+    let yaml = delimiter == "---";
+
+    let literal = match value {
+        Field::Str(s) if yaml => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        Field::Str(s) => toml::Value::String((*s).to_string()).to_string(),
+        Field::Date(date) if *date == date.date_naive().and_time(NaiveTime::MIN).and_utc() => {
+            date.format("%Y-%m-%d").to_string()
+        }
+        Field::Date(date) => date.to_rfc3339(),
+        Field::Bool(value) => value.to_string(),
+    };
+    let line = if yaml {
+        format!("{key}: {literal}\n")
+    } else {
+        format!("{key} = {literal}\n")
+    };
+
+    let is_key = |existing: &str| {
+        existing
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with(if yaml { ':' } else { '=' }))
+    };
+    let mut lines = text
+        .split_inclusive('\n')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    match lines.iter().position(|existing| is_key(existing)) {
+        Some(index) => lines[index] = line,
+        None => lines.push(line),
+    }
+
+    let body = if block.is_some() {
+        body.to_string()
+    } else {
+        format!("\n{body}")
+    };
+    Ok(format!(
+        "{delimiter}\n{}{delimiter}\n{body}",
+        lines.concat()
+    ))
+}
+
+/// Scalar or list value shared by the TOML and YAML readers
+enum Value {
+    Str(String),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+fn parse_toml(text: &str) -> Result<FrontMatter, anyhow::Error> {
+    let table: toml::Table = toml::from_str(text)?;
+    let fields = table.into_iter().map(|(key, value)| {
+        let value = match value {
+            toml::Value::String(s) => Value::Str(s),
+            toml::Value::Boolean(b) => Value::Bool(b),
+            toml::Value::Datetime(dt) => Value::Str(dt.to_string()),
+            toml::Value::Array(items) => Value::List(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        toml::Value::String(s) => Ok(s),
+                        other => Err(anyhow!("'{key}' must be a list of strings, got {other}")),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            other => bail!("Unsupported value for '{key}': {other}"),
+        };
+        Ok((key, value))
+    });
+    build(fields)
+}
+
+/// Parse the YAML subset used by front matter: `key: value` pairs with quoted or
+/// bare scalars, booleans, and lists either inline (`[a, b]`) or as `- item` lines.
+fn parse_yaml(text: &str) -> Result<FrontMatter, anyhow::Error> {
+    let mut fields: Vec<(String, Value)> = Vec::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = raw.trim_end();
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            match fields.last_mut() {
+                Some((_, Value::List(items))) => items.push(yaml_scalar(item)),
+                _ => bail!("line {}: list item outside of a list", index + 1),
+            }
+            continue;
+        }
+
+        let (key, value) = trimmed
+            .split_once(':')
+            .ok_or_else(|| anyhow!("line {}: expected 'key: value'", index + 1))?;
+        let key = key.trim().to_string();
+        let value = value.trim();
+
+        let value = if value.is_empty() {
+            Value::List(Vec::new())
+        } else if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Value::List(
+                inner
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(yaml_scalar)
+                    .collect(),
+            )
+        } else {
+            match value {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::Str(yaml_scalar(value)),
+            }
+        };
+        fields.push((key, value));
+    }
+
+    build(fields.into_iter().map(Ok))
+}
+
+/// A plain or quoted YAML scalar, see [`scalar::unquote`]
+fn yaml_scalar(value: &str) -> String {
+    scalar::unquote(value, Syntax::Yaml)
+}
+
+fn build(
+    fields: impl Iterator<Item = Result<(String, Value), anyhow::Error>>,
+) -> Result<FrontMatter, anyhow::Error> {
+    let mut meta = FrontMatter::default();
+
+    for field in fields {
+        let (key, value) = field?;
+        match (key.as_str(), value) {
+            ("title", Value::Str(s)) => meta.title = Some(s),
+            ("description", Value::Str(s)) => meta.description = Some(s),
+            ("date", Value::Str(s)) => meta.date = Some(parse_date(&s)?),
+            ("updated", Value::Str(s)) => meta.updated = Some(parse_date(&s)?),
+            ("tags", Value::List(tags)) => meta.tags = tags,
+            ("tags", Value::Str(tag)) => meta.tags = vec![tag],
+            ("draft", Value::Bool(b)) => meta.draft = b,
+            ("shadow", Value::Bool(b)) => meta.shadow = b,
+            ("toc", Value::Bool(b)) => meta.toc = b,
+            (
+                "title" | "description" | "date" | "updated" | "tags" | "draft" | "shadow" | "toc",
+                _,
+            ) => {
+                bail!("Wrong type for front matter key '{key}'")
+            }
+            _ => bail!("Unknown front matter key '{key}'"),
+        }
+    }
+
+    Ok(meta)
+}
+
+/// Parse a front matter date: RFC 3339, `YYYY-MM-DD HH:MM[:SS]` or `YYYY-MM-DD`.
+/// Dates without an offset are taken as UTC.
+pub fn parse_date(value: &str) -> Result<DateTime<FixedOffset>, anyhow::Error> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date);
+    }
+
+    let utc = Utc.fix();
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(naive.and_local_timezone(utc).unwrap());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is valid")
+            .and_local_timezone(utc)
+            .unwrap());
+    }
+
+    bail!("Invalid date '{value}'. Expected YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC 3339")
+}
+
+/// Plain text of the first level-1 heading, if the markdown has one
+/// `text` with ASCII punctuation backslash-escaped, so markdown renders it literally
+/// (and any HTML in it as text)
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn first_heading(markdown: &str) -> Option<String> {
+    use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+
+    let mut title: Option<String> = None;
+    for event in Parser::new(markdown) {
+        match (event, title.as_mut()) {
+            (Event::Start(Tag::Heading(HeadingLevel::H1, _, _)), None) => {
+                title = Some(String::new());
+            }
+            (Event::Text(text) | Event::Code(text), Some(title)) => title.push_str(&text),
+            (Event::End(Tag::Heading(..)), Some(_)) => break,
+            _ => {}
+        }
+    }
+    title.map(|title| title.trim().to_string())
+}
+
+/// Plain text of the first non-empty paragraph. Paragraphs containing inline HTML
+/// (such as the legacy timestamp span) are skipped.
+fn first_paragraph(markdown: &str) -> Option<String> {
+    use pulldown_cmark::{Event, Parser, Tag};
+
+    let mut text: Option<String> = None;
+    for event in Parser::new(markdown) {
+        match (event, text.as_mut()) {
+            (Event::Start(Tag::Paragraph), _) => text = Some(String::new()),
+            (Event::Html(_), Some(_)) => text = None,
+            (Event::Text(t) | Event::Code(t), Some(text)) => text.push_str(&t),
+            (Event::SoftBreak | Event::HardBreak, Some(text)) => text.push(' '),
+            (Event::End(Tag::Paragraph), Some(paragraph)) => {
+                let paragraph = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !paragraph.is_empty() {
+                    return Some(paragraph);
+                }
+                text = None;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse the timestamp written by `add` before front matter existed,
+/// e.g. `24.V.2024 13.05 UTC+2`
+pub fn parse_legacy_timestamp(text: &str) -> Option<DateTime<FixedOffset>> {
+    let mut parts = text.split_whitespace();
+    let mut date = parts.next()?.split('.');
+    let day: u32 = date.next()?.parse().ok()?;
+    let month = roman_to_month(date.next()?)?;
+    let year: i32 = date.next()?.parse().ok()?;
+
+    let (hour, minute) = match parts.next() {
+        Some(time) => {
+            let (h, m) = time.split_once('.')?;
+            (h.parse().ok()?, m.parse().ok()?)
+        }
+        None => (0, 0),
+    };
+    let offset_hours: i32 = match parts.next().and_then(|tz| tz.strip_prefix("UTC")) {
+        Some(offset) => offset.trim_start_matches('+').parse().ok()?,
+        None => 0,
+    };
+
+    let offset = FixedOffset::east_opt(offset_hours * 3600)?;
+    NaiveDate::from_ymd_opt(year, month, day)?
+        .and_hms_opt(hour, minute, 0)?
+        .and_local_timezone(offset)
+        .single()
+}
+
+/// Render the `entry-timestamp` span in the same format `add` always used
+pub fn timestamp_span(date: DateTime<FixedOffset>) -> String {
+    format!(
+        "<span class=\"entry-timestamp\">{}</span>",
+        format_timestamp(date)
+    )
+}
+
+/// Format as `DD.ROMAN_MONTH.YYYY HH.MM UTC+OFFSET`
+pub fn format_timestamp(date: DateTime<FixedOffset>) -> String {
+    use chrono::{Datelike, Timelike};
+
+    let offset = date.offset().local_minus_utc() / 3600;
+    let offset_sign = if offset >= 0 { "+" } else { "" };
+    format!(
+        "{}.{}.{} {:02}.{:02} UTC{offset_sign}{offset}",
+        date.day(),
+        crate::month_to_roman(date.month()),
+        date.year(),
+        date.hour(),
+        date.minute()
+    )
+}
+
+/// Format as `DD.ROMAN_MONTH.YYYY`, the date style used in listings
+pub fn format_date(date: DateTime<FixedOffset>) -> String {
+    use chrono::Datelike;
+
+    format!(
+        "{}.{}.{}",
+        date.day(),
+        crate::month_to_roman(date.month()),
+        date.year()
+    )
+}
+
+fn roman_to_month(roman: &str) -> Option<u32> {
+    (1..=12).find(|&month| crate::month_to_roman(month) == roman)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_falls_back_to_first_paragraph() {
+        let page = Page::parse(
+            "# Title\n\n<span class=\"entry-timestamp\">24.V.2024</span>\n\nFirst *real*\nparagraph.\n\nSecond.\n",
+        )
+        .unwrap();
+        assert_eq!(page.summary().as_deref(), Some("First real paragraph."));
+
+        let long = format!("+++\ntitle = \"t\"\n+++\n{}\n", "word ".repeat(50));
+        let summary = Page::parse(&long).unwrap().summary().unwrap();
+        assert!(summary.ends_with("word…"));
+        assert!(summary.chars().count() <= 161);
+
+        let page = Page::parse("+++\ndescription = \"given\"\n+++\nbody\n").unwrap();
+        assert_eq!(page.summary().as_deref(), Some("given"));
+    }
+
+    #[test]
+    fn test_toml_front_matter() {
+        let page = Page::parse(
+            "+++\ntitle = \"Hello\"\ndate = 2024-05-24\ntags = [\"net\", \"ipv6\"]\ndraft = true\n+++\n\nBody\n",
+        )
+        .unwrap();
+        assert!(page.has_front_matter);
+        assert_eq!(page.meta.title.as_deref(), Some("Hello"));
+        assert_eq!(page.meta.tags, vec!["net", "ipv6"]);
+        assert!(page.meta.draft);
+        assert_eq!(
+            page.meta.date.unwrap().to_rfc3339(),
+            "2024-05-24T00:00:00+00:00"
+        );
+        assert_eq!(page.body, "\nBody\n");
+    }
+
+    #[test]
+    fn test_yaml_front_matter() {
+        let page = Page::parse(
+            "---\ntitle: \"Hello: world\"\ndate: 2024-05-24 13:05\ntags:\n  - net\n  - ipv6\nshadow: true\n---\nBody\n",
+        )
+        .unwrap();
+        assert_eq!(page.meta.title.as_deref(), Some("Hello: world"));
+        assert_eq!(page.meta.tags, vec!["net", "ipv6"]);
+        assert!(page.meta.shadow);
+        assert_eq!(page.body, "Body\n");
+    }
+
+    #[test]
+    fn test_yaml_inline_list() {
+        let page = Page::parse("---\ntags: [a, 'b c']\n---\n").unwrap();
+        assert_eq!(page.meta.tags, vec!["a", "b c"]);
+    }
+
+    #[test]
+    fn test_legacy_heading_and_timestamp_fallback() {
+        let page = Page::parse(
+            "# Old Entry\n\n<span class=\"entry-timestamp\">24.V.2024 13.05 UTC+2</span>\n\ntext\n",
+        )
+        .unwrap();
+        assert!(!page.has_front_matter);
+        assert_eq!(page.meta.title.as_deref(), Some("Old Entry"));
+        assert_eq!(
+            page.meta.date.unwrap().to_rfc3339(),
+            "2024-05-24T13:05:00+02:00"
+        );
+        assert_eq!(page.markdown(), page.body);
+    }
+
+    #[test]
+    fn test_markdown_synthesizes_heading_from_front_matter() {
+        let page =
+            Page::parse("+++\ntitle = \"New\"\ndate = 2024-05-24T13:05:00+02:00\n+++\n\ntext\n")
+                .unwrap();
+        assert_eq!(
+            page.markdown(),
+            "# New\n\n<span class=\"entry-timestamp\">24.V.2024 13.05 UTC+2</span>\n\ntext\n"
+        );
+
+        let page = Page::parse("---\ntitle: \"<b>1</b> *x*\"\n---\n").unwrap();
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(&page.markdown()));
+        assert_eq!(html, "<h1>&lt;b&gt;1&lt;/b&gt; *x*</h1>\n");
+    }
+
+    #[test]
+    fn test_unknown_key_and_unclosed_block_are_errors() {
+        assert!(Page::parse("+++\nauthor = \"me\"\n+++\n").is_err());
+        assert!(Page::parse("---\ntitle: x\n").is_err());
+    }
+
+    #[test]
+    fn test_fallback_title_is_plain_text() {
+        let page = Page::parse("# [Hello](/download/cover.pdf) `world`\n").unwrap();
+        assert_eq!(page.meta.title.as_deref(), Some("Hello world"));
+    }
+
+    #[test]
+    fn test_horizontal_rule_is_not_front_matter_when_not_first() {
+        let page = Page::parse("# Title\n\n---\n\ntext\n").unwrap();
+        assert!(!page.has_front_matter);
+        assert_eq!(page.meta.title.as_deref(), Some("Title"));
+    }
+
+    #[test]
+    fn test_set_field() {
+        let date = parse_date("2023-12-26").unwrap();
+        let source = set_field("# xxxx\n", "title", &Field::Str("init")).unwrap();
+        let source = set_field(&source, "date", &Field::Date(date)).unwrap();
+        assert_eq!(
+            source,
+            "+++\ntitle = \"init\"\ndate = 2023-12-26\n+++\n\n# xxxx\n"
+        );
+        let page = Page::parse(&source).unwrap();
+        assert_eq!(page.meta.title.as_deref(), Some("init"));
+        assert_eq!(page.meta.date, Some(date));
+
+        let source = set_field("---\ntitle: a\n---\nBody\n", "title", &Field::Str("b")).unwrap();
+        assert_eq!(source, "---\ntitle: \"b\"\n---\nBody\n");
+
+        let title = r#"It's "quoted" \ escaped"#;
+        assert_eq!(
+            set_field("---\ntitle: a\n---\n", "title", &Field::Str(title)).unwrap(),
+            "---\ntitle: \"It's \\\"quoted\\\" \\\\ escaped\"\n---\n"
+        );
+        for source in ["---\ntitle: a\n---\n", "+++\ntitle = \"a\"\n+++\n"] {
+            let source = set_field(source, "title", &Field::Str(title)).unwrap();
+            let page = Page::parse(&source).unwrap();
+            assert_eq!(page.meta.title.as_deref(), Some(title), "{source}");
+        }
+        let page = Page::parse("---\ntitle: 'It''s'\n---\n").unwrap();
+        assert_eq!(page.meta.title.as_deref(), Some("It's"));
+
+        let source = set_field("+++\nshadow = true\n+++\n", "shadow", &Field::Bool(false)).unwrap();
+        assert_eq!(source, "+++\nshadow = false\n+++\n");
+    }
+}
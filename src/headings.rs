@@ -0,0 +1,163 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Heading tree of a markdown document.
+//!
+//! The HTML renderer uses it for heading ids, permalinks and the table of contents,
+//! and the PDF renderer for the document outline, so both agree on structure.
+
+use crate::rend::escape_html;
+use crate::tags;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// HTML comment, on a line of its own, replaced by the table of contents
+pub const TOC_MARKER: &str = "<!-- toc -->";
+
+/// A heading and the headings nested under it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: u32,
+    pub title: String,
+    /// Unique within the document: `{#id}` if given, else a slug of the title
+    pub id: String,
+    pub children: Vec<Heading>,
+}
+
+/// Headings of `markdown` in document order, without children
+pub fn collect(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut used = HashSet::new();
+    // Level, explicit id and title text of the heading being read
+    let mut current: Option<(u32, Option<String>, String)> = None;
+
+    for event in Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(level, id, _)) => {
+                current = Some((level_number(level), id.map(str::to_string), String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, title)) = &mut current {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, id, title)) = current.take() {
+                    let id = unique_id(&mut used, id.unwrap_or_else(|| tags::slug(&title)));
+                    headings.push(Heading {
+                        level,
+                        title,
+                        id,
+                        children: Vec::new(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+/// Nest headings under the closest preceding heading of a lower level
+pub fn tree(headings: Vec<Heading>) -> Vec<Heading> {
+    let mut roots: Vec<Heading> = Vec::new();
+    for heading in headings {
+        let mut siblings = &mut roots;
+        while siblings
+            .last()
+            .is_some_and(|last| last.level < heading.level)
+        {
+            siblings = &mut siblings.last_mut().expect("checked above").children;
+        }
+        siblings.push(heading);
+    }
+    roots
+}
+
+fn unique_id(used: &mut HashSet<String>, id: String) -> String {
+    let id = if id.is_empty() {
+        "section".to_string()
+    } else {
+        id
+    };
+    let mut candidate = id.clone();
+    let mut n = 1;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{id}-{n}");
+        n += 1;
+    }
+    candidate
+}
+
+pub fn level_number(level: HeadingLevel) -> u32 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Table of contents for `tree`. The page title (`#`) isn't listed, only what's under it.
+pub fn toc_html(tree: &[Heading]) -> String {
+    let mut items = String::new();
+    for heading in tree {
+        if heading.level == 1 {
+            toc_items(&mut items, &heading.children);
+        } else {
+            toc_items(&mut items, std::slice::from_ref(heading));
+        }
+    }
+    if items.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<nav class=\"toc\">\n<p class=\"toc-title\">Contents</p>\n<ul>\n{items}</ul>\n</nav>\n"
+    )
+}
+
+fn toc_items(html: &mut String, headings: &[Heading]) {
+    for heading in headings {
+        let _ = write!(
+            html,
+            "<li><a href=\"#{}\">{}</a>",
+            escape_html(&heading.id),
+            escape_html(&heading.title)
+        );
+        if !heading.children.is_empty() {
+            html.push_str("\n<ul>\n");
+            toc_items(html, &heading.children);
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</li>\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_slugged_and_unique() {
+        let headings = collect("# Title\n## Setup `virsh`\n## Setup virsh\n### Custom {#mine}\n");
+        let ids = headings.iter().map(|h| h.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["title", "setup-virsh", "setup-virsh-1", "mine"]);
+        assert_eq!(headings[1].title, "Setup virsh");
+    }
+
+    #[test]
+    fn test_tree_and_toc() {
+        let tree = tree(collect("# T\n## a\n### b\n### c\n## d\n"));
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].children.len(), 2);
+        assert_eq!(tree[0].children[0].children.len(), 2);
+
+        let toc = toc_html(&tree);
+        assert!(toc.contains("<li><a href=\"#a\">a</a>\n<ul>\n<li><a href=\"#b\">b</a></li>"));
+        assert!(toc.contains("<li><a href=\"#d\">d</a></li>"));
+        assert!(!toc.contains("#t\""));
+        assert_eq!(toc_html(&super::tree(collect("# only title"))), "");
+    }
+}
@@ -0,0 +1,379 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Build-time syntax highlighting of fenced code blocks.
+//!
+//! Code is split into classed spans (`hl-comment`, `hl-string`, ...) coloured by CSS
+//! variables in `css/main.css`, so highlighting follows the light/dark theme without any
+//! client-side code. The fence info string is the language followed by options:
+//!
+//! ```text
+//! ```bash linenos hl_lines=2-3,5
+//! ```
+//!
+//! `linenos` numbers the lines and `hl_lines` marks lines (1-based ranges) as highlighted.
+//! Unknown languages are rendered as plain text with the same options.
+
+use crate::rend::escape_html;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+/// Language and options of a fenced code block
+#[derive(Debug, Default, PartialEq)]
+pub struct Fence {
+    pub lang: String,
+    pub line_numbers: bool,
+    pub highlighted: Vec<RangeInclusive<usize>>,
+}
+
+impl Fence {
+    /// Parse a fence info string. Options that don't parse are ignored, so a typo
+    /// degrades to an unhighlighted block rather than a broken page.
+    pub fn parse(info: &str) -> Self {
+        let mut words = info.split_whitespace();
+        let mut fence = Self {
+            lang: words.next().unwrap_or_default().to_lowercase(),
+            ..Self::default()
+        };
+
+        for word in words {
+            if word == "linenos" {
+                fence.line_numbers = true;
+            } else if let Some(ranges) = word.strip_prefix("hl_lines=") {
+                fence
+                    .highlighted
+                    .extend(ranges.split(',').filter_map(parse_range));
+            }
+        }
+        fence
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// `3` or `2-5`
+fn parse_range(range: &str) -> Option<RangeInclusive<usize>> {
+    if let Some((start, end)) = range.split_once('-') {
+        return Some(start.parse().ok()?..=end.parse().ok()?);
+    }
+    let line = range.parse().ok()?;
+    Some(line..=line)
+}
+
+/// Lexical rules of a language
+struct Syntax {
+    comments: &'static [&'static str],
+    keywords: &'static [&'static str],
+    /// `'...'` is a string (rather than a char literal or lifetime)
+    single_quotes: bool,
+    /// Shell: `$VAR`, `${VAR}`, command names and `--options`
+    shell: bool,
+    /// Config files: `key = value` / `key: value` and `[section]` lines
+    config: bool,
+}
+
+const SHELL: Syntax = Syntax {
+    comments: &["#"],
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "export", "local",
+    ],
+    single_quotes: true,
+    shell: true,
+    config: false,
+};
+
+const CONFIG: Syntax = Syntax {
+    comments: &["#", ";"],
+    keywords: &["true", "false", "null", "yes", "no"],
+    single_quotes: true,
+    shell: false,
+    config: true,
+};
+
+const JSON: Syntax = Syntax {
+    comments: &[],
+    keywords: &["true", "false", "null"],
+    single_quotes: false,
+    shell: false,
+    config: false,
+};
+
+const RUST: Syntax = Syntax {
+    comments: &["//"],
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "else", "enum", "false",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+        "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    single_quotes: false,
+    shell: false,
+    config: false,
+};
+
+fn syntax(lang: &str) -> Option<&'static Syntax> {
+    match lang {
+        "bash" | "sh" | "shell" | "zsh" | "console" => Some(&SHELL),
+        "toml" | "ini" | "conf" | "cfg" | "yaml" | "yml" => Some(&CONFIG),
+        "json" => Some(&JSON),
+        "rust" | "rs" => Some(&RUST),
+        _ => None,
+    }
+}
+
+/// Render a fenced code block to HTML
+pub fn code_block(info: &str, code: &str) -> String {
+    let fence = Fence::parse(info);
+
+    let mut html = String::from("<pre class=\"highlight");
+    if fence.line_numbers {
+        html.push_str(" linenos");
+    }
+    html.push_str("\"><code");
+    if !fence.lang.is_empty() {
+        let _ = write!(html, " class=\"language-{}\"", escape_html(&fence.lang));
+    }
+    html.push('>');
+
+    for (index, line) in code
+        .strip_suffix('\n')
+        .unwrap_or(code)
+        .split('\n')
+        .enumerate()
+    {
+        let number = index + 1;
+        html.push_str("<span class=\"line");
+        if fence.is_highlighted(number) {
+            html.push_str(" highlighted");
+        }
+        let _ = write!(html, "\" data-line=\"{number}\">");
+        html.push_str(&highlight_line(&fence.lang, line));
+        html.push_str("</span>\n");
+    }
+
+    html.push_str("</code></pre>\n");
+    html
+}
+
+fn highlight_line(lang: &str, line: &str) -> String {
+    if matches!(lang, "diff" | "patch") {
+        return diff_line(line);
+    }
+    match syntax(lang) {
+        Some(syntax) => tokenize(syntax, line),
+        None => escape_html(line),
+    }
+}
+
+/// Whole-line classes for unified diffs
+fn diff_line(line: &str) -> String {
+    let class = if ["+++", "---", "diff ", "index "]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+    {
+        Some("hl-meta")
+    } else if line.starts_with("@@") {
+        Some("hl-hunk")
+    } else if line.starts_with('+') {
+        Some("hl-ins")
+    } else if line.starts_with('-') {
+        Some("hl-del")
+    } else {
+        None
+    };
+
+    match class {
+        Some(class) => span(class, line),
+        None => escape_html(line),
+    }
+}
+
+fn span(class: &str, text: &str) -> String {
+    format!("<span class=\"{class}\">{}</span>", escape_html(text))
+}
+
+/// Highlight one line of code. Strings and comments don't continue onto the next
+/// line, which holds for the shell and config snippets this site is made of.
+fn tokenize(syntax: &Syntax, line: &str) -> String {
+    if syntax.config && line.trim_start().starts_with('[') {
+        return span("hl-section", line);
+    }
+
+    let mut html = String::new();
+    let mut rest = line;
+    // Shell: the next word is a command name
+    let mut command_position = true;
+    // Config: nothing but whitespace so far, so the next word is a key
+    let mut line_start = true;
+
+    while let Some(ch) = rest.chars().next() {
+        let preceded_by_space = rest.len() == line.len()
+            || line[..line.len() - rest.len()].ends_with(char::is_whitespace);
+
+        if syntax
+            .comments
+            .iter()
+            .any(|comment| rest.starts_with(comment) && preceded_by_space)
+        {
+            html.push_str(&span("hl-comment", rest));
+            break;
+        }
+
+        let (class, len) = if ch == '"' || (ch == '\'' && syntax.single_quotes) {
+            ("hl-string", string_len(rest, ch))
+        } else if syntax.shell && ch == '$' && rest.len() > 1 {
+            ("hl-variable", variable_len(rest))
+        } else if syntax.shell && ch == '-' && preceded_by_space && !command_position {
+            ("hl-option", word_len(rest, true))
+        } else if ch.is_ascii_digit() && preceded_by_boundary(line, rest) {
+            ("hl-number", word_len(rest, false))
+        } else if ch.is_alphabetic() || ch == '_' {
+            let len = word_len(rest, syntax.shell || syntax.config);
+            let word = &rest[..len];
+            let class = if syntax.keywords.contains(&word) {
+                "hl-keyword"
+            } else if syntax.shell && command_position {
+                "hl-function"
+            } else if syntax.config && line_start && is_assignment(&rest[len..]) {
+                "hl-key"
+            } else {
+                ""
+            };
+            if syntax.shell {
+                command_position = word == "sudo" || class == "hl-keyword";
+            }
+            (class, len)
+        } else {
+            if syntax.shell && !ch.is_whitespace() {
+                command_position = matches!(ch, '|' | ';' | '&' | '(' | '`');
+            }
+            ("", ch.len_utf8())
+        };
+
+        if !ch.is_whitespace() {
+            line_start = false;
+        }
+        let (token, tail) = rest.split_at(len);
+        if class.is_empty() {
+            html.push_str(&escape_html(token));
+        } else {
+            html.push_str(&span(class, token));
+        }
+        rest = tail;
+    }
+
+    html
+}
+
+/// Length of a quoted string starting at `text`, up to the closing quote or end of line
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, ch) in text.char_indices().skip(1) {
+        match ch {
+            '\\' if !escaped => escaped = true,
+            _ if ch == quote && !escaped => return i + ch.len_utf8(),
+            _ => escaped = false,
+        }
+    }
+    text.len()
+}
+
+/// `$NAME`, `${...}` or a special parameter like `$?`
+fn variable_len(text: &str) -> usize {
+    if text[1..].starts_with('{') {
+        return text.find('}').map_or(text.len(), |end| end + 1);
+    }
+    let name = text[1..]
+        .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+        .unwrap_or(text.len() - 1);
+    if name == 0 {
+        // `$?`, `$#`, ...; a `$` before anything else is left as plain text
+        text[1..]
+            .chars()
+            .next()
+            .filter(|ch| "?#@*!$-".contains(*ch))
+            .map_or(1, |ch| 1 + ch.len_utf8())
+    } else {
+        1 + name
+    }
+}
+
+/// Length of the word starting at `text`; `dashed` also allows `-` and `.` inside it
+fn word_len(text: &str, dashed: bool) -> usize {
+    text.find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || (dashed && "-.".contains(ch))))
+        .unwrap_or(text.len())
+}
+
+fn preceded_by_boundary(line: &str, rest: &str) -> bool {
+    line[..line.len() - rest.len()]
+        .chars()
+        .next_back()
+        .is_none_or(|ch| !(ch.is_alphanumeric() || ch == '_' || ch == '-'))
+}
+
+/// `= value` or `: value` follows
+fn is_assignment(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with('=') || text.starts_with(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fence_options() {
+        assert_eq!(
+            Fence::parse("bash linenos hl_lines=2-3,5"),
+            Fence {
+                lang: "bash".to_string(),
+                line_numbers: true,
+                highlighted: vec![2..=3, 5..=5],
+            }
+        );
+        assert_eq!(Fence::parse(""), Fence::default());
+        assert!(Fence::parse("TOML hl_lines=x").highlighted.is_empty());
+    }
+
+    #[test]
+    fn test_shell_tokens() {
+        let html = tokenize(&SHELL, "sudo ip link add \"$NAME\" type dummy # comment");
+        assert!(html.starts_with("<span class=\"hl-function\">sudo</span> "));
+        assert!(html.contains("<span class=\"hl-function\">ip</span> link"));
+        assert!(html.contains("<span class=\"hl-string\">&quot;$NAME&quot;</span>"));
+        assert!(html.ends_with("<span class=\"hl-comment\"># comment</span>"));
+
+        let html = tokenize(&SHELL, "virsh net-autostart --disable ${NET} | grep -c 1");
+        assert!(html.contains("<span class=\"hl-option\">--disable</span>"));
+        assert!(html.contains("<span class=\"hl-variable\">${NET}</span>"));
+        assert!(html.contains("<span class=\"hl-function\">grep</span>"));
+        assert!(html.contains("<span class=\"hl-number\">1</span>"));
+        assert!(!html.contains("net-<span"));
+    }
+
+    #[test]
+    fn test_config_tokens() {
+        assert_eq!(
+            tokenize(&CONFIG, "[dirs]"),
+            "<span class=\"hl-section\">[dirs]</span>"
+        );
+        assert_eq!(
+            tokenize(&CONFIG, "per_tag = true"),
+            "<span class=\"hl-key\">per_tag</span> = <span class=\"hl-keyword\">true</span>"
+        );
+    }
+
+    #[test]
+    fn test_code_block_lines_and_diff() {
+        let html = code_block("diff linenos hl_lines=2", "--- a\n+added\n-removed\n <x>\n");
+        assert!(html.starts_with("<pre class=\"highlight linenos\"><code class=\"language-diff\">"));
+        assert!(html.contains(
+            "<span class=\"line highlighted\" data-line=\"2\"><span class=\"hl-ins\">+added</span></span>"
+        ));
+        assert!(html.contains("<span class=\"hl-del\">-removed</span>"));
+        assert!(html.contains("<span class=\"hl-meta\">--- a</span>"));
+        assert!(html.contains("data-line=\"4\"> &lt;x&gt;</span>"));
+        assert!(!html.contains("data-line=\"5\""));
+    }
+}
@@ -0,0 +1,252 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! `include` shortcode: embed a file from `dirs.snippets` as a fenced code block.
+//!
+//! ```text
+//! {{include: file="nginx/site.conf"}}                    whole file
+//! {{include: file="setup.sh", lines="3-10"}}             lines 3 to 10 (or one line: "7")
+//! {{include: file="main.rs", start="^fn main", end="^}"}}
+//! ```
+//!
+//! `start`/`end` are regexes: the range runs from the first line matching `start` to
+//! the next line matching `end`, both included (to the end of the file without `end`).
+//! The language comes from the file extension unless `lang` is given; `options` are
+//! appended to the fence info string (`linenos hl_lines=2`). The marker should be on
+//! a line of its own. Missing files and ranges that don't resolve fail the build.
+
+use crate::config::Config;
+use crate::shortcode::{Args, Document, Shortcode};
+use regex::Regex;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub struct Include {
+    dir: PathBuf,
+}
+
+impl Include {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            dir: PathBuf::from(&config.dirs.snippets),
+        }
+    }
+
+    /// Path of `file` inside the snippets directory; it may not leave it
+    fn path(&self, file: &str) -> Result<PathBuf, anyhow::Error> {
+        let relative = Path::new(file);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow::anyhow!(
+                "`file` must be a relative path inside {}",
+                self.dir.display()
+            ));
+        }
+        Ok(self.dir.join(relative))
+    }
+}
+
+impl Shortcode for Include {
+    fn name(&self) -> &'static str {
+        "include"
+    }
+
+    fn params(&self) -> &'static [&'static str] {
+        &["file", "lines", "start", "end", "lang", "options"]
+    }
+
+    fn expand(&self, args: &Args, _doc: &Document) -> Result<String, anyhow::Error> {
+        let file: String = args.get("file")?;
+        let path = self.path(&file)?;
+        let text = fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+
+        let lines = text.lines().collect::<Vec<_>>();
+        let selected = match (
+            args.get_opt::<Lines>("lines")?,
+            args.get_opt::<Regex>("start")?,
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!("`lines` and `start` can't be combined"));
+            }
+            (Some(range), None) => range.select(&lines)?,
+            (None, Some(start)) => select_between(&lines, &start, args.get_opt("end")?.as_ref())?,
+            (None, None) if args.get_opt::<String>("end")?.is_some() => {
+                return Err(anyhow::anyhow!("`end` needs `start`"));
+            }
+            (None, None) => &lines,
+        };
+
+        let lang = match args.get_opt::<String>("lang")? {
+            Some(lang) => lang,
+            None => language(&path),
+        };
+        let options: Option<String> = args.get_opt("options")?;
+        Ok(fenced(&lang, options.as_deref(), selected))
+    }
+}
+
+/// `lines="3-10"` or `lines="7"`, 1-based and inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Lines {
+    first: usize,
+    last: usize,
+}
+
+impl std::str::FromStr for Lines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || "expected a line number or range like 3-10".to_string();
+        let (first, last) = s.split_once('-').unwrap_or((s, s));
+        let first: usize = first.trim().parse().map_err(|_| invalid())?;
+        let last: usize = last.trim().parse().map_err(|_| invalid())?;
+        if first == 0 || last < first {
+            return Err(invalid());
+        }
+        Ok(Self { first, last })
+    }
+}
+
+impl Lines {
+    fn select<'a>(self, lines: &'a [&'a str]) -> Result<&'a [&'a str], anyhow::Error> {
+        if self.last > lines.len() {
+            return Err(anyhow::anyhow!(
+                "`lines` {}-{} is past the end of the file ({} lines)",
+                self.first,
+                self.last,
+                lines.len()
+            ));
+        }
+        Ok(&lines[self.first - 1..self.last])
+    }
+}
+
+/// Lines from the first matching `start` to the next matching `end`, both included
+fn select_between<'a>(
+    lines: &'a [&'a str],
+    start: &Regex,
+    end: Option<&Regex>,
+) -> Result<&'a [&'a str], anyhow::Error> {
+    let first = lines
+        .iter()
+        .position(|line| start.is_match(line))
+        .ok_or_else(|| anyhow::anyhow!("no line matches `start` '{start}'"))?;
+    let last = match end {
+        Some(end) => {
+            first
+                + lines[first..]
+                    .iter()
+                    .position(|line| end.is_match(line))
+                    .ok_or_else(|| anyhow::anyhow!("no line after `start` matches `end` '{end}'"))?
+        }
+        None => lines.len() - 1,
+    };
+    Ok(&lines[first..=last])
+}
+
+/// Fence language for a file: its extension, with a few aliases normalised
+fn language(path: &Path) -> String {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match ext.as_str() {
+        "" => "text",
+        "rs" => "rust",
+        "sh" | "bash" => "bash",
+        "yml" => "yaml",
+        "patch" => "diff",
+        other => other,
+    }
+    .to_string()
+}
+
+/// A fenced code block of `lines`, with a fence longer than any backtick run in them
+fn fenced(lang: &str, options: Option<&str>, lines: &[&str]) -> String {
+    let longest_run = lines
+        .iter()
+        .flat_map(|line| line.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    let mut block = format!("{fence}{lang}");
+    if let Some(options) = options {
+        block.push(' ');
+        block.push_str(options);
+    }
+    block.push('\n');
+    for line in lines {
+        block.push_str(line);
+        block.push('\n');
+    }
+    block.push_str(&fence);
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shortcode::Registry;
+
+    fn expand(source: &str) -> Result<String, anyhow::Error> {
+        let root = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(root.path().join("nginx")).unwrap();
+        fs::write(
+            root.path().join("setup.sh"),
+            "#!/bin/sh\nset -e\n\nmain() {\n    echo ```\n}\n\nmain\n",
+        )
+        .unwrap();
+        fs::write(root.path().join("nginx/site.conf"), "listen 80;\n").unwrap();
+
+        let config = Config::parse(&format!(
+            "[dirs]\nsnippets = \"{}\"\n",
+            root.path().display()
+        ))
+        .unwrap();
+        Registry::new(&config, chrono::NaiveDate::default())
+            .expand(Path::new("in/entries/5-setup.md"), source)
+    }
+
+    #[test]
+    fn test_include_whole_file_and_lines() {
+        assert_eq!(
+            expand(r#"{{include: file="nginx/site.conf"}}"#).unwrap(),
+            "```conf\nlisten 80;\n```"
+        );
+        assert_eq!(
+            expand(r#"{{include: file="setup.sh", lines="2", options="linenos"}}"#).unwrap(),
+            "```bash linenos\nset -e\n```"
+        );
+    }
+
+    #[test]
+    fn test_include_regex_range() {
+        assert_eq!(
+            expand(r#"{{include: file="setup.sh", start="^main", end="^\}", lang="sh"}}"#).unwrap(),
+            "````sh\nmain() {\n    echo ```\n}\n````"
+        );
+        assert_eq!(
+            expand(r#"{{include: file="setup.sh", start="^main$"}}"#).unwrap(),
+            "```bash\nmain\n```"
+        );
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let error = |source: &str| expand(source).unwrap_err().to_string();
+        assert!(error(r#"{{include: file="missing.sh"}}"#)
+            .starts_with("in/entries/5-setup.md:1: include: failed to read"));
+        assert!(error(r#"{{include: file="setup.sh", lines="7-9"}}"#).contains("past the end"));
+        assert!(error(r#"{{include: file="setup.sh", start="^exit"}}"#).contains("no line matches"));
+        assert!(
+            error(r#"{{include: file="setup.sh", start="^main", end="^exit"}}"#)
+                .contains("no line after")
+        );
+        assert!(error(r#"{{include: file="../enkronio.toml"}}"#).contains("relative path"));
+        assert!(error(r#"{{include: file="setup.sh", lines="0"}}"#).contains("invalid `lines`"));
+    }
+}
@@ -0,0 +1,357 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! The junkyard index page.
+//!
+//! `junkyard.md` is a template: the line holding [`PLACEHOLDER`] is replaced at build
+//! time by one `- DATE: [title](url)` line per listed entry, newest first, so the index
+//! follows renames, deletions and date changes without editing it by hand.
+//!
+//! Sites that still keep a hand-maintained list are migrated with `reindex`, which moves
+//! the titles and dates of that list into the entries and leaves the placeholder.
+
+use crate::config::Config;
+use crate::entries::Entry;
+use crate::front_matter::{self, Field, Page};
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use std::fmt::Write;
+use std::fs;
+
+/// HTML comment, on a line of its own, replaced by the list of entries
+pub const PLACEHOLDER: &str = "<!-- entries -->";
+
+/// Whether `source` has a line for the generated list
+pub fn has_placeholder(source: &str) -> bool {
+    source.lines().any(|line| line.trim() == PLACEHOLDER)
+}
+
+/// Replace the placeholder line of `source` with the list of `entries`
+pub fn expand(source: &str, config: &Config, entries: &[Entry]) -> String {
+    let mut expanded = String::with_capacity(source.len());
+    for line in source.split_inclusive('\n') {
+        if line.trim() == PLACEHOLDER {
+            expanded.push_str(&list(config, entries));
+        } else {
+            expanded.push_str(line);
+        }
+    }
+    expanded
+}
+
+/// One markdown list line per listed entry; `entries` are already newest first
+fn list(config: &Config, entries: &[Entry]) -> String {
+    let mut list = String::new();
+    for entry in entries.iter().filter(|entry| entry.is_listed()) {
+        let title = entry.title().replace('[', "\\[").replace(']', "\\]");
+        let url = entry.url(config);
+        let _ = match entry.meta.date {
+            Some(date) => writeln!(
+                list,
+                "- {}: [{title}]({url})",
+                front_matter::format_date(date)
+            ),
+            None => writeln!(list, "- [{title}]({url})"),
+        };
+    }
+    list
+}
+
+/// A line of a hand-maintained list: `- 24.V.2024: [title](/pub/entries/3.html)`
+struct Listed {
+    line: usize,
+    number: u32,
+    title: String,
+    date: Option<DateTime<FixedOffset>>,
+}
+
+/// Regex of a `Listed` line: date, title, entry number
+fn listed_line(config: &Config) -> Regex {
+    Regex::new(&format!(
+        r"^- (?:(\S+): )?\[(.*)\]\({}(\d+)\.html\)\s*$",
+        regex::escape(&config.entries_url(false))
+    ))
+    .expect("Invalid regex")
+}
+
+fn listed(config: &Config, source: &str) -> Vec<Listed> {
+    let line_re = listed_line(config);
+
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let caps = line_re.captures(text)?;
+            Some(Listed {
+                line,
+                number: caps[3].parse().ok()?,
+                title: caps[2].replace("\\[", "[").replace("\\]", "]"),
+                date: caps
+                    .get(1)
+                    .and_then(|date| front_matter::parse_legacy_timestamp(date.as_str())),
+            })
+        })
+        .collect()
+}
+
+/// Follow a retitled (`Some(title)`) or deleted (`None`) public entry in a
+/// hand-maintained list. A generated list follows the entries by itself.
+pub fn update_listed(config: &Config, source: &str, number: u32, title: Option<&str>) -> String {
+    let line_re = listed_line(config);
+    let mut updated = String::with_capacity(source.len());
+    for line in source.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\r', '\n']);
+        match line_re.captures(text) {
+            Some(caps) if caps[3].parse() == Ok(number) => {
+                let Some(title) = title else {
+                    continue;
+                };
+                let date = caps
+                    .get(1)
+                    .map_or(String::new(), |date| format!("{}: ", date.as_str()));
+                let title = title.replace('[', "\\[").replace(']', "\\]");
+                let url = format!("{}{number}.html", config.entries_url(false));
+                let _ = write!(updated, "- {date}[{title}]({url})");
+                updated.push_str(&line[text.len()..]);
+            }
+            _ => updated.push_str(line),
+        }
+    }
+    updated
+}
+
+/// Migrate a hand-maintained junkyard list: titles and dates move into the front
+/// matter of each entry (or the lockfile record of a locked one), and the list is
+/// replaced by the placeholder.
+pub fn reindex(config: &Config) -> Result<(), anyhow::Error> {
+    let source = fs::read_to_string(&config.dirs.junkyard)?;
+    let listed = listed(config, &source);
+
+    if listed.is_empty() {
+        if has_placeholder(&source) {
+            println!("{} is already generated", config.dirs.junkyard);
+            return Ok(());
+        }
+        let separator = if source.ends_with('\n') { "" } else { "\n" };
+        fs::write(
+            &config.dirs.junkyard,
+            format!("{source}{separator}\n{PLACEHOLDER}\n"),
+        )?;
+        println!(
+            "No entry list found; added {PLACEHOLDER} at the end of {}",
+            config.dirs.junkyard
+        );
+        return Ok(());
+    }
+
+    let mut lockfile = crate::read_lockfile(config)?;
+    let mut updated = 0;
+    let mut locked_updated = false;
+    for item in &listed {
+        match migrate_entry(config, &mut lockfile, item)? {
+            Migrated::Unchanged => {}
+            Migrated::Entry => updated += 1,
+            Migrated::Locked => {
+                updated += 1;
+                locked_updated = true;
+            }
+        }
+    }
+    if locked_updated {
+        crate::write_lockfile(config, &lockfile)?;
+    }
+
+    let first = listed[0].line;
+    let mut template = String::with_capacity(source.len());
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        if index == first {
+            template.push_str(PLACEHOLDER);
+            template.push('\n');
+        } else if !listed.iter().any(|item| item.line == index) {
+            template.push_str(line);
+        }
+    }
+    fs::write(&config.dirs.junkyard, template)?;
+
+    println!(
+        "Reindexed {} listed entries ({updated} updated); {} now holds {PLACEHOLDER}",
+        listed.len(),
+        config.dirs.junkyard
+    );
+    Ok(())
+}
+
+/// What `migrate_entry` changed
+enum Migrated {
+    Unchanged,
+    /// The front matter of a plaintext entry
+    Entry,
+    /// The lockfile record of a locked entry
+    Locked,
+}
+
+/// Record the listed title and date on the entry
+fn migrate_entry(
+    config: &Config,
+    lockfile: &mut crate::Lockfile,
+    item: &Listed,
+) -> Result<Migrated, anyhow::Error> {
+    let Ok(path) = crate::find_entry_file(config.entries_dir(false), item.number) else {
+        eprintln!(
+            "Warning: {} lists entry {} which doesn't exist; dropping it",
+            config.dirs.junkyard, item.number
+        );
+        return Ok(Migrated::Unchanged);
+    };
+
+    if path.extension().is_some_and(|ext| ext == "enc") {
+        return Ok(if migrate_locked(lockfile, item) {
+            Migrated::Locked
+        } else {
+            Migrated::Unchanged
+        });
+    }
+
+    let original = fs::read_to_string(&path)?;
+    let page = Page::parse(&original)?;
+    let mut source = original.clone();
+    if page.meta.title.as_deref() != Some(item.title.as_str()) {
+        source = front_matter::set_field(&source, "title", &Field::Str(&item.title))?;
+    }
+    if let (None, Some(date)) = (page.meta.date, item.date) {
+        source = front_matter::set_field(&source, "date", &Field::Date(date))?;
+    }
+    if source == original {
+        return Ok(Migrated::Unchanged);
+    }
+
+    fs::write(&path, source)?;
+    println!("Updated {}", path.display());
+    Ok(Migrated::Entry)
+}
+
+/// Fill the lockfile record of a locked entry. Returns whether anything changed.
+fn migrate_locked(lockfile: &mut crate::Lockfile, item: &Listed) -> bool {
+    let existing = lockfile
+        .locked_entries
+        .iter()
+        .rposition(|e| e.number == item.number && !e.shadow);
+    let index = existing.unwrap_or_else(|| {
+        lockfile.locked_entries.push(crate::LockedEntry {
+            number: item.number,
+            shadow: false,
+            created: chrono::Utc::now().to_rfc3339(),
+            title: None,
+            date: None,
+            tags: Vec::new(),
+            draft: false,
+        });
+        lockfile.locked_entries.len() - 1
+    });
+    let record = &mut lockfile.locked_entries[index];
+
+    let mut changed = false;
+    if record.title.as_deref() != Some(item.title.as_str()) {
+        record.title = Some(item.title.clone());
+        changed = true;
+    }
+    if let (None, Some(date)) = (&record.date, item.date) {
+        record.date = Some(date.to_rfc3339());
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::FrontMatter;
+
+    fn entry(number: u32, title: &str, date: Option<&str>) -> Entry {
+        Entry {
+            number,
+            shadow: false,
+            locked: false,
+            scheduled: false,
+            meta: FrontMatter {
+                title: Some(title.to_string()),
+                date: date.map(|date| front_matter::parse_date(date).unwrap()),
+                ..FrontMatter::default()
+            },
+            markdown: None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let config = Config::default();
+        let mut shadow = entry(9, "hidden", Some("2024-06-01"));
+        shadow.shadow = true;
+        let entries = [
+            shadow,
+            entry(2, "nat [libvirt]", Some("2024-01-17")),
+            entry(1, "init", None),
+        ];
+
+        let page = expand("# index\n\n<!-- entries -->\n\nfooter\n", &config, &entries);
+        assert_eq!(
+            page,
+            "# index\n\n- 17.I.2024: [nat \\[libvirt\\]](/pub/entries/2.html)\n\
+             - [init](/pub/entries/1.html)\n\nfooter\n"
+        );
+        assert!(!has_placeholder(&page));
+    }
+
+    #[test]
+    fn test_update_listed() {
+        let config = Config::default();
+        let source = "# index\n\n- 17.I.2024: [nat](/pub/entries/2.html)\n\
+                      - [init](/pub/entries/1.html)\n";
+        assert_eq!(
+            update_listed(&config, source, 2, Some("nat [libvirt]")),
+            "# index\n\n- 17.I.2024: [nat \\[libvirt\\]](/pub/entries/2.html)\n\
+             - [init](/pub/entries/1.html)\n"
+        );
+        assert_eq!(
+            update_listed(&config, source, 1, None),
+            "# index\n\n- 17.I.2024: [nat](/pub/entries/2.html)\n"
+        );
+    }
+
+    #[test]
+    fn test_reindex_migrates_list() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("entries")).unwrap();
+        let config = Config::parse(&format!(
+            "[dirs]\nentries = \"{0}/entries\"\njunkyard = \"{0}/junkyard.md\"\nlockfile = \"{0}/locks\"\n",
+            root.display()
+        ))
+        .unwrap();
+        fs::write(root.join("entries/1-initial.md"), "# xxxx\n").unwrap();
+        fs::write(
+            root.join("entries/2-nat.md"),
+            "+++\ntitle = \"nat\"\ndate = 2024-01-18\n+++\n\nBody\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("junkyard.md"),
+            "# index\n\n## posts\n\n- 17.I.2024: [nat](/pub/entries/2.html)\n\
+             - 26.XII.2023: [init](/pub/entries/1.html)\n\nbye\n",
+        )
+        .unwrap();
+
+        reindex(&config).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join("junkyard.md")).unwrap(),
+            "# index\n\n## posts\n\n<!-- entries -->\n\nbye\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("entries/1-initial.md")).unwrap(),
+            "+++\ntitle = \"init\"\ndate = 2023-12-26\n+++\n\n# xxxx\n"
+        );
+        // Front matter dates win over the listed ones
+        assert!(fs::read_to_string(root.join("entries/2-nat.md"))
+            .unwrap()
+            .contains("date = 2024-01-18"));
+    }
+}
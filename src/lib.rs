@@ -1,6 +1,11 @@
 #![warn(clippy::all, clippy::pedantic)]
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
-use web_sys::{window, Event, HtmlElement, HtmlInputElement, MediaQueryList};
+use web_sys::{window, Event, HtmlElement, HtmlInputElement, MediaQueryList, XmlHttpRequest};
 
 // Import crypto module for browser-side decryption
 use aes_gcm::{
@@ -13,6 +18,9 @@ use argon2::{
 };
 use base64::prelude::*;
 
+mod scalar;
+use scalar::Syntax;
+
 /// Theme preference options: light, dark, or auto (follow system)
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum ThemePreference {
@@ -68,6 +76,9 @@ pub fn main() -> Result<(), JsValue> {
     // Initialize locked entry decryption UI (if present)
     init_locked_entry()?;
 
+    // Initialize the search box (if present)
+    init_search()?;
+
     Ok(())
 }
 
@@ -446,6 +457,15 @@ fn markdown_to_html(markdown: &str) -> String {
     let mut html = String::new();
     let mut in_code_block = false;
 
+    let (title, markdown) = split_front_matter(markdown);
+    if let Some(title) = title {
+        if !markdown.lines().any(|line| line.starts_with("# ")) {
+            html.push_str("<h1>");
+            html.push_str(&html_escape(&title));
+            html.push_str("</h1>\n");
+        }
+    }
+
     for line in markdown.lines() {
         if line.starts_with("```") {
             in_code_block = !in_code_block;
@@ -484,6 +504,37 @@ fn markdown_to_html(markdown: &str) -> String {
     html
 }
 
+/// Strip a leading `+++` (TOML) or `---` (YAML) front matter block.
+/// Returns its `title`, if any, and the remaining markdown.
+fn split_front_matter(markdown: &str) -> (Option<String>, &str) {
+    let Some((delimiter, syntax)) = [("+++", Syntax::Toml), ("---", Syntax::Yaml)]
+        .into_iter()
+        .find(|(delimiter, _)| markdown.lines().next().map(str::trim_end) == Some(*delimiter))
+    else {
+        return (None, markdown);
+    };
+
+    let mut title = None;
+    let mut offset = markdown.find('\n').map_or(markdown.len(), |pos| pos + 1);
+    for line in markdown[offset..].split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim();
+        if line == delimiter {
+            return (title, &markdown[offset..]);
+        }
+        let value = line
+            .strip_prefix("title")
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix('=').or_else(|| rest.strip_prefix(':')));
+        if let Some(value) = value {
+            title = Some(scalar::unquote(value, syntax));
+        }
+    }
+
+    // Unterminated block: render everything as-is
+    (None, markdown)
+}
+
 /// Process inline HTML - allows certain safe HTML tags while escaping others
 fn process_inline_html(s: &str) -> String {
     // Allow <span> tags with class attributes (for timestamps, etc.)
@@ -537,6 +588,318 @@ fn process_inline_html(s: &str) -> String {
     result
 }
 
+// ============================================================================
+// Full-text Search
+// ============================================================================
+
+/// Search index written by the site build (see src/search.rs)
+#[derive(Debug, Deserialize)]
+struct SearchIndex {
+    docs: Vec<SearchDoc>,
+    /// Token → `[document, weight]` pairs
+    terms: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchDoc {
+    url: String,
+    title: String,
+    date: Option<String>,
+    text: String,
+}
+
+const MAX_RESULTS: usize = 10;
+// Bytes of text shown on either side of the first match in a snippet
+const SNIPPET_CONTEXT: usize = 80;
+
+/// Loading state of the search index, shared by the event handlers
+enum IndexState {
+    Unloaded,
+    Loading,
+    Loaded(Rc<SearchIndex>),
+    Failed,
+}
+
+thread_local! {
+    static SEARCH_INDEX: RefCell<IndexState> = const { RefCell::new(IndexState::Unloaded) };
+}
+
+/// Initialize the search box if present on the page.
+/// The index is only fetched once the box is focused or typed into.
+///
+/// # Errors
+/// Returns an error if DOM elements cannot be accessed (ignored if no search box)
+fn init_search() -> Result<(), JsValue> {
+    let window = window().ok_or("no window")?;
+    let document = window.document().ok_or("no document")?;
+
+    let Some(input) = document.get_element_by_id("search-input") else {
+        return Ok(()); // No search box on this page
+    };
+    let input = input.dyn_into::<HtmlInputElement>()?;
+
+    let focus_closure = Closure::wrap(Box::new(move |_event: Event| {
+        let _ = load_search_index();
+    }) as Box<dyn FnMut(Event)>);
+    input.add_event_listener_with_callback("focus", focus_closure.as_ref().unchecked_ref())?;
+    focus_closure.forget();
+
+    let input_closure = Closure::wrap(Box::new(move |_event: Event| {
+        let _ = load_search_index();
+        let _ = update_search_results();
+    }) as Box<dyn FnMut(Event)>);
+    input.add_event_listener_with_callback("input", input_closure.as_ref().unchecked_ref())?;
+    input_closure.forget();
+
+    // Escape clears the query and closes the results
+    let input_clone = input.clone();
+    let escape_closure = Closure::wrap(Box::new(move |event: Event| {
+        if let Some(keyboard_event) = event.dyn_ref::<web_sys::KeyboardEvent>() {
+            if keyboard_event.key() == "Escape" {
+                input_clone.set_value("");
+                let _ = update_search_results();
+            }
+        }
+    }) as Box<dyn FnMut(Event)>);
+    input.add_event_listener_with_callback("keydown", escape_closure.as_ref().unchecked_ref())?;
+    escape_closure.forget();
+
+    Ok(())
+}
+
+/// Fetch the search index named by the search box's `data-index` attribute, once
+///
+/// # Errors
+/// Returns an error if the DOM cannot be accessed or the request cannot be sent.
+fn load_search_index() -> Result<(), JsValue> {
+    let unloaded = SEARCH_INDEX.with(|state| matches!(*state.borrow(), IndexState::Unloaded));
+    if !unloaded {
+        return Ok(());
+    }
+
+    let document = window()
+        .ok_or("no window")?
+        .document()
+        .ok_or("no document")?;
+    let url = document
+        .get_element_by_id("search-input")
+        .ok_or("no search-input")?
+        .get_attribute("data-index")
+        .ok_or("no data-index attribute")?;
+
+    let request = XmlHttpRequest::new()?;
+    request.open("GET", &url)?;
+
+    let request_clone = request.clone();
+    let load_closure = Closure::wrap(Box::new(move |_event: Event| {
+        let index = match request_clone.status() {
+            Ok(200) => request_clone
+                .response_text()
+                .ok()
+                .flatten()
+                .and_then(|json| serde_json::from_str::<SearchIndex>(&json).ok()),
+            _ => None,
+        };
+        let state = index.map_or(IndexState::Failed, |index| {
+            IndexState::Loaded(Rc::new(index))
+        });
+        SEARCH_INDEX.with(|cell| *cell.borrow_mut() = state);
+        let _ = update_search_results();
+    }) as Box<dyn FnMut(Event)>);
+    request.set_onloadend(Some(load_closure.as_ref().unchecked_ref()));
+    load_closure.forget();
+
+    request.send()?;
+    SEARCH_INDEX.with(|state| *state.borrow_mut() = IndexState::Loading);
+
+    Ok(())
+}
+
+/// Render results for the current query into `#search-results`
+///
+/// # Errors
+/// Returns an error if the DOM or its elements cannot be accessed.
+fn update_search_results() -> Result<(), JsValue> {
+    let document = window()
+        .ok_or("no window")?
+        .document()
+        .ok_or("no document")?;
+    let query = document
+        .get_element_by_id("search-input")
+        .ok_or("no search-input")?
+        .dyn_into::<HtmlInputElement>()?
+        .value();
+    let results = document
+        .get_element_by_id("search-results")
+        .ok_or("no search-results")?;
+
+    let tokens = tokenize(&query).collect::<Vec<_>>();
+    if tokens.is_empty() {
+        results.set_inner_html("");
+        results.set_class_name("search-results hidden");
+        return Ok(());
+    }
+
+    let html = SEARCH_INDEX.with(|state| match &*state.borrow() {
+        IndexState::Unloaded | IndexState::Loading => {
+            "<p class=\"search-status\">Loading...</p>".to_string()
+        }
+        IndexState::Failed => "<p class=\"search-status\">Search is unavailable</p>".to_string(),
+        IndexState::Loaded(index) => results_html(index, &tokens),
+    });
+    results.set_inner_html(&html);
+    results.set_class_name("search-results");
+
+    Ok(())
+}
+
+/// Result list for `tokens`, best match first
+fn results_html(index: &SearchIndex, tokens: &[String]) -> String {
+    let ranked = rank(index, tokens);
+    if ranked.is_empty() {
+        return "<p class=\"search-status\">No matches</p>".to_string();
+    }
+
+    let mut html = String::from("<ol>");
+    for doc in ranked.into_iter().take(MAX_RESULTS).map(|(doc, _)| doc) {
+        html.push_str("<li><a href=\"");
+        html.push_str(&html_escape(&doc.url));
+        html.push_str("\">");
+        html.push_str(&html_escape(&doc.title));
+        html.push_str("</a>");
+        if let Some(date) = &doc.date {
+            html.push_str(" <span class=\"search-date\">");
+            html.push_str(&html_escape(date));
+            html.push_str("</span>");
+        }
+        html.push_str("<p class=\"search-snippet\">");
+        html.push_str(&snippet(&doc.text, tokens));
+        html.push_str("</p></li>");
+    }
+    html.push_str("</ol>");
+    html
+}
+
+/// Documents matching every token with their scores, highest first.
+/// A token matches any indexed term it is a prefix of, so results show up while
+/// a word is still being typed; exact matches count double.
+fn rank<'a>(index: &'a SearchIndex, tokens: &[String]) -> Vec<(&'a SearchDoc, u32)> {
+    let mut scores: Option<BTreeMap<u32, u32>> = None;
+    for token in tokens {
+        let mut token_scores: BTreeMap<u32, u32> = BTreeMap::new();
+        let terms = index
+            .terms
+            .range::<str, _>((Bound::Included(token.as_str()), Bound::Unbounded))
+            .take_while(|(term, _)| term.starts_with(token.as_str()));
+        for (term, postings) in terms {
+            let factor = if term == token { 2 } else { 1 };
+            for &(doc, weight) in postings {
+                *token_scores.entry(doc).or_default() += weight * factor;
+            }
+        }
+
+        scores = Some(match scores {
+            None => token_scores,
+            Some(scores) => scores
+                .into_iter()
+                .filter_map(|(doc, score)| token_scores.get(&doc).map(|s| (doc, score + s)))
+                .collect(),
+        });
+    }
+
+    let mut ranked = scores
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(doc, score)| {
+            let doc = index.docs.get(usize::try_from(doc).ok()?)?;
+            Some((doc, score))
+        })
+        .collect::<Vec<_>>();
+    // Stable sort: equal scores keep index (newest first) order
+    ranked.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    ranked
+}
+
+/// Escaped excerpt of `text` around the first word matching `tokens`,
+/// with matching words wrapped in `<mark>`
+fn snippet(text: &str, tokens: &[String]) -> String {
+    let words = word_spans(text);
+    let matches = |word: &str| {
+        let word = word.to_lowercase();
+        tokens.iter().any(|token| word.starts_with(token.as_str()))
+    };
+    let first = words
+        .iter()
+        .find(|(_, word)| matches(word))
+        .map_or(0, |(offset, _)| *offset);
+
+    // Widen to whole words: start at a word, end after one
+    let mut start = first.saturating_sub(SNIPPET_CONTEXT);
+    if start > 0 {
+        start = words
+            .iter()
+            .map(|(offset, _)| *offset)
+            .find(|&offset| offset >= start)
+            .unwrap_or(first);
+    }
+    let limit = first + 2 * SNIPPET_CONTEXT;
+    let end = words
+        .iter()
+        .map(|(offset, word)| offset + word.len())
+        .take_while(|&end| end <= limit)
+        .last()
+        .unwrap_or(text.len())
+        .max(first);
+
+    let mut html = String::new();
+    if start > 0 {
+        html.push('…');
+    }
+    let mut pos = start;
+    for &(offset, word) in &words {
+        if offset >= start && offset + word.len() <= end && matches(word) {
+            html.push_str(&html_escape(&text[pos..offset]));
+            html.push_str("<mark>");
+            html.push_str(&html_escape(word));
+            html.push_str("</mark>");
+            pos = offset + word.len();
+        }
+    }
+    html.push_str(&html_escape(&text[pos..end]));
+    if end < text.len() {
+        html.push('…');
+    }
+    html
+}
+
+/// Alphanumeric runs of `text` with their byte offsets
+fn word_spans(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (offset, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(offset),
+            (false, Some(word_start)) => {
+                words.push((word_start, &text[word_start..offset]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, &text[word_start..]));
+    }
+    words
+}
+
+/// Lowercase alphanumeric runs of at least two characters.
+/// Must match `tokenize` in src/search.rs, which builds the index.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().nth(1).is_some())
+        .map(str::to_lowercase)
+}
+
 /// Escape HTML special characters
 fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
@@ -645,6 +1008,122 @@ mod tests {
         assert_ne!(ThemePreference::Auto, ThemePreference::Light);
     }
 
+    /// Tests front matter stripping in decrypted entries.
+    /// Verifies the title becomes a heading and the metadata block is not rendered.
+    #[test]
+    fn test_markdown_to_html_front_matter() {
+        let html = markdown_to_html(
+            "+++\ntitle = \"secret \\\"notes\\\"\"\ndate = 2024-05-24\n+++\n\nbody\n",
+        );
+        assert!(html.starts_with("<h1>secret &quot;notes&quot;</h1>"));
+        assert!(!html.contains("date"));
+        assert!(html.contains("<p>body</p>"));
+
+        let html = markdown_to_html("---\ntitle: notes\n---\n# own heading\n");
+        assert!(!html.contains("<h1>notes</h1>"));
+        assert!(html.contains("<h1>own heading</h1>"));
+    }
+
+    /// Tests titles quoted and escaped the way the generator writes them.
+    /// Verifies they read back like the build reads them.
+    #[test]
+    fn test_split_front_matter_unescapes_like_the_build() {
+        // As `front_matter::set_field` writes `It's "quoted" \ escaped`
+        let yaml = "---\ntitle: \"It's \\\"quoted\\\" \\\\ escaped\"\n---\nbody\n";
+        assert_eq!(
+            split_front_matter(yaml),
+            (Some(r#"It's "quoted" \ escaped"#.to_string()), "body\n")
+        );
+        assert_eq!(
+            split_front_matter("---\ntitle: 'It''s'\n---\n")
+                .0
+                .as_deref(),
+            Some("It's")
+        );
+        assert_eq!(
+            split_front_matter("+++\ntitle = 'C:\\dir'\n+++\n")
+                .0
+                .as_deref(),
+            Some("C:\\dir")
+        );
+        assert_eq!(
+            split_front_matter("+++\ntitle = \"a\\tb\"\n+++\n")
+                .0
+                .as_deref(),
+            Some("a\tb")
+        );
+    }
+
+    fn search_index() -> SearchIndex {
+        let doc = |url: &str, text: &str| SearchDoc {
+            url: url.to_string(),
+            title: url.to_string(),
+            date: None,
+            text: text.to_string(),
+        };
+        SearchIndex {
+            docs: vec![
+                doc("/pub/entries/2.html", "ipv6 networking"),
+                doc("/pub/entries/1.html", "nat networking"),
+            ],
+            terms: BTreeMap::from([
+                ("ipv6".to_string(), vec![(0, 10)]),
+                ("nat".to_string(), vec![(1, 5)]),
+                ("networking".to_string(), vec![(0, 1), (1, 3)]),
+            ]),
+        }
+    }
+
+    /// Tests search query tokenization.
+    /// Verifies queries split the same way as indexed text.
+    #[test]
+    fn test_search_tokenize() {
+        let tokens = tokenize("NAT-based IPv6, a ULA").collect::<Vec<_>>();
+        assert_eq!(tokens, ["nat", "based", "ipv6", "ula"]);
+    }
+
+    /// Tests search ranking.
+    /// Verifies prefix matching, that every token must match, and ordering by score.
+    #[test]
+    fn test_search_rank() {
+        let index = search_index();
+        let urls = |query: &str| {
+            let tokens = tokenize(query).collect::<Vec<_>>();
+            rank(&index, &tokens)
+                .into_iter()
+                .map(|(doc, score)| (doc.url.as_str(), score))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            urls("networking"),
+            [("/pub/entries/1.html", 6), ("/pub/entries/2.html", 2)]
+        );
+        assert_eq!(
+            urls("net"),
+            [("/pub/entries/1.html", 3), ("/pub/entries/2.html", 1)]
+        );
+        assert_eq!(urls("ipv6 net"), [("/pub/entries/2.html", 21)]);
+        assert!(urls("ipv6 nat").is_empty());
+    }
+
+    /// Tests search result snippets.
+    /// Verifies matches are marked, text is escaped and long text is trimmed around the match.
+    #[test]
+    fn test_search_snippet() {
+        let tokens = ["nat".to_string()];
+        assert_eq!(
+            snippet("use <b>NAT</b> here", &tokens),
+            "use &lt;b&gt;<mark>NAT</mark>&lt;/b&gt; here"
+        );
+
+        let text = format!("{} nat {}", "word ".repeat(40), "tail ".repeat(40));
+        let html = snippet(&text, &tokens);
+        assert!(html.starts_with("…word"));
+        assert!(html.contains("<mark>nat</mark>"));
+        assert!(html.ends_with("tail…"));
+    }
+
     /// Tests `ThemePreference` Clone trait implementation.
     /// Verifies cloning produces equal values.
     #[test]
@@ -0,0 +1,297 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! `list`: inventory of public and shadow entries.
+//!
+//! One row per entry with its number, visibility, state (locked, draft, scheduled),
+//! date, generated page and whether that page is current. Locked entries are
+//! cross-checked against `.enkronio-locks`: an encrypted file without a record is
+//! listed under its file name, and a record for a plaintext file is left over from an
+//! earlier lock.
+
+use crate::cache::{self, BuildCache};
+use crate::config::Config;
+use crate::entries::{self, Entry};
+use crate::front_matter;
+use crate::shortcode::Registry;
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Which entries to list; every given filter must match
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Filter {
+    pub shadow: bool,
+    pub locked: bool,
+    pub drafts: bool,
+}
+
+impl Filter {
+    fn matches(self, entry: &Entry) -> bool {
+        (!self.shadow || entry.shadow)
+            && (!self.locked || entry.locked)
+            && (!self.drafts || entry.meta.draft || entry.scheduled)
+    }
+}
+
+/// State of an entry's generated page
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    /// Generated from the source as it is now
+    Current,
+    /// Generated from an earlier version of the source (or by a build that didn't
+    /// record it), or it shouldn't exist anymore
+    Stale,
+    /// Not generated yet
+    Missing,
+    /// A draft or scheduled entry, left out of builds
+    NotBuilt,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::Stale => "stale",
+            Self::Missing => "missing",
+            Self::NotBuilt => "not built",
+        }
+    }
+}
+
+/// One listed entry
+#[allow(clippy::struct_excessive_bools)] // independent flags of the entry
+#[derive(Debug, Serialize)]
+pub struct Row {
+    pub number: u32,
+    pub title: String,
+    #[serde(serialize_with = "rfc3339")]
+    pub date: Option<DateTime<FixedOffset>>,
+    pub shadow: bool,
+    pub locked: bool,
+    pub draft: bool,
+    pub scheduled: bool,
+    pub source: Option<PathBuf>,
+    pub output: PathBuf,
+    pub status: Status,
+    /// Whether `.enkronio-locks` has a record for the entry
+    pub lock_record: bool,
+}
+
+impl Row {
+    fn new(config: &Config, cache: &BuildCache, entry: &Entry) -> Self {
+        let source = crate::find_entry_file(config.entries_dir(entry.shadow), entry.number).ok();
+        let output = config
+            .entries_output_dir(entry.shadow)
+            .join(format!("{}.html", entry.number));
+        let unpublished = entry.meta.draft || entry.scheduled;
+
+        Self {
+            number: entry.number,
+            title: entry.title(),
+            date: entry.meta.date,
+            shadow: entry.shadow,
+            locked: entry.locked,
+            draft: entry.meta.draft,
+            scheduled: entry.scheduled,
+            status: status(cache, source.as_deref(), &output, unpublished),
+            lock_record: crate::is_entry_locked(config, entry.number, entry.shadow),
+            source,
+            output,
+        }
+    }
+
+    /// `locked`, `draft`, `scheduled`, comma separated, or `-`
+    fn state(&self) -> String {
+        let state = [
+            (self.locked, "locked"),
+            (self.draft, "draft"),
+            (self.scheduled, "scheduled"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+        if state.is_empty() {
+            "-".to_string()
+        } else {
+            state.join(",")
+        }
+    }
+
+    /// Disagreement between the entry and its lockfile record, if any
+    fn lock_mismatch(&self) -> Option<&'static str> {
+        match (self.locked, self.lock_record) {
+            (true, false) => Some("is encrypted but has no lockfile record"),
+            (false, true) => Some("has a lockfile record but isn't encrypted"),
+            _ => None,
+        }
+    }
+}
+
+#[allow(clippy::ref_option)] // signature required by `serialize_with`
+fn rfc3339<S: serde::Serializer>(
+    date: &Option<DateTime<FixedOffset>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    date.map(|date| date.to_rfc3339()).serialize(serializer)
+}
+
+/// Whether the page at `output` was generated from `source` as it is now, going by
+/// the source digest the build cache recorded for it. The file's modification time
+/// can't tell: a build leaves a page alone when its inputs didn't change, however
+/// recently the source was touched.
+fn status(cache: &BuildCache, source: Option<&Path>, output: &Path, unpublished: bool) -> Status {
+    if !output.exists() {
+        return if unpublished {
+            Status::NotBuilt
+        } else {
+            Status::Missing
+        };
+    }
+    let current = source
+        .and_then(|source| fs::read(source).ok())
+        .map(cache::digest);
+    match cache.source(output) {
+        Some(recorded) if !unpublished && current.as_deref() == Some(recorded) => Status::Current,
+        _ => Status::Stale,
+    }
+}
+
+/// Print the entries matching `filter`, as a table or as JSON
+pub fn run(
+    config: &Config,
+    filter: Filter,
+    json: bool,
+    as_of: DateTime<FixedOffset>,
+) -> Result<(), anyhow::Error> {
+    let lockfile = crate::read_lockfile(config)?;
+    let cache = BuildCache::load(&config.dirs.cache, false);
+    let shortcodes = Registry::new(config, as_of.date_naive());
+    let mut entries = entries::collect(config, &lockfile, &shortcodes, as_of)?;
+    entries.sort_by_key(|entry| (entry.shadow, entry.number));
+
+    let rows = entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .map(|entry| Row::new(config, &cache, entry))
+        .collect::<Vec<_>>();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else {
+        print!("{}", table(&rows));
+    }
+
+    for row in &rows {
+        if let Some(mismatch) = row.lock_mismatch() {
+            eprintln!(
+                "Warning: entry {}{} {mismatch} ({})",
+                row.number,
+                if row.shadow { "s" } else { "p" },
+                config.dirs.lockfile
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Rows aligned in columns, the title last
+fn table(rows: &[Row]) -> String {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            [
+                format!("{}{}", row.number, if row.shadow { "s" } else { "p" }),
+                row.state(),
+                row.date.map_or("-".to_string(), front_matter::format_date),
+                row.status.label().to_string(),
+                row.output.display().to_string(),
+                row.title.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["ENTRY", "STATE", "DATE", "HTML", "OUTPUT", "TITLE"].map(str::to_string);
+
+    let mut widths = [0; 5];
+    for line in std::iter::once(&header).chain(&cells) {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for line in std::iter::once(&header).chain(&cells) {
+        for (width, cell) in widths.iter().zip(line) {
+            let _ = write!(table, "{cell:<width$}  ");
+        }
+        table.push_str(&line[5]);
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("1-a.md");
+        let output = dir.path().join("1.html");
+        fs::write(&source, "a").unwrap();
+        let mut cache = BuildCache::default();
+
+        assert_eq!(
+            status(&cache, Some(&source), &output, false),
+            Status::Missing
+        );
+        assert_eq!(
+            status(&cache, Some(&source), &output, true),
+            Status::NotBuilt
+        );
+
+        fs::write(&output, "<p>a</p>").unwrap();
+        assert_eq!(status(&cache, Some(&source), &output, false), Status::Stale);
+        cache.record_source(&output, cache::digest("a"));
+        assert_eq!(
+            status(&cache, Some(&source), &output, false),
+            Status::Current
+        );
+        assert_eq!(status(&cache, Some(&source), &output, true), Status::Stale);
+
+        // Touching the source leaves it current; changing it doesn't
+        fs::write(&source, "a").unwrap();
+        assert_eq!(
+            status(&cache, Some(&source), &output, false),
+            Status::Current
+        );
+        fs::write(&source, "b").unwrap();
+        assert_eq!(status(&cache, Some(&source), &output, false), Status::Stale);
+    }
+
+    #[test]
+    fn test_table_and_lock_mismatch() {
+        let row = Row {
+            number: 4,
+            title: "Secret".to_string(),
+            date: front_matter::parse_date("2024-05-24").ok(),
+            shadow: true,
+            locked: true,
+            draft: true,
+            scheduled: false,
+            source: None,
+            output: PathBuf::from("priv/entries/4.html"),
+            status: Status::NotBuilt,
+            lock_record: false,
+        };
+        assert_eq!(
+            table(std::slice::from_ref(&row)),
+            "ENTRY  STATE         DATE       HTML       OUTPUT               TITLE\n\
+             4s     locked,draft  24.V.2024  not built  priv/entries/4.html  Secret\n"
+        );
+        assert!(row.lock_mismatch().is_some());
+    }
+}
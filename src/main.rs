@@ -1,27 +1,49 @@
 #![warn(clippy::all, clippy::pedantic)]
-use chrono::{Datelike, Timelike};
-use clap::{Parser, Subcommand};
-use pulldown_cmark::{self, Options, Parser as MdParser};
+use chrono::{DateTime, FixedOffset};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
     fs,
     path::{Path, PathBuf},
 };
 use walkdir::WalkDir;
 
 mod rend;
-use rend::Layout;
+use rend::{Layout, PageContext, PageType};
+mod assets;
+mod build_info;
+mod cache;
+mod check;
+mod clean;
+use cache::{BuildCache, CacheKey};
+mod config;
+use config::Config;
 mod crypto;
+mod entries;
+mod feed;
+mod front_matter;
+mod headings;
+mod highlight;
+mod include;
+use front_matter::Page;
+mod junkyard;
+mod list;
+mod manage;
+mod parallel;
 mod pdf;
+mod redirects;
+mod scalar;
+mod search;
+mod serve;
+mod shortcode;
+mod sitemap;
+mod tags;
+mod template;
+use template::Value;
 mod work_period;
 
-const CONTENT_DIR: &str = "in";
-const DOWNLOAD_DIR: &str = "download";
-const PUBLIC_DIR: &str = "pub";
-const ENTRIES_DIR: &str = "in/entries";
-const SHADOW_ENTRIES_DIR: &str = "in/entries/shadow";
-const JUNKYARD_FILE: &str = "in/junkyard.md";
 const LOCK_KEY_ENV: &str = "ENKRONIO_LOCK_KEY";
-const LOCKFILE_PATH: &str = ".enkronio-locks";
+const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
 
 #[derive(Parser)]
 #[command(name = "enkronio")]
@@ -29,6 +51,90 @@ const LOCKFILE_PATH: &str = ".enkronio-locks";
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Site configuration file [default: enkronio.toml if present]
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    build: BuildOptions,
+    /// Build twice from scratch and fail if any output differs between the builds
+    #[arg(long)]
+    verify_reproducible: bool,
+}
+
+impl Cli {
+    /// Parse the command line, rejecting build options given before a subcommand:
+    /// they'd be ignored, as only the bare build reads the top-level ones
+    fn parse_checked() -> Self {
+        let mut command = Self::command();
+        let matches = command.get_matches_mut();
+        if let Some((name, _)) = matches.subcommand() {
+            let misplaced = command.get_arguments().find(|arg| {
+                !arg.is_global_set()
+                    && matches.value_source(arg.get_id().as_str())
+                        == Some(clap::parser::ValueSource::CommandLine)
+            });
+            if let Some(arg) = misplaced {
+                let flag = arg.get_long().unwrap_or(arg.get_id().as_str()).to_string();
+                command
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        format!(
+                            "'--{flag}' only applies to the bare build, \
+                             pass it after '{name}' if that takes it"
+                        ),
+                    )
+                    .exit();
+            }
+        }
+        Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
+    }
+}
+
+/// Options for the default (build) action and the commands that build (`check`, `serve`)
+#[derive(clap::Args)]
+struct BuildOptions {
+    /// Regenerate every output, ignoring the build cache
+    #[arg(long)]
+    force: bool,
+    /// Number of pages and PDFs rendered in parallel
+    #[arg(short, long, default_value_t = parallel::default_jobs())]
+    jobs: usize,
+    /// Also render draft and scheduled entries and pages, marked with a banner
+    #[arg(long)]
+    drafts: bool,
+    /// Publish entries dated up to this time [default: `SOURCE_DATE_EPOCH` if set, else now]
+    #[arg(long, value_name = "DATE", value_parser = front_matter::parse_date)]
+    as_of: Option<DateTime<FixedOffset>>,
+    /// List outputs of earlier builds that are no longer produced instead of removing them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl BuildOptions {
+    /// Time the publish dates of entries are compared against, see [`publish_time`]
+    fn as_of(&self) -> Result<DateTime<FixedOffset>, anyhow::Error> {
+        publish_time(self.as_of)
+    }
+}
+
+/// Time the publish dates of entries are compared against: `as_of` (`--as-of`), else
+/// `SOURCE_DATE_EPOCH` (seconds since the Unix epoch), else the current time
+fn publish_time(
+    as_of: Option<DateTime<FixedOffset>>,
+) -> Result<DateTime<FixedOffset>, anyhow::Error> {
+    if let Some(as_of) = as_of {
+        return Ok(as_of);
+    }
+    match std::env::var(SOURCE_DATE_EPOCH_ENV) {
+        Ok(epoch) => epoch
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .map(|time| time.fixed_offset())
+            .ok_or_else(|| anyhow::anyhow!("Invalid {SOURCE_DATE_EPOCH_ENV} '{epoch}'")),
+        Err(_) => Ok(chrono::Utc::now().fixed_offset()),
+    }
 }
 
 #[derive(Subcommand)]
@@ -40,6 +146,9 @@ enum Commands {
         /// Create as shadow entry (private, not listed in junkyard)
         #[arg(long)]
         shadow: bool,
+        /// Create as a draft, left out of builds until `draft = true` is removed
+        #[arg(long)]
+        draft: bool,
     },
     /// Lock (encrypt) or unlock (decrypt) a markdown file
     Lock {
@@ -55,24 +164,178 @@ enum Commands {
         /// or full path to markdown/encrypted file
         target: String,
     },
+    /// Change an entry's title and file name
+    Rename {
+        /// Entry specifier ("5p", "5s", "5") or path
+        target: String,
+        /// New title
+        title: String,
+    },
+    /// Delete an entry
+    Delete {
+        /// Entry specifier ("5p", "5s", "5") or path
+        target: String,
+        /// Redirect the entry's URL to the junkyard
+        #[arg(long)]
+        redirect: bool,
+    },
+    /// Renumber entries 1, 2, 3, ... to close the gaps left by deleted ones
+    Renumber {
+        /// Renumber shadow entries instead of public ones
+        #[arg(long)]
+        shadow: bool,
+        /// Redirect the old URLs to the new ones
+        #[arg(long)]
+        redirect: bool,
+    },
+    /// Move an entry to another number, or between public and shadow
+    Mv {
+        /// Entry specifier ("5p", "5s", "5") or path
+        target: String,
+        /// Destination: "7p", "7s", "7" (same visibility), or "p"/"s" for the next free number
+        destination: String,
+        /// Redirect the old URL to the new one (to the junkyard for moves into shadow)
+        #[arg(long)]
+        redirect: bool,
+    },
+    /// Make a shadow entry public under the next free public number
+    Publish {
+        /// Shadow entry specifier ("5s", or "5") or path
+        target: String,
+        /// Redirect the old shadow URL to the new public one
+        #[arg(long)]
+        redirect: bool,
+    },
+    /// Make a public entry shadow under the next free shadow number
+    Unpublish {
+        /// Public entry specifier ("5p", or "5") or path
+        target: String,
+        /// Redirect the old public URL to the junkyard
+        #[arg(long)]
+        redirect: bool,
+    },
+    /// List entries with their state and whether their pages are up to date
+    List {
+        /// Only shadow entries
+        #[arg(long)]
+        shadow: bool,
+        /// Only locked entries
+        #[arg(long)]
+        locked: bool,
+        /// Only drafts and scheduled entries
+        #[arg(long)]
+        drafts: bool,
+        /// Count entries dated after this time as scheduled [default: as for the build]
+        #[arg(long, value_name = "DATE", value_parser = front_matter::parse_date)]
+        as_of: Option<DateTime<FixedOffset>>,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Build the site and check that every internal link and anchor resolves
+    Check {
+        #[command(flatten)]
+        build: BuildOptions,
+    },
+    /// Replace the hand-maintained junkyard list with the generated one
+    Reindex,
+    /// Remove every generated output recorded in the build cache, and the cache
+    Clean {
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Build the site, serve it on localhost and rebuild and reload on changes
+    Serve {
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        #[command(flatten)]
+        build: BuildOptions,
+    },
 }
 
 fn main() -> Result<(), anyhow::Error> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_checked();
+    let config = Config::load(cli.config.as_deref())?;
 
     match cli.command {
-        Some(Commands::Add { title, shadow }) => {
-            add_entry(&title, shadow)?;
+        Some(Commands::Add {
+            title,
+            shadow,
+            draft,
+        }) => {
+            add_entry(&config, &title, shadow, draft)?;
         }
         Some(Commands::Lock { path, unlock }) => {
-            lock_file(&path, unlock)?;
+            lock_file(&config, &path, unlock)?;
         }
         Some(Commands::Edit { target }) => {
-            handle_edit(&target)?;
+            handle_edit(&config, &target)?;
+        }
+        Some(Commands::Rename { target, title }) => {
+            manage::rename(&config, &target, &title)?;
+        }
+        Some(Commands::Delete { target, redirect }) => {
+            manage::delete(&config, &target, redirect)?;
+        }
+        Some(Commands::Renumber { shadow, redirect }) => {
+            manage::renumber(&config, shadow, redirect)?;
+        }
+        Some(Commands::Mv {
+            target,
+            destination,
+            redirect,
+        }) => {
+            manage::mv(&config, &target, &destination, redirect)?;
+        }
+        Some(Commands::Publish { target, redirect }) => {
+            manage::publish(&config, &target, redirect)?;
+        }
+        Some(Commands::Unpublish { target, redirect }) => {
+            manage::unpublish(&config, &target, redirect)?;
+        }
+        Some(Commands::List {
+            shadow,
+            locked,
+            drafts,
+            as_of,
+            json,
+        }) => {
+            let filter = list::Filter {
+                shadow,
+                locked,
+                drafts,
+            };
+            list::run(&config, filter, json, publish_time(as_of)?)?;
+        }
+        Some(Commands::Check { build }) => {
+            Site::check(&config, &build)?;
+        }
+        Some(Commands::Reindex) => {
+            junkyard::reindex(&config)?;
+        }
+        Some(Commands::Clean { dry_run }) => {
+            clean::run(&config, dry_run)?;
+        }
+        Some(Commands::Serve { port, build }) => {
+            let watch = [
+                Path::new(&config.dirs.content),
+                Path::new(&config.dirs.templates),
+                Path::new(&config.dirs.snippets),
+                Path::new("css"),
+                Path::new("web"),
+            ];
+            serve::run(Path::new("."), port, &watch, || {
+                Site::build(&config, &build)
+            })?;
+        }
+        None if cli.verify_reproducible => {
+            Site::verify_reproducible(&config, &cli.build)?;
         }
         None => {
             // Default behavior: build the site
-            Site::build()?;
+            Site::build(&config, &cli.build)?;
         }
     }
 
@@ -115,7 +378,7 @@ fn get_passphrase(prompt_message: &str) -> Result<String, anyhow::Error> {
 ///
 /// When encrypting: reads .md file, encrypts it, saves as .enc, removes .md
 /// When decrypting: reads .enc file, decrypts it, saves as .md, removes .enc
-fn lock_file(path: &str, unlock: bool) -> Result<(), anyhow::Error> {
+fn lock_file(config: &Config, path: &str, unlock: bool) -> Result<(), anyhow::Error> {
     let file_path = PathBuf::from(path);
 
     if !file_path.exists() {
@@ -177,14 +440,19 @@ fn lock_file(path: &str, unlock: bool) -> Result<(), anyhow::Error> {
         println!("File encrypted successfully!");
 
         // Track in lockfile if it's an entry
-        if path.contains("/entries/") {
-            let is_shadow = path.contains("/entries/shadow/");
+        let parent = file_path.parent().unwrap_or_else(|| Path::new(""));
+        let is_shadow = config.is_entries_dir(parent, true);
+        if is_shadow || config.is_entries_dir(parent, false) {
             // Try to extract entry number
             if let Some(filename) = file_path.file_name() {
                 if let Some(filename_str) = filename.to_str() {
                     if let Some(dash_pos) = filename_str.find('-') {
                         if let Ok(entry_num) = filename_str[..dash_pos].parse::<u32>() {
-                            track_locked_entry(entry_num, is_shadow)?;
+                            // Unparsable front matter only costs the listing metadata
+                            let meta = Page::parse(&plaintext)
+                                .map(|page| page.meta)
+                                .unwrap_or_default();
+                            track_locked_entry(config, entry_num, is_shadow, &meta)?;
                             eprintln!("Tracked in lockfile: entry {entry_num}");
                         }
                     }
@@ -197,13 +465,9 @@ fn lock_file(path: &str, unlock: bool) -> Result<(), anyhow::Error> {
 }
 
 /// Add a new blog entry
-fn add_entry(title: &str, shadow: bool) -> Result<(), anyhow::Error> {
+fn add_entry(config: &Config, title: &str, shadow: bool, draft: bool) -> Result<(), anyhow::Error> {
     // Determine directory based on shadow flag
-    let entries_dir = if shadow {
-        SHADOW_ENTRIES_DIR
-    } else {
-        ENTRIES_DIR
-    };
+    let entries_dir = config.entries_dir(shadow);
 
     // Find the next entry number in the appropriate directory
     let next_number = find_next_entry_number(entries_dir)?;
@@ -213,19 +477,18 @@ fn add_entry(title: &str, shadow: bool) -> Result<(), anyhow::Error> {
     let entry_path = PathBuf::from(entries_dir).join(&filename);
 
     // Create the entry file with template content
-    create_entry_file(&entry_path, title)?;
+    create_entry_file(&entry_path, title, shadow, draft)?;
 
     println!("Created new entry: {}", entry_path.display());
 
-    // Update junkyard for non-shadow entries
-    if shadow {
+    if draft {
+        println!("Draft entry: preview with --drafts, remove `draft = true` to publish");
+    } else if shadow {
         println!("Shadow entry created (private, not listed in junkyard)");
-        println!("To encrypt: cargo run -- lock {}", entry_path.display());
     } else {
-        update_junkyard(next_number, title)?;
-        println!("Updated {JUNKYARD_FILE}");
-        println!("To encrypt: cargo run -- lock {}", entry_path.display());
+        println!("Listed in {} on the next build", config.dirs.junkyard);
     }
+    println!("To encrypt: cargo run -- lock {}", entry_path.display());
 
     Ok(())
 }
@@ -242,48 +505,87 @@ struct LockedEntry {
     number: u32,
     shadow: bool,
     created: String,
+    /// Front matter captured at lock time, so listings can show the entry
+    /// without decrypting it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    draft: bool,
+}
+
+impl LockedEntry {
+    /// Drop the captured front matter. Shadow entries keep none: the lockfile is
+    /// committed in plain text, so it would give away what they're about.
+    fn clear_metadata(&mut self) {
+        self.title = None;
+        self.date = None;
+        self.tags.clear();
+        self.draft = false;
+    }
 }
 
 /// Read the lockfile (.enkronio-locks) or return empty default
-fn read_lockfile() -> Result<Lockfile, anyhow::Error> {
-    if !Path::new(LOCKFILE_PATH).exists() {
+fn read_lockfile(config: &Config) -> Result<Lockfile, anyhow::Error> {
+    if !Path::new(&config.dirs.lockfile).exists() {
         return Ok(Lockfile {
             version: "1.0".to_string(),
             locked_entries: vec![],
         });
     }
 
-    let content = fs::read_to_string(LOCKFILE_PATH)?;
+    let content = fs::read_to_string(&config.dirs.lockfile)?;
     let lockfile: Lockfile = serde_json::from_str(&content)?;
     Ok(lockfile)
 }
 
 /// Write the lockfile (.enkronio-locks)
-fn write_lockfile(lockfile: &Lockfile) -> Result<(), anyhow::Error> {
-    let content = serde_json::to_string_pretty(lockfile)?;
-    fs::write(LOCKFILE_PATH, content)?;
+fn write_lockfile(config: &Config, lockfile: &Lockfile) -> Result<(), anyhow::Error> {
+    fs::write(&config.dirs.lockfile, lockfile_json(lockfile)?)?;
     Ok(())
 }
 
-/// Track a locked entry in the lockfile
-fn track_locked_entry(entry_number: u32, shadow: bool) -> Result<(), anyhow::Error> {
-    let mut lockfile = read_lockfile()?;
+/// Content of the lockfile as `write_lockfile` writes it
+fn lockfile_json(lockfile: &Lockfile) -> Result<String, anyhow::Error> {
+    Ok(serde_json::to_string_pretty(lockfile)?)
+}
 
-    // Add new locked entry
-    lockfile.locked_entries.push(LockedEntry {
+/// Track a locked entry in the lockfile, replacing any earlier record for it
+fn track_locked_entry(
+    config: &Config,
+    entry_number: u32,
+    shadow: bool,
+    meta: &front_matter::FrontMatter,
+) -> Result<(), anyhow::Error> {
+    let mut lockfile = read_lockfile(config)?;
+
+    lockfile
+        .locked_entries
+        .retain(|e| !(e.number == entry_number && e.shadow == shadow));
+    let mut record = LockedEntry {
         number: entry_number,
         shadow,
         created: chrono::Utc::now().to_rfc3339(),
-    });
+        title: meta.title.clone(),
+        date: meta.date.map(|date| date.to_rfc3339()),
+        tags: meta.tags.clone(),
+        draft: meta.draft,
+    };
+    if shadow {
+        record.clear_metadata();
+    }
+    lockfile.locked_entries.push(record);
 
-    write_lockfile(&lockfile)?;
+    write_lockfile(config, &lockfile)?;
     Ok(())
 }
 
 /// Check if an entry is locked
-#[allow(dead_code)] // Reserved for future navigation features
-fn is_entry_locked(entry_number: u32, shadow: bool) -> bool {
-    let lockfile = read_lockfile().ok();
+fn is_entry_locked(config: &Config, entry_number: u32, shadow: bool) -> bool {
+    let lockfile = read_lockfile(config).ok();
     if let Some(lockfile) = lockfile {
         lockfile
             .locked_entries
@@ -337,77 +639,26 @@ fn generate_entry_filename(number: u32, title: &str) -> String {
     format!("{number}-{slug}.md")
 }
 
-/// Create a new entry file with a basic template including timestamp
-fn create_entry_file(path: &Path, title: &str) -> Result<(), anyhow::Error> {
-    // Generate timestamp in format: DD.ROMAN_MONTH.YYYY HH.MM UTC+OFFSET
-    let now = chrono::Local::now();
-    let day = now.day();
-    let month_roman = month_to_roman(now.month());
-    let year = now.year();
-    let hour = now.hour();
-    let minute = now.minute();
-
-    // Get timezone offset in hours
-    let offset = now.offset().local_minus_utc() / 3600;
-    let offset_sign = if offset >= 0 { "+" } else { "" };
-
-    let timestamp =
-        format!("{day}.{month_roman}.{year} {hour:02}.{minute:02} UTC{offset_sign}{offset}");
+/// Create a new entry file with TOML front matter holding its title and creation date.
+/// The heading and timestamp are rendered from the front matter at build time.
+fn create_entry_file(
+    path: &Path,
+    title: &str,
+    shadow: bool,
+    draft: bool,
+) -> Result<(), anyhow::Error> {
+    let now = chrono::Local::now().fixed_offset();
 
-    // Wrap timestamp in HTML span with CSS class for styling
-    let content = format!("# {title}\n\n<span class=\"entry-timestamp\">{timestamp}</span>\n\n");
+    let title = toml::Value::String(title.to_string());
+    let date = now.format("%Y-%m-%dT%H:%M:%S%:z");
+    let shadow = if shadow { "shadow = true\n" } else { "" };
+    let draft = if draft { "draft = true\n" } else { "" };
+    let content = format!("+++\ntitle = {title}\ndate = {date}\n{shadow}{draft}+++\n\n");
 
     fs::write(path, content)?;
     Ok(())
 }
 
-/// Update junkyard.md with a new entry link
-fn update_junkyard(entry_number: u32, title: &str) -> Result<(), anyhow::Error> {
-    let junkyard_content = fs::read_to_string(JUNKYARD_FILE)?;
-
-    // Generate date in Roman numeral format (like "24.V.2024")
-    let now = chrono::Local::now();
-    let day = now.day();
-    let month_roman = month_to_roman(now.month());
-    let year = now.year();
-    let date_str = format!("{day}.{month_roman}.{year}");
-
-    // Generate the new entry line
-    let new_entry = format!("- {date_str}: [{title}](/pub/entries/{entry_number}.html)\n");
-
-    // Find the "## recent posts" section and insert after it
-    let lines: Vec<&str> = junkyard_content.lines().collect();
-    let mut new_content = String::new();
-    let mut inserted = false;
-
-    for (i, line) in lines.iter().enumerate() {
-        new_content.push_str(line);
-        new_content.push('\n');
-
-        // Insert after "## recent posts" header
-        if !inserted && line.trim() == "## recent posts" {
-            // Skip empty line if present
-            if i + 1 < lines.len() && lines[i + 1].trim().is_empty() {
-                new_content.push('\n');
-                new_content.push_str(&new_entry);
-                inserted = true;
-            } else {
-                new_content.push_str(&new_entry);
-                inserted = true;
-            }
-        }
-    }
-
-    // If we didn't find the section, append to the end
-    if !inserted {
-        new_content.push_str("\n## recent posts\n\n");
-        new_content.push_str(&new_entry);
-    }
-
-    fs::write(JUNKYARD_FILE, new_content)?;
-    Ok(())
-}
-
 /// Convert month number to Roman numeral
 fn month_to_roman(month: u32) -> &'static str {
     match month {
@@ -458,7 +709,7 @@ impl Drop for TempFileGuard {
 }
 
 /// Main entry point for edit command
-fn handle_edit(target: &str) -> Result<(), anyhow::Error> {
+fn handle_edit(config: &Config, target: &str) -> Result<(), anyhow::Error> {
     // Parse target (path or entry specifier)
     let target_spec = parse_target(target)?;
 
@@ -470,7 +721,7 @@ fn handle_edit(target: &str) -> Result<(), anyhow::Error> {
             }
             path
         }
-        TargetSpec::Entry { num, visibility } => resolve_entry(num, visibility)?,
+        TargetSpec::Entry { num, visibility } => resolve_entry(config, num, visibility)?,
     };
 
     // Determine if file is encrypted
@@ -519,11 +770,12 @@ fn parse_target(target: &str) -> Result<TargetSpec, anyhow::Error> {
 }
 
 /// Resolve entry number to file path
-fn resolve_entry(num: u32, visibility: Visibility) -> Result<PathBuf, anyhow::Error> {
-    let entries_dir = match visibility {
-        Visibility::Public => ENTRIES_DIR,
-        Visibility::Shadow => SHADOW_ENTRIES_DIR,
-    };
+fn resolve_entry(
+    config: &Config,
+    num: u32,
+    visibility: Visibility,
+) -> Result<PathBuf, anyhow::Error> {
+    let entries_dir = config.entries_dir(visibility == Visibility::Shadow);
 
     let visibility_str = match visibility {
         Visibility::Public => "public",
@@ -688,246 +940,371 @@ fn open_in_editor(file_path: &Path) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-/// Generate navigation HTML for blog entry pagination
-/// Returns HTML with links to previous/next entries if they exist
-/// For shadow entries, uses /priv/entries/ URL prefix and checks shadow directory
-fn generate_entry_navigation(entry_number: u32, is_shadow: bool) -> String {
-    let entries_dir = if is_shadow {
-        SHADOW_ENTRIES_DIR
-    } else {
-        ENTRIES_DIR
-    };
-    let url_prefix = if is_shadow {
-        "/priv/entries/"
-    } else {
-        "/pub/entries/"
-    };
-
-    let prev_exists = PathBuf::from(entries_dir)
-        .join(format!("{}-", entry_number - 1))
-        .parent()
-        .and_then(|parent| {
-            fs::read_dir(parent).ok().and_then(|entries| {
-                entries
-                    .filter_map(Result::ok)
-                    .find(|e| {
-                        e.file_name()
-                            .to_string_lossy()
-                            .starts_with(&format!("{}-", entry_number - 1))
-                    })
-                    .map(|_| true)
-            })
-        })
-        .unwrap_or(false);
-
-    let next_exists = PathBuf::from(entries_dir)
-        .join(format!("{}-", entry_number + 1))
-        .parent()
-        .and_then(|parent| {
-            fs::read_dir(parent).ok().and_then(|entries| {
-                entries
-                    .filter_map(Result::ok)
-                    .find(|e| {
-                        e.file_name()
-                            .to_string_lossy()
-                            .starts_with(&format!("{}-", entry_number + 1))
-                    })
-                    .map(|_| true)
-            })
+/// Links to the previous/next entries for blog entry pagination (the `nav` template variable).
+/// For shadow entries, uses /priv/entries/ URL prefix and checks shadow directory.
+/// Entries left out of the build (`hidden`, relative to `dirs.content`) are skipped over,
+/// so a page never links to one that isn't published.
+fn generate_entry_navigation(
+    config: &Config,
+    entry_number: u32,
+    is_shadow: bool,
+    hidden: &HashSet<PathBuf>,
+) -> Value {
+    let entries_dir = config.entries_dir(is_shadow);
+    let url_prefix = config.entries_url(is_shadow);
+
+    let numbers = fs::read_dir(entries_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|path| {
+            path.strip_prefix(&config.dirs.content)
+                .map_or(true, |mdfile| !hidden.contains(mdfile))
         })
-        .unwrap_or(false);
-
-    let prev_link = if prev_exists {
-        format!(
-            "  <a href=\"{}{}.html\" class=\"entry-nav-prev\">← Previous</a>\n",
-            url_prefix,
-            entry_number - 1
-        )
-    } else {
-        String::new()
-    };
-
-    let next_link = if next_exists {
-        format!(
-            "  <a href=\"{}{}.html\" class=\"entry-nav-next\">Next →</a>\n",
-            url_prefix,
-            entry_number + 1
-        )
-    } else {
-        String::new()
-    };
-
-    // Only render nav if at least one link exists
-    if prev_exists || next_exists {
-        format!("<nav class=\"entry-nav\">\n{prev_link}{next_link}</nav>\n\n")
-    } else {
-        String::new()
-    }
+        .filter_map(|path| entries::number_from_path(&path))
+        .collect::<BTreeSet<_>>();
+    let link = |number: &u32| format!("{url_prefix}{number}.html");
+    let prev = numbers.range(..entry_number).next_back().map(link);
+    let next = numbers.range(entry_number + 1..).next().map(link);
+
+    Value::from([
+        ("any", (prev.is_some() || next.is_some()).into()),
+        ("prev", prev.into()),
+        ("next", next.into()),
+    ])
 }
 
-/// Generate a locked HTML stub with embedded encrypted content for browser decryption.
+/// Template variables of a locked entry stub: the encrypted bytes, embedded as base64 for
+/// browser decryption, and a blurred gibberish preview of them.
 ///
-/// This function creates a blurred preview showing encrypted gibberish with a centered
-/// unlock overlay. The encrypted markdown is embedded as base64 in a data attribute for WASM decryption.
-///
-/// This version directly embeds already-encrypted bytes without requiring the passphrase.
-/// The browser WASM module will handle decryption when the user enters their passphrase.
-fn generate_locked_stub_from_encrypted(encrypted_b64: &str) -> String {
-    // Generate gibberish preview from encrypted data (first ~200 chars, formatted as hex)
+/// The passphrase isn't needed here: the browser WASM module handles decryption when
+/// the user enters it.
+fn locked_stub_vars(encrypted_b64: &str) -> template::Context {
+    // Generate gibberish preview from encrypted data (first 600 chars, in 64 char lines)
     let gibberish_preview = encrypted_b64
         .chars()
         .take(600)
-        .collect::<String>()
-        .chars()
         .collect::<Vec<_>>()
         .chunks(64)
         .map(|chunk| chunk.iter().collect::<String>())
         .collect::<Vec<_>>()
         .join("\n");
 
-    // Generate the locked stub HTML with blurred gibberish and centered unlock overlay
-    let stub = format!(
-        r#"
-<div id="locked-entry-container" class="locked-entry" data-encrypted="{encrypted_b64}">
-  <!-- Blurred encrypted gibberish preview -->
-  <div id="locked-preview" class="locked-preview blurred">
-    <h1>Encrypted Entry</h1>
-    <p class="encryption-notice">This content is encrypted with AES-256-GCM</p>
-    <pre class="encrypted-data">{gibberish_preview}</pre>
-  </div>
-
-  <!-- Centered unlock overlay -->
-  <div id="unlock-overlay" class="unlock-overlay">
-    <div class="unlock-form">
-      <img src="/favicon/favicon-32x32.png" alt="Lock" class="lock-icon-img"/>
-      <h2>Locked Entry</h2>
-      <p>Enter passphrase to decrypt</p>
-
-      <input type="password"
-             id="passphrase-input"
-             placeholder="Passphrase"
-             autocomplete="off"
-             aria-label="Passphrase"
-             class="passphrase-input">
-      <button id="decrypt-button" class="decrypt-button">🔓 Unlock</button>
-
-      <div id="error-message" class="error-message hidden" role="alert"></div>
-      <div id="decrypt-status" class="decrypt-status hidden" aria-live="polite">
-        Decrypting...
-      </div>
-    </div>
-  </div>
-
-  <!-- Hidden decrypted content (shown after unlock) -->
-  <div id="decrypted-content" class="decrypted-content hidden"></div>
-</div>
-"#,
-    );
-
-    stub
+    template::context([
+        ("encrypted", encrypted_b64.into()),
+        ("preview", gibberish_preview.into()),
+    ])
 }
 
 /// Generate a 404 error page with full layout
-fn generate_404_html() -> String {
-    let body = r#"
-<div class="error-page">
-    <img src="/favicon/android-chrome-192x192.png" alt="Logo" class="error-logo"/>
-    <h1>404</h1>
-    <p>Page not found</p>
-    <nav class="error-nav">
-        <a href="/">Home</a>
-        <a href="/pub/junkyard.html">Junkyard</a>
-    </nav>
-</div>
-"#;
-
-    let mut html = String::new();
-    html.push_str(&Layout::header());
-    html.push_str(&Layout::body(body));
-    html.push_str(&Layout::footer());
-    html
+fn generate_404_html(layout: &Layout) -> Result<String, anyhow::Error> {
+    layout.render(
+        "404.html",
+        &PageContext::default(),
+        template::context([("message", "Page not found".into())]),
+    )
 }
 
 /// Generate a directory index stub that redirects to a target URL
-/// If `redirect_to` is None, displays a 404-style message
-fn generate_directory_index_html(redirect_to: Option<&str>) -> String {
-    if let Some(url) = redirect_to {
-        // Generate redirect stub
-        format!(
-            r#"<!DOCTYPE html>
-<html lang="en-US">
-<head>
-    <meta charset="utf-8">
-    <meta http-equiv="refresh" content="0;url={url}">
-    <link rel="canonical" href="{url}">
-    <title>Redirecting...</title>
-</head>
-<body>
-    <p>Redirecting to <a href="{url}">{url}</a>...</p>
-</body>
-</html>"#
-        )
-    } else {
-        // Generate 404-style stub
-        let body = r#"
-<div class="error-page">
-    <img src="/favicon/android-chrome-192x192.png" alt="Logo" class="error-logo"/>
-    <h1>404</h1>
-    <p>This directory is not browsable</p>
-    <nav class="error-nav">
-        <a href="/">Home</a>
-        <a href="/pub/junkyard.html">Junkyard</a>
-    </nav>
-</div>
-"#;
-
-        let mut html = String::new();
-        html.push_str(&Layout::header());
-        html.push_str(&Layout::body(body));
-        html.push_str(&Layout::footer());
-        html
-    }
+/// If `redirect_to` is None, displays a 404-style message kept out of search indexes
+fn generate_directory_index_html(
+    layout: &Layout,
+    redirect_to: Option<&str>,
+) -> Result<String, anyhow::Error> {
+    let context = PageContext {
+        title: redirect_to.map(|_| "Redirecting...".to_string()),
+        noindex: redirect_to.is_none(),
+        redirect: redirect_to.map(str::to_string),
+        ..PageContext::default()
+    };
+    layout.render(
+        "index.html",
+        &context,
+        template::context([
+            ("redirect", redirect_to.into()),
+            ("message", "This directory is not browsable".into()),
+        ]),
+    )
 }
 
 /// Generate 404 page and directory index stubs to prevent directory listings
-fn generate_error_pages() -> Result<(), anyhow::Error> {
-    // Generate 404 page at root
-    let html_404 = generate_404_html();
-    fs::write("404.html", html_404)?;
-    eprintln!("Generated: 404.html");
-
-    // Generate directory index stubs to prevent directory listings
-    let pub_index = generate_directory_index_html(Some("/pub/junkyard.html"));
-    fs::write("pub/index.html", pub_index)?;
-    eprintln!("Generated: pub/index.html (redirects to junkyard)");
+fn generate_error_pages(
+    layout: &Layout,
+    cache: &mut BuildCache,
+    generator: &str,
+) -> Result<(), anyhow::Error> {
+    let config = layout.config();
+    // Error pages only depend on the layout, so they share a single cache key
+    let key = CacheKey::new()
+        .with(generator)
+        .with(layout.fingerprint())
+        .finish();
+
+    let junkyard_url = config.junkyard_url();
+    let pages = [
+        (PathBuf::from("404.html"), None, ""),
+        (
+            PathBuf::from(&config.dirs.public).join("index.html"),
+            Some(Some(junkyard_url.as_str())),
+            " (redirects to junkyard)",
+        ),
+        (
+            config.entries_output_dir(false).join("index.html"),
+            Some(Some(junkyard_url.as_str())),
+            " (redirects to junkyard)",
+        ),
+        (
+            config.entries_output_dir(true).join("index.html"),
+            Some(None),
+            " (not browsable)",
+        ),
+        (
+            PathBuf::from(&config.dirs.download).join("index.html"),
+            Some(Some("/")),
+            " (redirects to home)",
+        ),
+    ];
+
+    for (path, directory_index, note) in pages {
+        if cache.is_fresh(&path, &key) {
+            continue;
+        }
+        let html = match directory_index {
+            Some(redirect_to) => generate_directory_index_html(layout, redirect_to)?,
+            None => generate_404_html(layout)?,
+        };
+        fs::write(&path, html)?;
+        cache.record(&path, key.clone());
+        eprintln!("Generated: {}{note}", path.display());
+    }
 
-    let pub_entries_index = generate_directory_index_html(Some("/pub/junkyard.html"));
-    fs::write("pub/entries/index.html", pub_entries_index)?;
-    eprintln!("Generated: pub/entries/index.html (redirects to junkyard)");
+    Ok(())
+}
 
-    let priv_entries_index = generate_directory_index_html(None);
-    fs::write("priv/entries/index.html", priv_entries_index)?;
-    eprintln!("Generated: priv/entries/index.html (not browsable)");
+/// Redirect stubs for the old URLs in `dirs.redirects`. A redirect from a URL that
+/// this build already wrote a page to is skipped.
+fn generate_redirects(
+    layout: &Layout,
+    cache: &mut BuildCache,
+    generator: &str,
+) -> Result<(), anyhow::Error> {
+    let config = layout.config();
+    let redirects = redirects::Redirects::load(&config.dirs.redirects)?;
+
+    for redirect in &redirects.redirects {
+        let Some(path) = redirect.output() else {
+            eprintln!(
+                "Warning: {}: redirect from '{}' must be a site path starting with /",
+                config.dirs.redirects, redirect.from
+            );
+            continue;
+        };
+        if cache.is_claimed(&path) {
+            eprintln!(
+                "Warning: {}: {} is a page of the site; not redirecting it",
+                config.dirs.redirects, redirect.from
+            );
+            continue;
+        }
 
-    let download_index = generate_directory_index_html(Some("/"));
-    fs::write("download/index.html", download_index)?;
-    eprintln!("Generated: download/index.html (redirects to home)");
+        let key = CacheKey::new()
+            .with(generator)
+            .with(layout.fingerprint())
+            .with(&redirect.to)
+            .finish();
+        if cache.is_fresh(&path, &key) {
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let html = generate_directory_index_html(layout, Some(&redirect.to))?;
+        cache.write(&path, key, html)?;
+        eprintln!(
+            "Generated: {} (redirects to {})",
+            path.display(),
+            redirect.to
+        );
+    }
 
     Ok(())
 }
 
+/// A unit of independent work in `Site::build`
+enum BuildJob<'a> {
+    /// Content file (relative to `dirs.content`) rendered to HTML
+    Page(PathBuf),
+    /// Configured PDF export
+    Pdf(&'a config::PdfExport),
+}
+
+impl BuildJob<'_> {
+    fn source(&self, config: &Config) -> PathBuf {
+        match self {
+            Self::Page(mdfile) => PathBuf::from(&config.dirs.content).join(mdfile),
+            Self::Pdf(export) => PathBuf::from(&config.dirs.content).join(&export.source),
+        }
+    }
+
+    fn run(&self, env: &BuildEnv) -> Result<Option<Generated>, anyhow::Error> {
+        match self {
+            Self::Page(mdfile) => Site::render_page(env, mdfile),
+            Self::Pdf(export) => Site::export(env, export),
+        }
+    }
+}
+
+/// Read-only state shared by all build jobs
+struct BuildEnv<'a> {
+    config: &'a Config,
+    cache: &'a BuildCache,
+    /// Fingerprint of the generator binary
+    generator: String,
+    /// Page templates
+    layout: &'a Layout<'a>,
+    /// Entry index, for metadata of locked entries
+    entries: &'a [entries::Entry],
+    /// Content files left out of this build (drafts and scheduled pages without `--drafts`)
+    hidden: &'a HashSet<PathBuf>,
+    /// Publish time of this build, see `BuildOptions::as_of`
+    as_of: DateTime<FixedOffset>,
+    /// Last commit times, dating pages without front matter dates
+    commits: &'a sitemap::LastCommits,
+    /// Shortcodes expanded in every markdown source
+    shortcodes: &'a shortcode::Registry,
+}
+
+/// An output written by a `BuildJob`, to be recorded in the build cache
+struct Generated {
+    path: PathBuf,
+    key: String,
+    /// `cache::digest` of the source file, for `list`
+    source: String,
+    locked: bool,
+}
+
 struct Site;
 impl Site {
-    fn build() -> Result<(), anyhow::Error> {
-        // Collect all files from content directory (.md and .enc only)
-        let all_files = WalkDir::new(CONTENT_DIR)
+    fn build(config: &Config, opts: &BuildOptions) -> Result<(), anyhow::Error> {
+        let all_files = Self::content_files(config);
+
+        fs::create_dir_all(config.entries_output_dir(false))?;
+        fs::create_dir_all(config.entries_output_dir(true))?;
+        fs::create_dir_all(&config.dirs.download)?;
+
+        let as_of = opts.as_of()?;
+        let shortcodes = shortcode::Registry::new(config, as_of.date_naive());
+
+        // Listings are built from the entry index rather than from junkyard.md
+        let entries = entries::collect(config, &read_lockfile(config)?, &shortcodes, as_of)?;
+
+        let unpublished = Self::unpublished(config, &all_files, &entries, as_of);
+        let hidden = if opts.drafts {
+            HashSet::new()
+        } else {
+            for entry in entries.iter().filter(|entry| entry.scheduled) {
+                if let Some(date) = entry.meta.date {
+                    eprintln!("Scheduled: {} (publishes {date})", entry.url(config));
+                }
+            }
+            unpublished.clone()
+        };
+
+        // Sort so job order (and therefore log and error order) doesn't depend on
+        // the directory iteration order of the filesystem
+        let mut jobs = all_files
+            .into_iter()
+            .filter(|mdfile| !hidden.contains(mdfile))
+            .map(BuildJob::Page)
+            .collect::<Vec<_>>();
+        jobs.sort_by_key(|job| job.source(config));
+        jobs.extend(config.pdf.iter().map(BuildJob::Pdf));
+
+        let mut cache = BuildCache::load(&config.dirs.cache, opts.force);
+        let generator = cache::generator_fingerprint();
+
+        // Assets are versioned first: every page links to them
+        let assets = assets::Assets::build(config, Path::new("."), &mut cache, &generator)?;
+        let layout = Layout::load(config, assets)?;
+        let commits = sitemap::LastCommits::load(as_of);
+
+        let env = BuildEnv {
+            config,
+            cache: &cache,
+            layout: &layout,
+            generator,
+            entries: &entries,
+            hidden: &hidden,
+            as_of,
+            commits: &commits,
+            shortcodes: &shortcodes,
+        };
+        let results = parallel::map(&jobs, opts.jobs, |job| job.run(&env));
+        let generator = env.generator;
+
+        // Record every success before reporting failures, so a single broken entry
+        // doesn't throw away the work done for all the others
+        let mut unchanged = 0;
+        let mut failures = Vec::new();
+        for (job, result) in jobs.iter().zip(results) {
+            match result {
+                Ok(Some(generated)) => {
+                    if generated.locked {
+                        eprintln!("Generated locked HTML: {}", generated.path.display());
+                    }
+                    cache.record(&generated.path, generated.key);
+                    cache.record_source(&generated.path, generated.source);
+                }
+                Ok(None) => unchanged += 1,
+                Err(e) => failures.push(format!("  {}: {e:#}", job.source(config).display())),
+            }
+        }
+
+        // Generate 404 page and directory index stubs
+        generate_error_pages(&layout, &mut cache, &generator)?;
+
+        tags::generate(&layout, &entries, &mut cache, &generator)?;
+        feed::generate(config, &entries, &mut cache, &generator)?;
+        search::generate(config, &entries, &mut cache, &generator)?;
+        let urls = Self::sitemap_urls(config, &jobs, &entries, &unpublished, &commits);
+        sitemap::generate(config, &urls, &mut cache, &generator)?;
+        // Last, so a redirect never replaces a page of the site
+        generate_redirects(&layout, &mut cache, &generator)?;
+
+        // A failed job may not have claimed its output yet, so prune complete builds only
+        if failures.is_empty() {
+            Self::prune(config, &mut cache, &hidden, opts.dry_run)?;
+        }
+        cache.save(&config.dirs.cache)?;
+        if unchanged > 0 {
+            eprintln!("Up to date: {unchanged} output(s) unchanged (use --force to rebuild)");
+        }
+
+        if !failures.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} of {} build job(s) failed:\n{}",
+                failures.len(),
+                jobs.len(),
+                failures.join("\n")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Every `.md` and `.enc` file under `dirs.content`, relative to it
+    fn content_files(config: &Config) -> Vec<PathBuf> {
+        let content_dir = &config.dirs.content;
+
+        WalkDir::new(content_dir)
             .min_depth(1)
             .into_iter()
             .filter(|e| e.as_ref().unwrap().clone().into_path().is_file())
             .map(|e| {
                 e.unwrap()
                     .into_path()
-                    .strip_prefix(CONTENT_DIR)
+                    .strip_prefix(content_dir)
                     .unwrap()
                     .to_owned()
             })
@@ -939,133 +1316,486 @@ impl Site {
                         ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("enc")
                     })
             })
+            .collect()
+    }
+
+    /// Build the site, then check every internal link of the generated pages
+    fn check(config: &Config, opts: &BuildOptions) -> Result<(), anyhow::Error> {
+        Self::build(config, opts)?;
+
+        let sources = Self::content_files(config)
+            .into_iter()
+            .map(|mdfile| {
+                let output = Self::output_path(config, &mdfile, entries::number_from_path(&mdfile));
+                (output, PathBuf::from(&config.dirs.content).join(mdfile))
+            })
+            .collect();
+        let dirs = [
+            config.dirs.public.as_str(),
+            config.dirs.private.as_str(),
+            config.dirs.download.as_str(),
+        ];
+        check::run(Path::new("."), &dirs, &sources)
+    }
+
+    /// Build the site twice with `--force` and compare every output of the two builds.
+    /// Both builds share one `as_of` time; anything else that varies between them
+    /// (wall clock, iteration order, environment) shows up as a difference.
+    fn verify_reproducible(config: &Config, opts: &BuildOptions) -> Result<(), anyhow::Error> {
+        let opts = BuildOptions {
+            force: true,
+            jobs: opts.jobs,
+            drafts: opts.drafts,
+            as_of: Some(opts.as_of()?),
+            dry_run: opts.dry_run,
+        };
+
+        Self::build(config, &opts)?;
+        let first = Self::snapshot(config)?;
+        // Let a second boundary pass, so timestamps taken during the build differ
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        Self::build(config, &opts)?;
+        let second = Self::snapshot(config)?;
+
+        let paths = first.keys().chain(second.keys()).collect::<BTreeSet<_>>();
+        let differences = paths
+            .into_iter()
+            .filter_map(|path| {
+                let difference = match (first.get(path), second.get(path)) {
+                    (Some(a), Some(b)) if a == b => return None,
+                    (Some(a), Some(b)) => Self::first_difference(a, b),
+                    (Some(_), None) => "only in the first build".to_string(),
+                    (None, _) => "only in the second build".to_string(),
+                };
+                Some(format!("{}: {difference}", path.display()))
+            })
             .collect::<Vec<_>>();
 
-        fs::create_dir_all(PathBuf::from(PUBLIC_DIR).join("entries"))?;
-        fs::create_dir_all("priv/entries")?;
+        if !differences.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Build is not reproducible, {} output(s) differ:\n{}",
+                differences.len(),
+                differences.join("\n")
+            ));
+        }
+        eprintln!(
+            "Reproducible: {} output(s) identical across two builds",
+            first.len()
+        );
+        Ok(())
+    }
 
-        for mdfile in &all_files {
-            let file_path = PathBuf::from(CONTENT_DIR).join(mdfile);
-            let filename = mdfile.to_str().unwrap();
+    /// Where two versions of an output first differ: the line for text, the byte
+    /// offset otherwise
+    fn first_difference(a: &[u8], b: &[u8]) -> String {
+        if let (Ok(a), Ok(b)) = (std::str::from_utf8(a), std::str::from_utf8(b)) {
+            let mut lines = a.lines().zip(b.lines()).enumerate();
+            if let Some((index, (a, b))) = lines.find(|(_, (a, b))| a != b) {
+                return format!("line {} differs:\n  - {a}\n  + {b}", index + 1);
+            }
+            return "differs in length".to_string();
+        }
+        let offset = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+        format!("differs from byte {offset}")
+    }
 
-            // Check if this is an encrypted file (.enc)
-            let is_locked = mdfile
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .is_some_and(|ext| ext.eq_ignore_ascii_case("enc"));
+    /// Contents of every output recorded in the build cache
+    fn snapshot(config: &Config) -> Result<BTreeMap<PathBuf, Vec<u8>>, anyhow::Error> {
+        let cache = BuildCache::load(&config.dirs.cache, false);
+        cache
+            .outputs()
+            .map(|path| {
+                let content = fs::read(path)
+                    .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+                Ok((path.to_path_buf(), content))
+            })
+            .collect()
+    }
 
-            // For locked entries, we keep them encrypted and generate a stub
-            // For regular entries, we process markdown normally
-            let encrypted_bytes = if is_locked {
-                Some(fs::read(&file_path)?)
-            } else {
-                None
-            };
+    /// Content files (relative to `dirs.content`) that are drafts or dated after `as_of`.
+    /// Entries take both from the entry index, so locked ones are found through their
+    /// lockfile record.
+    fn unpublished(
+        config: &Config,
+        files: &[PathBuf],
+        entries: &[entries::Entry],
+        as_of: DateTime<FixedOffset>,
+    ) -> HashSet<PathBuf> {
+        files
+            .iter()
+            .filter(|mdfile| {
+                let source = Path::new(&config.dirs.content).join(mdfile);
+                let entry = entries::number_from_path(mdfile).and_then(|number| {
+                    let parent = source.parent().unwrap_or(Path::new(""));
+                    let shadow = config.is_entries_dir(parent, true);
+                    let in_entries = shadow || config.is_entries_dir(parent, false);
+                    entries.iter().find(|entry| {
+                        in_entries && entry.number == number && entry.shadow == shadow
+                    })
+                });
+                match entry {
+                    Some(entry) => entry.meta.draft || entry.scheduled,
+                    None => fs::read_to_string(&source)
+                        .ok()
+                        .and_then(|source| Page::parse(&source).ok())
+                        .is_some_and(|page| {
+                            page.meta.draft || page.meta.date.is_some_and(|date| date > as_of)
+                        }),
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the outputs earlier builds recorded that this one didn't produce: pages
+    /// of deleted, renumbered or moved entries, unused tags, unpublished (`hidden`)
+    /// pages. With `dry_run` they are only listed, and stay recorded.
+    fn prune(
+        config: &Config,
+        cache: &mut BuildCache,
+        hidden: &HashSet<PathBuf>,
+        dry_run: bool,
+    ) -> Result<(), anyhow::Error> {
+        let unpublished = hidden
+            .iter()
+            .map(|mdfile| Self::output_path(config, mdfile, entries::number_from_path(mdfile)))
+            .collect::<HashSet<_>>();
 
-            // Read markdown content (skip for locked entries, will generate stub)
-            let md = if is_locked {
-                String::new() // Placeholder, we'll use encrypted_bytes directly
+        for output in cache.stale() {
+            let reason = if unpublished.contains(&output) {
+                "unpublished"
             } else {
-                fs::read_to_string(&file_path)?
+                "stale"
             };
-
-            let md = work_period::process(&md);
-
-            // Determine if this is a shadow entry
-            let is_shadow = filename.contains("entries/shadow/");
-
-            // Extract entry number if this is an entry file
-            let entry_num: Option<u32> =
-                mdfile
-                    .file_name()
-                    .and_then(|f| f.to_str())
-                    .and_then(|fname| {
-                        // Remove .enc extension if present for parsing
-                        let fname_clean = fname.strip_suffix(".enc").unwrap_or(fname);
-                        fname_clean
-                            .find('-')
-                            .and_then(|dash_pos| fname_clean[..dash_pos].parse::<u32>().ok())
-                    });
-
-            // Generate HTML body
-            let body = if is_locked {
-                // For locked entries: generate stub with embedded encrypted bytes (no decryption needed!)
-                let encrypted_b64 = crypto::to_base64(encrypted_bytes.as_ref().unwrap());
-                generate_locked_stub_from_encrypted(&encrypted_b64)
+            if !output.is_file() {
+                cache.forget(&output);
+            } else if dry_run {
+                eprintln!("Would remove: {} ({reason})", output.display());
             } else {
-                // For regular entries: normal markdown to HTML
-                let parser = MdParser::new_ext(&md, Options::all());
-                let mut body = String::new();
-                pulldown_cmark::html::push_html(&mut body, parser);
-
-                // Add navigation for entry files
-                if let Some(entry_num) = entry_num {
-                    let navigation = generate_entry_navigation(entry_num, is_shadow);
-                    body = navigation + &body;
-                }
+                clean::remove(&output)?;
+                cache.forget(&output);
+                eprintln!("Removed: {} ({reason})", output.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Public pages and PDFs for the sitemap, with their last modification dates.
+    /// Shadow pages and unpublished ones are left out; shadow pages are disallowed in robots.txt.
+    fn sitemap_urls(
+        config: &Config,
+        jobs: &[BuildJob],
+        entries: &[entries::Entry],
+        unpublished: &HashSet<PathBuf>,
+        commits: &sitemap::LastCommits,
+    ) -> Vec<sitemap::Url> {
+        let private = Path::new(&config.dirs.private);
+        let public_entries = config.entries_output_dir(false);
+
+        let mut urls = Vec::new();
+        for job in jobs {
+            let source = job.source(config);
+            let url = match job {
+                BuildJob::Page(mdfile) => {
+                    let number = entries::number_from_path(mdfile);
+                    let output = Self::output_path(config, mdfile, number);
+                    if output.starts_with(private) || unpublished.contains(mdfile) {
+                        continue;
+                    }
 
-                body
+                    let entry = entries
+                        .iter()
+                        .find(|entry| Some(entry.number) == number && !entry.shadow);
+                    let lastmod = match entry {
+                        Some(entry) if output.starts_with(&public_entries) => {
+                            entry.meta.updated.or(entry.meta.date)
+                        }
+                        _ => Some(sitemap::source_lastmod(&source, commits)),
+                    };
+                    sitemap::Url { output, lastmod }
+                }
+                BuildJob::Pdf(export) => sitemap::Url {
+                    output: PathBuf::from(&config.dirs.download)
+                        .join(format!("{}.pdf", export.output)),
+                    lastmod: Some(sitemap::source_lastmod(&source, commits)),
+                },
             };
+            urls.push(url);
+        }
 
-            // Wrap in layout
-            let mut html = String::new();
-            html.push_str(&Layout::header());
-            html.push_str(Layout::body(&body).as_str());
-            html.push_str(&Layout::footer());
+        let tags = tags::collect(entries);
+        let tag_lastmod = |listed: &[&entries::Entry]| {
+            listed
+                .iter()
+                .filter_map(|entry| entry.meta.updated.or(entry.meta.date))
+                .max()
+        };
+        for tag in &tags {
+            urls.push(sitemap::Url {
+                output: config.tags_output_dir().join(format!("{}.html", tag.slug)),
+                lastmod: tag_lastmod(&tag.entries),
+            });
+        }
+        let all_tagged = tags.iter().flat_map(|tag| tag.entries.iter().copied());
+        urls.push(sitemap::Url {
+            output: config.tags_output_dir().join("index.html"),
+            lastmod: tag_lastmod(&all_tagged.collect::<Vec<_>>()),
+        });
 
-            // Determine output file path
-            let mut htmlfile = if let Some("index.md" | "cv.md") = mdfile.to_str() {
-                PathBuf::from(mdfile)
-            } else {
-                let mdfile_str = mdfile.to_str().unwrap();
-                // Remove .enc extension for path calculation
-                let mdfile_clean = mdfile_str.strip_suffix(".enc").unwrap_or(mdfile_str);
-
-                if mdfile_clean.contains("entries/shadow/") {
-                    // Shadow entry: write to priv/entries/N.html
-                    if let Some(entry_num) = entry_num {
-                        PathBuf::from("priv/entries").join(entry_num.to_string())
-                    } else {
-                        PathBuf::from("priv").join(mdfile)
-                    }
-                } else if let Some(v) = mdfile_clean.split_once('-') {
-                    // Regular numbered entry: write to pub/entries/N.html
-                    PathBuf::from(PUBLIC_DIR).join(v.0)
+        urls
+    }
+
+    /// Output path of a content file (relative to `dirs.content`)
+    fn output_path(config: &Config, mdfile: &Path, entry_num: Option<u32>) -> PathBuf {
+        let source_dir = Path::new(&config.dirs.content).join(mdfile.parent().unwrap_or(mdfile));
+        let is_shadow = config.is_entries_dir(&source_dir, true);
+        let is_entry = is_shadow || config.is_entries_dir(&source_dir, false);
+
+        let mut htmlfile = match entry_num {
+            // Numbered entry: write to pub/entries/N.html or priv/entries/N.html
+            Some(entry_num) if is_entry => config
+                .entries_output_dir(is_shadow)
+                .join(entry_num.to_string()),
+            _ => {
+                if let Some("index.md" | "cv.md") = mdfile.to_str() {
+                    PathBuf::from(mdfile)
+                } else if is_shadow {
+                    PathBuf::from(&config.dirs.private).join(mdfile)
                 } else {
                     // Other files: write to pub/
-                    PathBuf::from(PUBLIC_DIR).join(mdfile)
+                    PathBuf::from(&config.dirs.public).join(mdfile)
                 }
-            };
+            }
+        };
 
-            htmlfile.set_extension("html");
-            fs::write(&htmlfile, html)?;
+        htmlfile.set_extension("html");
+        htmlfile
+    }
 
-            if is_locked {
-                eprintln!("Generated locked HTML: {}", htmlfile.display());
+    /// Render a single content file (relative to `dirs.content`) to HTML.
+    /// Returns `None` when the cached page is still current and nothing was written.
+    fn render_page(env: &BuildEnv, mdfile: &Path) -> Result<Option<Generated>, anyhow::Error> {
+        let config = env.config;
+        let file_path = PathBuf::from(&config.dirs.content).join(mdfile);
+
+        // Check if this is an encrypted file (.enc)
+        let is_locked = mdfile
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("enc"));
+
+        let bytes = fs::read(&file_path)?;
+        let source_digest = cache::digest(&bytes);
+
+        // For locked entries, we keep them encrypted and generate a stub
+        // For regular entries, we process markdown normally
+        let (encrypted_bytes, source) = if is_locked {
+            (Some(bytes), String::new())
+        } else {
+            // Shortcodes are expanded before anything else, so the cache key covers
+            // whatever they pull in.
+            let source = Self::page_source(env, &file_path, String::from_utf8(bytes)?);
+            (None, env.shortcodes.expand(&file_path, &source)?)
+        };
+
+        // Locked entries have no readable front matter
+        let page = if is_locked {
+            None
+        } else {
+            Some(Page::parse(&source)?)
+        };
+        let md = page.as_ref().map(Page::markdown).unwrap_or_default();
+
+        // Determine if this is a shadow entry
+        let is_shadow = config.is_entries_dir(file_path.parent().unwrap_or(Path::new("")), true);
+
+        // Extract entry number if this is an entry file
+        let entry_num: Option<u32> =
+            mdfile
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|fname| {
+                    // Remove .enc extension if present for parsing
+                    let fname_clean = fname.strip_suffix(".enc").unwrap_or(fname);
+                    fname_clean
+                        .find('-')
+                        .and_then(|dash_pos| fname_clean[..dash_pos].parse::<u32>().ok())
+                });
+
+        // Navigation depends on which neighbouring entries exist, so it is part
+        // of the cache key alongside the source itself
+        let navigation = match entry_num {
+            Some(entry_num) if !is_locked => {
+                generate_entry_navigation(config, entry_num, is_shadow, env.hidden)
             }
+            _ => Value::from([("any", false.into())]),
+        };
+
+        let htmlfile = Self::output_path(config, mdfile, entry_num);
+        let context = Self::page_context(
+            env,
+            &file_path,
+            &htmlfile,
+            page.as_ref(),
+            entry_num,
+            is_shadow,
+        );
+
+        let key = CacheKey::new()
+            .with(&env.generator)
+            .with(env.layout.fingerprint())
+            .with(encrypted_bytes.as_deref().unwrap_or(source.as_bytes()))
+            .with(format!("{navigation:?}"))
+            .with(format!("{context:?}"))
+            .finish();
+
+        if env.cache.is_fresh(&htmlfile, &key) {
+            return Ok(None);
+        }
+
+        let html = if is_locked {
+            // For locked entries: generate stub with embedded encrypted bytes (no decryption needed!)
+            let encrypted_b64 = crypto::to_base64(encrypted_bytes.as_ref().unwrap());
+            env.layout
+                .render("locked.html", &context, locked_stub_vars(&encrypted_b64))?
+        } else {
+            // Shadow entries never appear on tag pages, so they don't link to them
+            let tags = match &page {
+                Some(page) if entry_num.is_some() && !is_shadow => {
+                    tags::values(config, &page.meta.tags)
+                }
+                _ => Vec::new(),
+            };
+
+            env.layout.render_markdown(
+                "entry.html",
+                &context,
+                &rend::markdown_to_html(&md, page.as_ref().is_some_and(|page| page.meta.toc)),
+                template::context([("nav", navigation), ("tags", tags.into())]),
+            )?
+        };
+
+        if let Some(parent) = htmlfile.parent() {
+            fs::create_dir_all(parent)?;
         }
+        fs::write(&htmlfile, html)?;
+
+        Ok(Some(Generated {
+            path: htmlfile,
+            key,
+            source: source_digest,
+            locked: is_locked,
+        }))
+    }
 
-        fs::create_dir_all(DOWNLOAD_DIR)?;
+    /// Markdown source of a page, from the text of its file. The junkyard lists the
+    /// entries in place of its placeholder; expanding it here makes the list part of
+    /// the cache key.
+    fn page_source(env: &BuildEnv, file_path: &Path, source: String) -> String {
+        let config = env.config;
+        if !rend::same_path(file_path, Path::new(&config.dirs.junkyard)) {
+            return source;
+        }
 
-        Self::export("cv.md", "sbelokon")?;
-        Self::export("index.md", "cover")?;
+        if !junkyard::has_placeholder(&source) {
+            eprintln!(
+                "Warning: {} has no {} line; run `reindex` to generate its entry list",
+                config.dirs.junkyard,
+                junkyard::PLACEHOLDER
+            );
+        }
+        junkyard::expand(&source, config, env.entries)
+    }
 
-        // Generate 404 page and directory index stubs
-        generate_error_pages()?;
+    /// Head metadata of a content page. Locked entries get only their listed title,
+    /// URL and type: nothing derived from the encrypted content.
+    fn page_context(
+        env: &BuildEnv,
+        source: &Path,
+        output: &Path,
+        page: Option<&Page>,
+        entry_num: Option<u32>,
+        is_shadow: bool,
+    ) -> PageContext {
+        let is_entry = output.starts_with(env.config.entries_output_dir(is_shadow));
+        let entry = env
+            .entries
+            .iter()
+            .find(|entry| Some(entry.number) == entry_num && entry.shadow == is_shadow)
+            .filter(|_| is_entry);
 
-        Ok(())
+        let mut context = PageContext {
+            path: Some(rend::site_path(output)),
+            page_type: if is_entry {
+                PageType::Article
+            } else {
+                PageType::Website
+            },
+            noindex: is_shadow,
+            ..PageContext::default()
+        };
+        let date = if let Some(page) = page {
+            context.title.clone_from(&page.meta.title);
+            context.description = page.summary();
+            context.published = entry.and_then(|entry| entry.meta.date);
+            context.draft = page.meta.draft;
+            context.updated = page.meta.updated.or(page.meta.date);
+            page.meta.date
+        } else {
+            context.title = entry.map(entries::Entry::title);
+            context.draft = entry.is_some_and(|entry| entry.meta.draft);
+            context.updated = entry.and_then(|entry| entry.meta.updated.or(entry.meta.date));
+            entry.and_then(|entry| entry.meta.date)
+        };
+        if context.updated.is_none() {
+            context.updated = Some(env.commits.get(source));
+        }
+        context.scheduled = date.filter(|date| *date > env.as_of);
+        context.noindex |= context.draft || context.scheduled.is_some();
+        context
     }
 
-    fn export<P: AsRef<Path>>(f_in: P, f_out: P) -> Result<(), anyhow::Error> {
-        let md = fs::read_to_string(PathBuf::from(CONTENT_DIR).join(f_in))?;
-        let md = work_period::process(&md);
-        let mut pdf_path = PathBuf::from(DOWNLOAD_DIR).join(f_out);
+    /// Render a configured page to PDF.
+    /// Returns `None` when the cached PDF is still current and nothing was written.
+    fn export(
+        env: &BuildEnv,
+        export: &config::PdfExport,
+    ) -> Result<Option<Generated>, anyhow::Error> {
+        let config = env.config;
+        let source_path = PathBuf::from(&config.dirs.content).join(&export.source);
+        let text = fs::read_to_string(&source_path)?;
+        let source_digest = cache::digest(&text);
+        let source = env.shortcodes.expand(&source_path, &text)?;
+        let page = Page::parse(&source)?;
+        let md = page.markdown();
+        let mut pdf_path = PathBuf::from(&config.dirs.download).join(&export.output);
 
         pdf_path.set_extension("pdf");
-        let pdf_bytes = pdf::render(&md);
-        fs::write(pdf_path, pdf_bytes)?;
 
-        Ok(())
+        let key = CacheKey::new()
+            .with(&env.generator)
+            .with(&config.author)
+            .with(&source)
+            .finish();
+        if env.cache.is_fresh(&pdf_path, &key) {
+            return Ok(None);
+        }
+
+        let pdf_bytes = pdf::render(
+            &md,
+            &pdf::DocumentInfo {
+                title: page.meta.title.as_deref(),
+                author: &config.author,
+            },
+        );
+        fs::write(&pdf_path, pdf_bytes)?;
+
+        Ok(Some(Generated {
+            path: pdf_path,
+            key,
+            source: source_digest,
+            locked: false,
+        }))
     }
 }
 
@@ -1262,4 +1992,108 @@ mod tests {
         let path = PathBuf::from("in/entries/999-test.md");
         assert_eq!(extract_entry_number(&path).unwrap(), 999);
     }
+
+    /// Tests `generate_entry_navigation` around entries left out of the build.
+    /// Verifies that hidden entries and gaps in the numbering are skipped over.
+    #[test]
+    fn test_generate_entry_navigation_skips_hidden() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("entries")).unwrap();
+        for name in ["1-a.md", "2-b.md", "3-draft.md", "5-c.enc"] {
+            fs::write(root.join("entries").join(name), "").unwrap();
+        }
+        let config = Config::parse(&format!(
+            "[dirs]\ncontent = \"{0}\"\nentries = \"{0}/entries\"\n",
+            root.display()
+        ))
+        .unwrap();
+        let hidden = HashSet::from([PathBuf::from("entries/3-draft.md")]);
+
+        let nav = generate_entry_navigation(&config, 2, false, &hidden);
+        assert_eq!(
+            nav,
+            Value::from([
+                ("any", true.into()),
+                ("prev", Some("/pub/entries/1.html").into()),
+                ("next", Some("/pub/entries/5.html").into()),
+            ])
+        );
+        let nav = generate_entry_navigation(&config, 2, false, &HashSet::new());
+        assert_eq!(
+            nav,
+            Value::from([
+                ("any", true.into()),
+                ("prev", Some("/pub/entries/1.html").into()),
+                ("next", Some("/pub/entries/3.html").into()),
+            ])
+        );
+    }
+
+    /// Tests that a public source marked `shadow = true` stops the build.
+    /// Verifies that the private entry isn't published, listed or indexed.
+    #[test]
+    fn test_build_rejects_shadow_entry_in_public_dir() {
+        let root = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| root.path().join(name).display().to_string();
+        fs::create_dir_all(path("in/entries")).unwrap();
+        fs::write(
+            path("in/entries/4-secret.md"),
+            "+++\ntitle = \"secret\"\nshadow = true\n+++\nBody\n",
+        )
+        .unwrap();
+        let config = Config::parse(&format!(
+            "pdf = []\n[dirs]\ncontent = \"{}\"\nentries = \"{}\"\nshadow_entries = \"{}\"\n\
+             public = \"{}\"\nprivate = \"{}\"\ndownload = \"{}\"\ncache = \"{}\"\n",
+            path("in"),
+            path("in/entries"),
+            path("in/entries/shadow"),
+            path("pub"),
+            path("priv"),
+            path("download"),
+            path("cache"),
+        ))
+        .unwrap();
+        let opts = BuildOptions {
+            force: false,
+            jobs: 1,
+            drafts: false,
+            as_of: None,
+            dry_run: false,
+        };
+
+        let err = Site::build(&config, &opts).unwrap_err().to_string();
+        assert!(err.contains("4-secret.md"), "{err}");
+        assert!(!root.path().join("pub/entries/4.html").exists());
+    }
+
+    /// Tests the lockfile record of a locked entry.
+    /// Verifies that front matter is kept for public entries only.
+    #[test]
+    fn test_track_locked_entry_keeps_shadow_metadata_out() {
+        let root = tempfile::TempDir::new().unwrap();
+        let lockfile = root.path().join("locks");
+        let config =
+            Config::parse(&format!("[dirs]\nlockfile = \"{}\"\n", lockfile.display())).unwrap();
+        let meta = Page::parse("+++\ntitle = \"salary\"\ntags = [\"job\"]\n+++\n")
+            .unwrap()
+            .meta;
+
+        track_locked_entry(&config, 1, false, &meta).unwrap();
+        track_locked_entry(&config, 1, true, &meta).unwrap();
+
+        let records = read_lockfile(&config).unwrap().locked_entries;
+        assert_eq!(records[0].title.as_deref(), Some("salary"));
+        assert_eq!(records[0].tags, ["job"]);
+        assert!(records[1].shadow);
+        assert_eq!(records[1].title, None);
+        assert!(records[1].tags.is_empty());
+        assert_eq!(
+            fs::read_to_string(lockfile)
+                .unwrap()
+                .matches("salary")
+                .count(),
+            1
+        );
+    }
 }
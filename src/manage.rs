@@ -0,0 +1,1040 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Entry management: `rename`, `delete`, `renumber`, `mv`, `publish` and `unpublish`.
+//!
+//! An entry's URL depends only on its number and visibility (`5p`, `5s`), so `rename`
+//! changes just the file name and title. Deleting or moving an entry changes its URL,
+//! and everything that refers to it is updated together: the source file, its
+//! lockfile record, links from other plaintext sources (by site path such as
+//! `/pub/entries/5.html`, or by absolute URL), a hand-maintained junkyard list and the
+//! redirects of `dirs.redirects`. With `--redirect` the old URL also gets a redirect.
+//! All of it is planned before the first file is touched, and a step that fails
+//! undoes the ones before it.
+//!
+//! A public URL never leads to a shadow entry: links from public sources to an entry
+//! moved into shadow are reported instead of rewritten, and its redirect goes to the
+//! junkyard. Links inside locked entries can't be read and are left alone.
+
+use crate::config::Config;
+use crate::front_matter::{self, Field, Page};
+use crate::redirects::Redirects;
+use crate::{entries, junkyard};
+use regex::{Captures, Regex};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// An entry's number and visibility
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Slot {
+    number: u32,
+    shadow: bool,
+}
+
+impl Slot {
+    fn url(self, config: &Config) -> String {
+        format!("{}{}.html", config.entries_url(self.shadow), self.number)
+    }
+
+    /// Source path in this slot for the file now called `file_name` (`3-title.md`)
+    fn path(self, config: &Config, file_name: &str) -> PathBuf {
+        let rest = file_name
+            .split_once('-')
+            .map_or(file_name, |(_, rest)| rest);
+        Path::new(config.entries_dir(self.shadow)).join(format!("{}-{rest}", self.number))
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.number, if self.shadow { 's' } else { 'p' })
+    }
+}
+
+/// A deletion (`to: None`) or move of one entry
+struct Change {
+    from: Slot,
+    source: PathBuf,
+    to: Option<Slot>,
+}
+
+/// Every file an entry command changes, planned before any of them is touched.
+///
+/// Renames are made first, in order, then the files are written or removed. If any
+/// step fails, the steps already taken are undone, so a failed command leaves the
+/// site as it was.
+#[derive(Default)]
+struct Plan {
+    /// Files to rename, as `(from, to)`
+    renames: Vec<(PathBuf, PathBuf)>,
+    /// New content of files once renamed, `None` to remove them, by `file_key`
+    files: BTreeMap<PathBuf, (PathBuf, Option<String>)>,
+    /// Reported once the plan has been carried out
+    messages: Vec<String>,
+}
+
+/// A step already taken, and how to take it back
+enum Undo {
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Restore {
+        path: PathBuf,
+        content: Option<Vec<u8>>,
+    },
+}
+
+impl Plan {
+    /// Rename `from` to `to`, which must be free or vacated by an earlier rename
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+        let taken = self.renames.iter().any(|(_, other)| same_file(other, to));
+        let vacated = self.renames.iter().any(|(other, _)| same_file(other, to));
+        if taken || (to.exists() && !vacated) {
+            return Err(anyhow::anyhow!("{} already exists", to.display()));
+        }
+        self.renames.push((from.to_path_buf(), to.to_path_buf()));
+        Ok(())
+    }
+
+    /// Where the file at `path` ends up once the renames are made
+    fn destination(&self, path: &Path) -> PathBuf {
+        self.renames
+            .iter()
+            .find(|(from, _)| same_file(from, path))
+            .map_or_else(|| path.to_path_buf(), |(_, to)| to.clone())
+    }
+
+    /// Content of the file at `path` once the plan is carried out, `None` if there
+    /// won't be one
+    fn read(&self, path: &Path) -> Result<Option<String>, anyhow::Error> {
+        if let Some((_, content)) = self.files.get(&file_key(path)) {
+            return Ok(content.clone());
+        }
+        let current = self
+            .renames
+            .iter()
+            .find(|(_, to)| same_file(to, path))
+            .map_or(path, |(from, _)| from.as_path());
+        match fs::read_to_string(current) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Write `content` to `path`, the path the file has once renamed
+    fn write(&mut self, path: &Path, content: String) {
+        self.files
+            .insert(file_key(path), (path.to_path_buf(), Some(content)));
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.files
+            .insert(file_key(path), (path.to_path_buf(), None));
+    }
+
+    fn report(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    /// Take every step, or none of them
+    fn execute(self) -> Result<(), anyhow::Error> {
+        let mut taken = Vec::new();
+        if let Err(err) = self.take_steps(&mut taken) {
+            for step in taken.into_iter().rev() {
+                let (path, undone) = match &step {
+                    Undo::Rename { from, to } => (to, fs::rename(to, from)),
+                    Undo::Restore {
+                        path,
+                        content: Some(content),
+                    } => (path, fs::write(path, content)),
+                    Undo::Restore {
+                        path,
+                        content: None,
+                    } => (path, remove_if_present(path)),
+                };
+                if let Err(undo_err) = undone {
+                    eprintln!("Warning: could not restore {}: {undo_err}", path.display());
+                }
+            }
+            return Err(err);
+        }
+        for message in self.messages {
+            println!("{message}");
+        }
+        Ok(())
+    }
+
+    fn take_steps(&self, taken: &mut Vec<Undo>) -> Result<(), anyhow::Error> {
+        for (from, to) in &self.renames {
+            if to.exists() {
+                return Err(anyhow::anyhow!("{} already exists", to.display()));
+            }
+            if let Some(dir) = to.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::rename(from, to)?;
+            taken.push(Undo::Rename {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+        for (path, content) in self.files.values() {
+            let previous = match fs::read(path) {
+                Ok(previous) => Some(previous),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+            taken.push(Undo::Restore {
+                path: path.clone(),
+                content: previous,
+            });
+            match content {
+                Some(content) => fs::write(path, content)?,
+                None => remove_if_present(path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove_if_present(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Whether `a` and `b` name the same file, however they are spelled
+fn same_file(a: &Path, b: &Path) -> bool {
+    crate::rend::same_path(a, b)
+}
+
+/// `path` made absolute and normalized, so every spelling of a file plans one change
+fn file_key(path: &Path) -> PathBuf {
+    std::path::absolute(path)
+        .map_or_else(|_| path.to_path_buf(), |path| crate::rend::normalize(&path))
+}
+
+/// Entry source files of the public or shadow directory, by number
+fn entry_files(config: &Config, shadow: bool) -> Result<BTreeMap<u32, PathBuf>, anyhow::Error> {
+    let dir = Path::new(config.entries_dir(shadow));
+    let mut files = BTreeMap::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let is_entry = path.is_file()
+            && path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("enc")
+            });
+        let Some(number) = entries::number_from_path(&path).filter(|_| is_entry) else {
+            continue;
+        };
+        if let Some(other) = files.insert(number, path.clone()) {
+            return Err(anyhow::anyhow!(
+                "Entry {number} has more than one source: {} and {}",
+                other.display(),
+                path.display()
+            ));
+        }
+    }
+    Ok(files)
+}
+
+/// Slot and source file of an entry specifier (`5p`, `5s`, `5`) or path
+fn locate(config: &Config, target: &str) -> Result<(Slot, PathBuf), anyhow::Error> {
+    let path = match crate::parse_target(target)? {
+        crate::TargetSpec::Path(path) => path,
+        crate::TargetSpec::Entry { num, visibility } => {
+            crate::resolve_entry(config, num, visibility)?
+        }
+    };
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let shadow = match () {
+        () if config.is_entries_dir(parent, true) => true,
+        () if config.is_entries_dir(parent, false) => false,
+        () => {
+            return Err(anyhow::anyhow!(
+                "{} is not an entry: entries live in {} and {}",
+                path.display(),
+                config.entries_dir(false),
+                config.entries_dir(true)
+            ))
+        }
+    };
+    let number = entries::number_from_path(&path)
+        .ok_or_else(|| anyhow::anyhow!("{} has no entry number", path.display()))?;
+    if !path.is_file() {
+        return Err(anyhow::anyhow!("File not found: {}", path.display()));
+    }
+    Ok((Slot { number, shadow }, path))
+}
+
+/// `mv` destination: `7p`, `7s`, `7` (same visibility as `from`), or `p`/`s` alone
+/// for the next free number
+fn parse_destination(config: &Config, spec: &str, from: Slot) -> Result<Slot, anyhow::Error> {
+    let (number, shadow) = match spec.strip_suffix(['p', 's']) {
+        Some(number) => (number, spec.ends_with('s')),
+        None => (spec, from.shadow),
+    };
+    let number = if number.is_empty() {
+        crate::find_next_entry_number(config.entries_dir(shadow))?
+    } else {
+        number.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid destination '{spec}'. Format: [<number>][p|s]. Examples: 7p, 3s, s"
+            )
+        })?
+    };
+    if number == 0 {
+        return Err(anyhow::anyhow!("Entry numbers start at 1"));
+    }
+    Ok(Slot { number, shadow })
+}
+
+/// Change the title of an entry, and its file name with it
+pub fn rename(config: &Config, target: &str, title: &str) -> Result<(), anyhow::Error> {
+    let (slot, source) = locate(config, target)?;
+    let locked = source.extension().is_some_and(|ext| ext == "enc");
+
+    let mut file_name = crate::generate_entry_filename(slot.number, title);
+    if locked {
+        file_name = format!("{}.enc", file_name.trim_end_matches(".md"));
+    }
+    let renamed = source.with_file_name(&file_name);
+
+    let mut plan = Plan::default();
+    if renamed != source {
+        plan.rename(&source, &renamed)?;
+        plan.report(format!(
+            "Renamed {} → {}",
+            source.display(),
+            renamed.display()
+        ));
+    }
+    if locked {
+        let mut lockfile = crate::read_lockfile(config)?;
+        let record = lockfile
+            .locked_entries
+            .iter_mut()
+            .rev()
+            .find(|e| e.number == slot.number && e.shadow == slot.shadow);
+        // Shadow entries keep their title out of the lockfile, see `LockedEntry::clear_metadata`
+        match record {
+            Some(record) if !slot.shadow => record.title = Some(title.to_string()),
+            Some(_) => {}
+            None => lockfile.locked_entries.push(crate::LockedEntry {
+                number: slot.number,
+                shadow: slot.shadow,
+                created: chrono::Utc::now().to_rfc3339(),
+                title: (!slot.shadow).then(|| title.to_string()),
+                date: None,
+                tags: Vec::new(),
+                draft: false,
+            }),
+        }
+        plan.write(
+            Path::new(&config.dirs.lockfile),
+            crate::lockfile_json(&lockfile)?,
+        );
+    } else {
+        let text = fs::read_to_string(&source)?;
+        plan.write(
+            &renamed,
+            front_matter::set_field(&text, "title", &Field::Str(title))?,
+        );
+    }
+
+    if !slot.shadow {
+        update_junkyard(config, &mut plan, slot.number, Some(title))?;
+    }
+    plan.report(format!("Entry {slot} is now titled \"{title}\""));
+    plan.execute()
+}
+
+/// Delete an entry
+pub fn delete(config: &Config, target: &str, redirect: bool) -> Result<(), anyhow::Error> {
+    let (from, source) = locate(config, target)?;
+    apply(
+        config,
+        &[Change {
+            from,
+            source,
+            to: None,
+        }],
+        redirect,
+    )
+}
+
+/// Move an entry to another number, or between public and shadow
+pub fn mv(
+    config: &Config,
+    target: &str,
+    destination: &str,
+    redirect: bool,
+) -> Result<(), anyhow::Error> {
+    let (from, source) = locate(config, target)?;
+    let to = parse_destination(config, destination, from)?;
+    if to == from {
+        println!("Entry {from} is already there");
+        return Ok(());
+    }
+    apply(
+        config,
+        &[Change {
+            from,
+            source,
+            to: Some(to),
+        }],
+        redirect,
+    )
+}
+
+/// Make a shadow entry public under the next free public number
+pub fn publish(config: &Config, target: &str, redirect: bool) -> Result<(), anyhow::Error> {
+    let to = switch_visibility(config, target, false, redirect)?;
+    let source = fs::read_to_string(&config.dirs.junkyard).unwrap_or_default();
+    if junkyard::has_placeholder(&source) {
+        println!("Listed in {} on the next build", config.dirs.junkyard);
+    } else {
+        println!(
+            "Add {} to the list in {}, or run `reindex` to generate it",
+            to.url(config),
+            config.dirs.junkyard
+        );
+    }
+    Ok(())
+}
+
+/// Make a public entry shadow under the next free shadow number
+pub fn unpublish(config: &Config, target: &str, redirect: bool) -> Result<(), anyhow::Error> {
+    switch_visibility(config, target, true, redirect).map(|_| ())
+}
+
+/// Move an entry into the next free slot of the other visibility (`shadow`). A bare
+/// number names an entry of the visibility it leaves: `publish 5` is `publish 5s`.
+fn switch_visibility(
+    config: &Config,
+    target: &str,
+    shadow: bool,
+    redirect: bool,
+) -> Result<Slot, anyhow::Error> {
+    let target = if !target.is_empty() && target.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{target}{}", if shadow { 'p' } else { 's' })
+    } else {
+        target.to_string()
+    };
+    let (from, source) = locate(config, &target)?;
+    if from.shadow == shadow {
+        return Err(anyhow::anyhow!(
+            "Entry {from} is already {}",
+            if shadow { "shadow" } else { "public" }
+        ));
+    }
+    let to = Slot {
+        number: crate::find_next_entry_number(config.entries_dir(shadow))?,
+        shadow,
+    };
+    apply(
+        config,
+        &[Change {
+            from,
+            source,
+            to: Some(to),
+        }],
+        redirect,
+    )?;
+    Ok(to)
+}
+
+/// Number the public (or shadow) entries 1, 2, 3, ... in their current order,
+/// closing the gaps left by deleted entries
+pub fn renumber(config: &Config, shadow: bool, redirect: bool) -> Result<(), anyhow::Error> {
+    let changes = entry_files(config, shadow)?
+        .into_iter()
+        .zip(1..)
+        .filter(|((number, _), new)| number != new)
+        .map(|((number, source), new)| Change {
+            from: Slot { number, shadow },
+            source,
+            to: Some(Slot {
+                number: new,
+                shadow,
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    if changes.is_empty() {
+        println!("Nothing to renumber: entries are numbered without gaps");
+        return Ok(());
+    }
+    apply(config, &changes, redirect)
+}
+
+/// Carry out `changes` and update everything that refers to the entries
+fn apply(config: &Config, changes: &[Change], redirect: bool) -> Result<(), anyhow::Error> {
+    check_destinations(config, changes)?;
+    check_locked(changes)?;
+
+    // Sources, in order of their new numbers, so a compacted entry never lands on a
+    // file that hasn't moved yet
+    let mut plan = Plan::default();
+    let mut order = changes.iter().collect::<Vec<_>>();
+    order.sort_by_key(|change| change.to);
+    for change in order {
+        match change.to {
+            None => {
+                plan.remove(&change.source);
+                plan.report(format!(
+                    "Deleted {} (entry {})",
+                    change.source.display(),
+                    change.from
+                ));
+            }
+            Some(to) => move_source(config, &mut plan, change, to)?,
+        }
+    }
+
+    update_lockfile(config, &mut plan, changes)?;
+
+    // A hand-maintained junkyard list drops entries that are no longer public
+    for change in changes {
+        let unlisted = !change.from.shadow && change.to.is_none_or(|to| to.shadow);
+        if unlisted {
+            update_junkyard(config, &mut plan, change.from.number, None)?;
+        }
+    }
+
+    let moved = changes
+        .iter()
+        .map(|change| (change.from, change.to))
+        .collect::<BTreeMap<_, _>>();
+    update_links(config, &mut plan, &moved)?;
+    update_redirects(config, &mut plan, changes, redirect)?;
+    plan.execute()
+}
+
+/// Every destination must be free, or be vacated by another change
+fn check_destinations(config: &Config, changes: &[Change]) -> Result<(), anyhow::Error> {
+    let vacated = changes
+        .iter()
+        .map(|change| change.from)
+        .collect::<BTreeSet<_>>();
+    let mut taken = BTreeSet::new();
+    for shadow in [false, true] {
+        for number in entry_files(config, shadow)?.into_keys() {
+            let slot = Slot { number, shadow };
+            if !vacated.contains(&slot) {
+                taken.insert(slot);
+            }
+        }
+    }
+
+    for to in changes.iter().filter_map(|change| change.to) {
+        if !taken.insert(to) {
+            return Err(anyhow::anyhow!("Entry {to} already exists"));
+        }
+    }
+    Ok(())
+}
+
+/// Locked shadow entries stay shadow: their encrypted front matter may say
+/// `shadow = true`, which can't be cleared without the passphrase and would stop
+/// the build once the entry is unlocked in the public directory
+fn check_locked(changes: &[Change]) -> Result<(), anyhow::Error> {
+    for change in changes {
+        let locked = change.source.extension().is_some_and(|ext| ext == "enc");
+        if locked && change.from.shadow && change.to.is_some_and(|to| !to.shadow) {
+            return Err(anyhow::anyhow!(
+                "Entry {} is locked: unlock it before making it public",
+                change.from
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Rename the source of `change` into slot `to`, updating its `shadow` flag
+fn move_source(
+    config: &Config,
+    plan: &mut Plan,
+    change: &Change,
+    to: Slot,
+) -> Result<(), anyhow::Error> {
+    let file_name = change
+        .source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid file name {}", change.source.display()))?;
+    let destination = to.path(config, file_name);
+    plan.rename(&change.source, &destination)?;
+
+    let is_plaintext = destination.extension().is_some_and(|ext| ext == "md");
+    if is_plaintext && change.from.shadow != to.shadow {
+        let text = fs::read_to_string(&change.source)?;
+        let page = Page::parse(&text)?;
+        if page.has_front_matter && page.meta.shadow != to.shadow {
+            let text = front_matter::set_field(&text, "shadow", &Field::Bool(to.shadow))?;
+            plan.write(&destination, text);
+        }
+    }
+
+    plan.report(format!(
+        "Moved {} → {} (entry {} → {to})",
+        change.source.display(),
+        destination.display(),
+        change.from
+    ));
+    Ok(())
+}
+
+/// Move or drop the lockfile records of changed entries
+fn update_lockfile(
+    config: &Config,
+    plan: &mut Plan,
+    changes: &[Change],
+) -> Result<(), anyhow::Error> {
+    let mut lockfile = crate::read_lockfile(config)?;
+    let before = lockfile.locked_entries.len();
+    let mut renumbered = false;
+
+    lockfile.locked_entries.retain_mut(|record| {
+        let slot = Slot {
+            number: record.number,
+            shadow: record.shadow,
+        };
+        match changes.iter().find(|change| change.from == slot) {
+            None => true,
+            Some(Change { to: None, .. }) => false,
+            Some(Change { to: Some(to), .. }) => {
+                record.number = to.number;
+                record.shadow = to.shadow;
+                if to.shadow {
+                    record.clear_metadata();
+                }
+                renumbered = true;
+                true
+            }
+        }
+    });
+
+    if renumbered || lockfile.locked_entries.len() != before {
+        plan.write(
+            Path::new(&config.dirs.lockfile),
+            crate::lockfile_json(&lockfile)?,
+        );
+        plan.report(format!("Updated {}", config.dirs.lockfile));
+    }
+    Ok(())
+}
+
+/// Apply `update_listed` to the junkyard source, when it has a hand-maintained list
+fn update_junkyard(
+    config: &Config,
+    plan: &mut Plan,
+    number: u32,
+    title: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let path = Path::new(&config.dirs.junkyard);
+    let Some(source) = plan.read(path)? else {
+        return Ok(());
+    };
+    let updated = junkyard::update_listed(config, &source, number, title);
+    if updated != source {
+        plan.write(path, updated);
+        plan.report(format!("Updated {}", config.dirs.junkyard));
+    }
+    Ok(())
+}
+
+/// Rewrite links to moved entries in every plaintext source under `dirs.content`
+fn update_links(
+    config: &Config,
+    plan: &mut Plan,
+    moved: &BTreeMap<Slot, Option<Slot>>,
+) -> Result<(), anyhow::Error> {
+    let sources = WalkDir::new(&config.dirs.content)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"));
+
+    for source in sources {
+        // Sources as they will be once moved; deleted ones have nothing to update
+        let source = plan.destination(&source);
+        let Some(text) = plan.read(&source)? else {
+            continue;
+        };
+        let in_shadow = config.is_entries_dir(source.parent().unwrap_or(Path::new("")), true);
+        let (rewritten, dangling) = rewrite_links(config, &text, moved, in_shadow);
+        if rewritten != text {
+            plan.write(&source, rewritten);
+            plan.report(format!("Updated links in {}", source.display()));
+        }
+        for (from, to) in dangling {
+            let why = if to.is_some() {
+                "moved to shadow"
+            } else {
+                "deleted"
+            };
+            eprintln!(
+                "Warning: {} links to {}, which was {why}",
+                source.display(),
+                from.url(config)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Point links to moved entries in `text` at their new URL. Links to deleted entries,
+/// and from public sources (`in_shadow` false) to entries moved into shadow, are
+/// returned instead.
+///
+/// A link is a site path such as `/pub/entries/5.html`, or the same path behind
+/// `base_url`, that starts the text, a line, a markdown link or a quoted attribute.
+/// Paths of other sites are left alone.
+fn rewrite_links(
+    config: &Config,
+    text: &str,
+    moved: &BTreeMap<Slot, Option<Slot>>,
+    in_shadow: bool,
+) -> (String, Vec<(Slot, Option<Slot>)>) {
+    let link = Regex::new(&format!(
+        r#"(?m)(^|[\s(<\["'=])({})?({}|{})(\d+)\.html\b"#,
+        regex::escape(config.base_url.trim_end_matches('/')),
+        regex::escape(&config.entries_url(false)),
+        regex::escape(&config.entries_url(true))
+    ))
+    .expect("Invalid regex");
+
+    let mut dangling = Vec::new();
+    let rewritten = link.replace_all(text, |caps: &Captures| {
+        let Ok(number) = caps[4].parse() else {
+            return caps[0].to_string();
+        };
+        let from = Slot {
+            number,
+            shadow: caps[3] == config.entries_url(true),
+        };
+        match moved.get(&from) {
+            None => caps[0].to_string(),
+            Some(Some(to)) if in_shadow || !to.shadow => {
+                let site = caps.get(2).map_or("", |site| site.as_str());
+                format!("{}{site}{}", &caps[1], to.url(config))
+            }
+            Some(to) => {
+                if !dangling.contains(&(from, *to)) {
+                    dangling.push((from, *to));
+                }
+                caps[0].to_string()
+            }
+        }
+    });
+    (rewritten.into_owned(), dangling)
+}
+
+/// Keep `dirs.redirects` pointing at the entries' new URLs, and with `redirect` add
+/// one from every old URL
+fn update_redirects(
+    config: &Config,
+    plan: &mut Plan,
+    changes: &[Change],
+    redirect: bool,
+) -> Result<(), anyhow::Error> {
+    let mut moved = BTreeMap::new();
+    let mut live = BTreeSet::new();
+    for change in changes {
+        let to = match change.to {
+            // A public URL never leads to a shadow entry
+            Some(to) if to.shadow && !change.from.shadow => config.junkyard_url(),
+            Some(to) => {
+                live.insert(to.url(config));
+                to.url(config)
+            }
+            None if redirect => config.junkyard_url(),
+            None => continue,
+        };
+        moved.insert(change.from.url(config), to);
+    }
+
+    let mut redirects = Redirects::load(&config.dirs.redirects)?;
+    let before = redirects.redirects.clone();
+    redirects.apply(&moved, &live, redirect);
+    if redirects.redirects != before {
+        let path = Path::new(&config.dirs.redirects);
+        match redirects.to_toml()? {
+            Some(content) => plan.write(path, content),
+            None => plan.remove(path),
+        }
+        plan.report(format!("Updated {}", config.dirs.redirects));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(number: u32, shadow: bool) -> Slot {
+        Slot { number, shadow }
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        let config = Config::default();
+        let from = slot(5, false);
+        assert_eq!(
+            parse_destination(&config, "7s", from).unwrap(),
+            slot(7, true)
+        );
+        assert_eq!(
+            parse_destination(&config, "3p", from).unwrap(),
+            slot(3, false)
+        );
+        assert_eq!(
+            parse_destination(&config, "9", from).unwrap(),
+            slot(9, false)
+        );
+        assert!(parse_destination(&config, "0p", from).is_err());
+        assert!(parse_destination(&config, "x", from).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let config = Config::default();
+        let moved = BTreeMap::from([
+            (slot(3, false), Some(slot(2, false))),
+            (slot(4, false), Some(slot(1, true))),
+            (slot(5, false), None),
+        ]);
+        let text = "[a](/pub/entries/3.html#x) [b](https://enkron.github.io/pub/entries/3.html) \
+                    [c](/pub/entries/13.html) [d](/pub/entries/4.html) [e](/pub/entries/5.html)\n\
+                    /pub/entries/3.html <a href=\"/pub/entries/3.html\"></a> \
+                    [f](https://example.com/pub/entries/3.html) /x/pub/entries/3.html \
+                    /pub/entries/3.html5";
+
+        let (public, dangling) = rewrite_links(&config, text, &moved, false);
+        assert_eq!(
+            public,
+            "[a](/pub/entries/2.html#x) [b](https://enkron.github.io/pub/entries/2.html) \
+             [c](/pub/entries/13.html) [d](/pub/entries/4.html) [e](/pub/entries/5.html)\n\
+             /pub/entries/2.html <a href=\"/pub/entries/2.html\"></a> \
+             [f](https://example.com/pub/entries/3.html) /x/pub/entries/3.html \
+             /pub/entries/3.html5"
+        );
+        assert_eq!(
+            dangling,
+            [
+                (slot(4, false), Some(slot(1, true))),
+                (slot(5, false), None)
+            ]
+        );
+
+        let (shadow, dangling) = rewrite_links(&config, text, &moved, true);
+        assert!(shadow.contains("[d](/priv/entries/1.html)"));
+        assert_eq!(dangling, [(slot(5, false), None)]);
+    }
+
+    /// A site in a temporary directory with entries `files` (`dir/name`, content)
+    fn site(files: &[(&str, &str)]) -> (tempfile::TempDir, Config) {
+        let root = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| root.path().join(name).display().to_string();
+        for (name, content) in files {
+            let file = root.path().join(name);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        }
+        let config = Config::parse(&format!(
+            "[dirs]\ncontent = \"{}\"\nentries = \"{}\"\nshadow_entries = \"{}\"\n\
+             junkyard = \"{}\"\nlockfile = \"{}\"\nredirects = \"{}\"\n",
+            path("in"),
+            path("in/entries"),
+            path("in/entries/shadow"),
+            path("in/junkyard.md"),
+            path("locks"),
+            path("redirects.toml"),
+        ))
+        .unwrap();
+        (root, config)
+    }
+
+    #[test]
+    fn test_renumber_moves_files_links_locks_and_redirects() {
+        let locks = r#"{"version": "1.0", "locked_entries": [
+            {"number": 5, "shadow": false, "created": "", "title": "Secret"}]}"#;
+        let (root, config) = site(&[
+            (
+                "in/entries/1-first.md",
+                "See [third](/pub/entries/3.html).\n",
+            ),
+            ("in/entries/3-third.md", "+++\ntitle = \"third\"\n+++\n"),
+            ("in/entries/5-secret.enc", "ciphertext"),
+            ("in/junkyard.md", "<!-- entries -->\n"),
+            ("locks", locks),
+        ]);
+
+        renumber(&config, false, true).unwrap();
+
+        let entries = root.path().join("in/entries");
+        assert!(entries.join("2-third.md").is_file());
+        assert!(entries.join("3-secret.enc").is_file());
+        assert!(!entries.join("5-secret.enc").exists());
+        assert_eq!(
+            fs::read_to_string(entries.join("1-first.md")).unwrap(),
+            "See [third](/pub/entries/2.html).\n"
+        );
+
+        let lockfile = crate::read_lockfile(&config).unwrap();
+        assert_eq!(lockfile.locked_entries[0].number, 3);
+
+        let redirects = Redirects::load(&config.dirs.redirects).unwrap().redirects;
+        let redirects = redirects
+            .iter()
+            .map(|r| (r.from.as_str(), r.to.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(redirects, [("/pub/entries/5.html", "/pub/entries/3.html")]);
+    }
+
+    #[test]
+    fn test_failed_renumber_leaves_the_site_as_it_was() {
+        let (root, mut config) = site(&[
+            (
+                "in/entries/1-first.md",
+                "See [third](/pub/entries/3.html).\n",
+            ),
+            ("in/entries/3-third.md", "+++\ntitle = \"third\"\n+++\n"),
+        ]);
+        // Planned fine, but can't be written once the sources have moved
+        config.dirs.redirects = root
+            .path()
+            .join("missing/redirects.toml")
+            .display()
+            .to_string();
+
+        assert!(renumber(&config, false, true).is_err());
+        let entries = root.path().join("in/entries");
+        assert!(entries.join("3-third.md").is_file());
+        assert!(!entries.join("2-third.md").exists());
+        assert_eq!(
+            fs::read_to_string(entries.join("1-first.md")).unwrap(),
+            "See [third](/pub/entries/3.html).\n"
+        );
+    }
+
+    #[test]
+    fn test_mv_to_shadow_and_delete() {
+        let (root, config) = site(&[
+            (
+                "in/entries/1-first.md",
+                "See [second](/pub/entries/2.html).\n",
+            ),
+            ("in/entries/2-second.md", "+++\ntitle = \"second\"\n+++\n"),
+            (
+                "in/junkyard.md",
+                "- [first](/pub/entries/1.html)\n- [second](/pub/entries/2.html)\n",
+            ),
+        ]);
+
+        mv(&config, "2p", "s", true).unwrap();
+        let moved = root.path().join("in/entries/shadow/1-second.md");
+        assert_eq!(
+            fs::read_to_string(&moved).unwrap(),
+            "+++\ntitle = \"second\"\nshadow = true\n+++\n"
+        );
+        // Public sources keep the link rather than expose the shadow URL
+        assert!(
+            fs::read_to_string(root.path().join("in/entries/1-first.md"))
+                .unwrap()
+                .contains("/pub/entries/2.html")
+        );
+        assert_eq!(
+            fs::read_to_string(&config.dirs.junkyard).unwrap(),
+            "- [first](/pub/entries/1.html)\n"
+        );
+        let redirects = Redirects::load(&config.dirs.redirects).unwrap().redirects;
+        assert_eq!(redirects[0].to, config.junkyard_url());
+
+        assert!(mv(&config, "1s", "1p", false).is_err());
+        delete(&config, "1p", false).unwrap();
+        assert!(!root.path().join("in/entries/1-first.md").exists());
+        assert!(fs::read_to_string(&config.dirs.junkyard)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_publish_and_unpublish() {
+        let locks = r#"{"version": "1.0", "locked_entries": [
+            {"number": 2, "shadow": true, "created": "", "title": "Secret"}]}"#;
+        let (root, config) = site(&[
+            ("in/entries/1-first.md", "+++\ntitle = \"first\"\n+++\n"),
+            ("in/entries/shadow/1-draft.md", "+++\nshadow = true\n+++\n"),
+            ("in/entries/shadow/2-secret.enc", "ciphertext"),
+            ("in/junkyard.md", "<!-- entries -->\n"),
+            ("locks", locks),
+        ]);
+        let entries = root.path().join("in/entries");
+
+        assert!(publish(&config, "1p", false).is_err());
+        publish(&config, "1", true).unwrap();
+        assert_eq!(
+            fs::read_to_string(entries.join("2-draft.md")).unwrap(),
+            "+++\nshadow = false\n+++\n"
+        );
+        let redirects = Redirects::load(&config.dirs.redirects).unwrap().redirects;
+        assert_eq!(redirects[0].from, "/priv/entries/1.html");
+        assert_eq!(redirects[0].to, "/pub/entries/2.html");
+
+        unpublish(&config, "1", false).unwrap();
+        assert!(entries.join("shadow/3-first.md").is_file());
+        assert!(!entries.join("1-first.md").exists());
+    }
+
+    #[test]
+    fn test_publish_refuses_locked_shadow_entry() {
+        let locks = r#"{"version": "1.0", "locked_entries": [
+            {"number": 2, "shadow": true, "created": ""}]}"#;
+        let (root, config) = site(&[
+            ("in/entries/shadow/2-secret.enc", "ciphertext"),
+            ("locks", locks),
+        ]);
+        let entries = root.path().join("in/entries");
+
+        for result in [publish(&config, "2", false), mv(&config, "2s", "p", false)] {
+            let err = result.unwrap_err().to_string();
+            assert!(err.contains("unlock it"), "{err}");
+        }
+        assert!(entries.join("shadow/2-secret.enc").is_file());
+        assert!(!entries.join("1-secret.enc").exists());
+        assert!(crate::read_lockfile(&config).unwrap().locked_entries[0].shadow);
+    }
+
+    #[test]
+    fn test_rename() {
+        let (root, config) = site(&[
+            ("in/entries/1-first.md", "+++\ntitle = \"first\"\n+++\n"),
+            (
+                "in/junkyard.md",
+                "- 1.I.2024: [first](/pub/entries/1.html)\n",
+            ),
+        ]);
+
+        rename(&config, "1p", "First Steps").unwrap();
+        let renamed = root.path().join("in/entries/1-first-steps.md");
+        assert_eq!(
+            fs::read_to_string(renamed).unwrap(),
+            "+++\ntitle = \"First Steps\"\n+++\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&config.dirs.junkyard).unwrap(),
+            "- 1.I.2024: [First Steps](/pub/entries/1.html)\n"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Minimal scoped worker pool for fanning out independent build jobs.
+//!
+//! Results are returned in input order regardless of which worker finished first,
+//! so everything downstream (logging, cache updates, error reports) stays deterministic.
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Number of workers to use when the caller does not specify one
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Apply `f` to every item using up to `jobs` threads and collect results in input order.
+pub fn map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = jobs.clamp(1, items.len().max(1));
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<R>> = std::iter::repeat_with(|| None).take(items.len()).collect();
+
+    thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        done.push((index, f(item)));
+                    }
+                    done
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            for (index, result) in handle.join().expect("build worker panicked") {
+                results[index] = Some(result);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every job produces a result"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_preserves_input_order() {
+        let items = (0..100).collect::<Vec<u32>>();
+        let results = map(&items, 8, |n| n * 2);
+        assert_eq!(results, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_map_handles_empty_input_and_zero_jobs() {
+        let empty: Vec<u32> = Vec::new();
+        assert!(map(&empty, 4, |n| *n).is_empty());
+        assert_eq!(map(&[1, 2, 3], 0, |n| n + 1), vec![2, 3, 4]);
+    }
+}
@@ -2,7 +2,10 @@
 use std::fmt::Write;
 use std::io::Write as IoWrite;
 
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::collections::HashMap;
+
+use crate::headings::{self, Heading};
 
 const PAGE_WIDTH: f32 = 595.0;
 const PAGE_HEIGHT: f32 = 842.0;
@@ -722,14 +725,31 @@ fn text_width(text: &str, font_size: f32, bold: bool) -> f32 {
     width_units * font_size / 1000.0
 }
 
-pub fn render(markdown: &str) -> Vec<u8> {
+/// Document metadata written to the PDF `/Info` dictionary
+pub struct DocumentInfo<'a> {
+    pub title: Option<&'a str>,
+    pub author: &'a str,
+}
+
+pub fn render(markdown: &str, info: &DocumentInfo) -> Vec<u8> {
     let blocks = parse_markdown(markdown);
 
     let mut composer = PdfComposer::new();
     composer.render(&blocks);
+    let destinations = std::mem::take(&mut composer.headings);
     let pages = composer.finish();
 
-    write_pdf(&pages)
+    // Outline entries come from the same heading tree the HTML table of contents uses,
+    // matched in document order with where each heading landed
+    let mut flat = headings::collect(markdown);
+    flat.truncate(destinations.len());
+    let destinations = flat
+        .iter()
+        .map(|heading| heading.id.clone())
+        .zip(destinations)
+        .collect::<HashMap<_, _>>();
+
+    write_pdf(&pages, info, &headings::tree(flat), &destinations)
 }
 
 #[derive(Debug, Clone)]
@@ -862,7 +882,7 @@ impl MarkdownParser {
             Tag::Heading(level, _, _) => {
                 self.inline_stack.push(InlineContainer::Plain(Vec::new()));
                 self.block_stack
-                    .push(BlockContext::Heading(heading_level_number(*level)));
+                    .push(BlockContext::Heading(headings::level_number(*level)));
             }
             Tag::List(_) => {
                 self.list_stack.push(Vec::new());
@@ -1019,17 +1039,6 @@ impl MarkdownParser {
     }
 }
 
-fn heading_level_number(level: HeadingLevel) -> u32 {
-    match level {
-        HeadingLevel::H1 => 1,
-        HeadingLevel::H2 => 2,
-        HeadingLevel::H3 => 3,
-        HeadingLevel::H4 => 4,
-        HeadingLevel::H5 => 5,
-        HeadingLevel::H6 => 6,
-    }
-}
-
 fn push_inline(stack: &mut [InlineContainer], inline: Inline) {
     if let Some(container) = stack.last_mut() {
         match container {
@@ -1116,6 +1125,8 @@ struct PdfComposer {
     pages: Vec<PdfPage>,
     current: PdfPage,
     cursor_y: f32,
+    /// Page index and top edge of every heading, in document order
+    headings: Vec<(usize, f32)>,
 }
 
 impl PdfComposer {
@@ -1124,6 +1135,7 @@ impl PdfComposer {
             pages: Vec::new(),
             current: PdfPage::new(),
             cursor_y: PAGE_HEIGHT - MARGIN_TOP,
+            headings: Vec::new(),
         }
     }
 
@@ -1154,6 +1166,7 @@ impl PdfComposer {
             MARGIN_HORIZONTAL
         };
         let y = self.cursor_y;
+        self.headings.push((self.pages.len(), y + size));
         self.current.write_text(x, y, FontFace::Bold, size, &text);
         self.cursor_y -= spacing;
         if level == 1 {
@@ -1503,7 +1516,83 @@ fn write_stream(buffer: &mut Vec<u8>, offsets: &mut [usize], id: usize, data: &s
     );
 }
 
-fn write_pdf(pages: &[PdfPage]) -> Vec<u8> {
+/// Objects of the outline items for `headings` (siblings under `parent`), numbered from
+/// `next_id`. Returns the number of items written, nested ones included.
+fn outline_items(
+    headings: &[Heading],
+    parent: usize,
+    next_id: &mut usize,
+    destinations: &HashMap<String, (usize, f32)>,
+    objects: &mut Vec<(usize, String)>,
+) -> usize {
+    let ids = (*next_id..*next_id + headings.len()).collect::<Vec<_>>();
+    *next_id += headings.len();
+
+    let mut count = headings.len();
+    for (index, (heading, id)) in headings.iter().zip(&ids).enumerate() {
+        let mut dict = format!(
+            "<< /Title ({}) /Parent {parent} 0 R",
+            escape_pdf_text(&heading.title)
+        );
+        if index > 0 {
+            let _ = write!(dict, " /Prev {} 0 R", ids[index - 1]);
+        }
+        if let Some(next) = ids.get(index + 1) {
+            let _ = write!(dict, " /Next {next} 0 R");
+        }
+        if !heading.children.is_empty() {
+            let first = *next_id;
+            let nested = outline_items(&heading.children, *id, next_id, destinations, objects);
+            let last = first + heading.children.len() - 1;
+            let _ = write!(dict, " /First {first} 0 R /Last {last} 0 R /Count {nested}");
+            count += nested;
+        }
+        if let Some((page, top)) = destinations.get(&heading.id) {
+            let _ = write!(dict, " /Dest [{} 0 R /XYZ 0 {top:.2} 0]", 3 + page);
+        }
+        dict.push_str(" >>");
+        objects.push((*id, dict));
+    }
+    count
+}
+
+/// The `/Outlines` root (`outlines_id`) followed by one object per heading; nothing
+/// for a document without headings
+fn outline_objects(
+    outline: &[Heading],
+    outlines_id: usize,
+    destinations: &HashMap<String, (usize, f32)>,
+) -> Vec<(usize, String)> {
+    if outline.is_empty() {
+        return Vec::new();
+    }
+
+    let mut objects = Vec::new();
+    let mut next_id = outlines_id + 1;
+    let count = outline_items(
+        outline,
+        outlines_id,
+        &mut next_id,
+        destinations,
+        &mut objects,
+    );
+    objects.push((
+        outlines_id,
+        format!(
+            "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {count} >>",
+            outlines_id + 1,
+            outlines_id + outline.len()
+        ),
+    ));
+    objects
+}
+
+fn write_pdf(
+    pages: &[PdfPage],
+    info: &DocumentInfo,
+    outline: &[Heading],
+    destinations: &HashMap<String, (usize, f32)>,
+) -> Vec<u8> {
     let mut buffer = Vec::new();
     buffer.extend_from_slice(b"%PDF-1.4\n");
 
@@ -1513,15 +1602,23 @@ fn write_pdf(pages: &[PdfPage]) -> Vec<u8> {
     let font_bold_id = base_objects + 2;
     let content_start_id = font_bold_id + 1;
 
-    let total_objects = base_objects + 2 + page_count;
+    let info_id = content_start_id + page_count;
+
+    let outlines_id = info_id + 1;
+    let outline_objects = outline_objects(outline, outlines_id, destinations);
+    let total_objects = outline_objects
+        .iter()
+        .map(|(id, _)| *id)
+        .max()
+        .unwrap_or(info_id);
     let mut offsets = vec![0usize; total_objects + 1];
 
-    write_object(
-        &mut buffer,
-        &mut offsets,
-        1,
-        "<< /Type /Catalog /Pages 2 0 R >>",
-    );
+    let catalog = if outline.is_empty() {
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string()
+    } else {
+        format!("<< /Type /Catalog /Pages 2 0 R /Outlines {outlines_id} 0 R >>")
+    };
+    write_object(&mut buffer, &mut offsets, 1, &catalog);
 
     let mut kids = String::new();
     for i in 0..page_count {
@@ -1540,12 +1637,9 @@ fn write_pdf(pages: &[PdfPage]) -> Vec<u8> {
     );
 
     let mut content_ids = Vec::with_capacity(page_count);
-    let mut next_content_id = content_start_id;
 
-    for (index, _) in pages.iter().enumerate() {
+    for (content_id, (index, _)) in (content_start_id..).zip(pages.iter().enumerate()) {
         let page_id = 3 + index;
-        let content_id = next_content_id;
-        next_content_id += 1;
         content_ids.push(content_id);
 
         let page_dict = format!(
@@ -1571,6 +1665,17 @@ fn write_pdf(pages: &[PdfPage]) -> Vec<u8> {
         write_stream(&mut buffer, &mut offsets, *content_id, &page.content);
     }
 
+    let mut info_dict = format!("<< /Author ({})", escape_pdf_text(info.author));
+    if let Some(title) = info.title {
+        let _ = write!(info_dict, " /Title ({})", escape_pdf_text(title));
+    }
+    info_dict.push_str(" >>");
+    write_object(&mut buffer, &mut offsets, info_id, &info_dict);
+
+    for (id, dict) in &outline_objects {
+        write_object(&mut buffer, &mut offsets, *id, dict);
+    }
+
     let xref_offset = buffer.len();
     let _ = write!(buffer, "xref\n0 {}\n", total_objects + 1);
     buffer.extend_from_slice(b"0000000000 65535 f \n");
@@ -1580,8 +1685,9 @@ fn write_pdf(pages: &[PdfPage]) -> Vec<u8> {
 
     let _ = write!(
         buffer,
-        "trailer<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        "trailer<< /Size {} /Root 1 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
         total_objects + 1,
+        info_id,
         xref_offset
     );
 
@@ -0,0 +1,169 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Redirects from old URLs, kept in `dirs.redirects`:
+//!
+//! ```toml
+//! [[redirect]]
+//! from = "/pub/entries/4.html"
+//! to = "/pub/entries/3.html"
+//! ```
+//!
+//! The entry commands (`rename`, `delete`, `renumber`, `mv`) add them with `--redirect`;
+//! they can also be written by hand. Every build writes a stub page at each `from`
+//! that sends the browser on to `to`, unless a page of the site is built there.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One old URL and where it now lives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Redirect {
+    /// Site path of the old page, e.g. `/pub/entries/4.html`
+    pub from: String,
+    /// Site path or URL to send visitors to
+    pub to: String,
+}
+
+impl Redirect {
+    /// Output file of the stub, relative to the site root: `/a/b.html` → `a/b.html`,
+    /// `/a/` → `a/index.html`
+    pub fn output(&self) -> Option<PathBuf> {
+        let path = self.from.strip_prefix('/')?;
+        let mut output = PathBuf::from(path);
+        if path.is_empty() || path.ends_with('/') {
+            output.push("index.html");
+        }
+        Some(output)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Redirects {
+    #[serde(default, rename = "redirect")]
+    pub redirects: Vec<Redirect>,
+}
+
+impl Redirects {
+    /// Read `path`, or no redirects when it doesn't exist
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        if !Path::new(path).exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid {path}: {e}"))
+    }
+
+    /// Content of the redirects file, or `None` when there are none left and it
+    /// should be removed
+    pub fn to_toml(&self) -> Result<Option<String>, anyhow::Error> {
+        if self.redirects.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(toml::to_string(self)?))
+    }
+
+    /// Follow pages that moved from one URL to another (`moved`, old → new).
+    ///
+    /// Existing redirects to a moved page point at its new URL. With `redirect`, each
+    /// old URL also gets a redirect of its own. Redirects from a URL in `live` (where a
+    /// page is now) are dropped, so they never shadow it.
+    pub fn apply(
+        &mut self,
+        moved: &BTreeMap<String, String>,
+        live: &BTreeSet<String>,
+        redirect: bool,
+    ) {
+        for existing in &mut self.redirects {
+            if let Some(new) = moved.get(&existing.to) {
+                existing.to.clone_from(new);
+            }
+        }
+        if redirect {
+            for (from, to) in moved {
+                self.redirects.retain(|existing| existing.from != *from);
+                self.redirects.push(Redirect {
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+        }
+        self.redirects
+            .retain(|existing| existing.from != existing.to && !live.contains(&existing.from));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(from: &str, to: &str) -> Redirect {
+        Redirect {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_output() {
+        assert_eq!(
+            redirect("/pub/entries/4.html", "/").output(),
+            Some(PathBuf::from("pub/entries/4.html"))
+        );
+        assert_eq!(
+            redirect("/old/", "/").output(),
+            Some(PathBuf::from("old/index.html"))
+        );
+        assert_eq!(redirect("old.html", "/").output(), None);
+    }
+
+    #[test]
+    fn test_apply_follows_moves() {
+        let mut redirects = Redirects {
+            redirects: vec![
+                redirect("/pub/entries/9.html", "/pub/entries/3.html"),
+                redirect("/pub/entries/2.html", "/pub/entries/5.html"),
+            ],
+        };
+        // Compacting 2, 3 → 1, 2: entry 3 now lives at 2
+        let moved = BTreeMap::from([
+            (
+                "/pub/entries/2.html".to_string(),
+                "/pub/entries/1.html".to_string(),
+            ),
+            (
+                "/pub/entries/3.html".to_string(),
+                "/pub/entries/2.html".to_string(),
+            ),
+        ]);
+        let live = moved.values().cloned().collect();
+        redirects.apply(&moved, &live, true);
+
+        assert_eq!(
+            redirects.redirects,
+            [
+                redirect("/pub/entries/9.html", "/pub/entries/2.html"),
+                redirect("/pub/entries/3.html", "/pub/entries/2.html"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_and_to_toml() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("redirects.toml");
+        let path = path.to_str().unwrap();
+        assert!(Redirects::load(path).unwrap().redirects.is_empty());
+
+        let mut redirects = Redirects::default();
+        redirects.redirects.push(redirect("/a.html", "/b.html"));
+        fs::write(path, redirects.to_toml().unwrap().unwrap()).unwrap();
+        assert_eq!(
+            Redirects::load(path).unwrap().redirects,
+            redirects.redirects
+        );
+        assert!(Redirects::default().to_toml().unwrap().is_none());
+    }
+}
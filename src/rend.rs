@@ -1,96 +1,415 @@
 #![warn(clippy::all, clippy::pedantic)]
+use chrono::{DateTime, FixedOffset};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
 use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
 
-// Hash the CSS bytes at compile time and reuse the digest when templating the head
-// so the generated HTML gets a cache-busting query string whenever these files change.
-// Browsers treat `?v=<hash>` as a new resource, which avoids manual version bumps.
-static MAIN_CSS_HASH: std::sync::LazyLock<String> =
-    std::sync::LazyLock::new(|| format!("{:x}", Sha256::digest(include_bytes!("../css/main.css"))));
-static HACK_CSS_HASH: std::sync::LazyLock<String> =
-    std::sync::LazyLock::new(|| format!("{:x}", Sha256::digest(include_bytes!("../web/hack.css"))));
-
-pub struct Layout;
-impl Layout {
-    pub fn header() -> String {
-        format!(
-            r#"
-        <!DOCTYPE html>
-        <html lang="en-US">
-
-        <head>
-            <meta charset="utf-8">
-            <meta name="viewport" content="width=device-width, initial-scale=1">
-            <link rel="stylesheet" href="/css/main.css?v={main_hash}" type="text/css">
-            <link rel="stylesheet" href="/web/hack.css?v={hack_hash}">
-            <title>enk junkyard</title>
-            <link rel="apple-touch-icon" sizes="180x180" href="/favicon/apple-touch-icon.png">
-            <link rel="icon" type="image/png" sizes="32x32" href="/favicon/favicon-32x32.png">
-            <link rel="icon" type="image/png" sizes="16x16" href="/favicon/favicon-16x16.png">
-            <link rel="manifest" href="/favicon/site.webmanifest">
-            <link rel="mask-icon" href="/favicon/safari-pinned-tab.svg" color="\#5bbad5">
-            <link rel="shortcut icon" href="/favicon/favicon.ico">
-            <meta name="msapplication-TileColor" content="\#da532c">
-            <meta name="msapplication-config" content="/favicon/browserconfig.xml">
-            <meta name="theme-color" content="\#ffffff">
-            <nav role="navigation" class="navigation">
-                <a href="/">
-                    <img class="logo" src="/favicon/favicon-32x32.png" alt="-__-"/>
-                </a>
-                <button class="theme-toggle" id="theme-toggle" aria-label="Toggle dark mode">
-                    <span id="theme-icon">✸</span>
-                </button>
-                <ul>
-                    <li><a href="/pub/junkyard.html">junkyard</a></li>
-                    <li><a href="/cv.html">cv</a></li>
-                </ul>
-            </nav>
-        </head>"#,
-            main_hash = &*MAIN_CSS_HASH,
-            hack_hash = &*HACK_CSS_HASH
-        )
-    }
-
-    pub fn body(body: &str) -> String {
-        format!(
-            r#"
-            <body>
-                <div id="page-container">
-                <div id="content-wrap">
-                <br />
-                    {body}
-                </div>
-            </body>"#,
-        )
-    }
-
-    pub fn footer() -> String {
-        let github_run_id = match std::env::var("GITHUB_RUN_NUMBER") {
-            Ok(v) => v,
-            Err(_) => "no GITHUB_RUN_NUMBER variable is found".into(),
+use crate::assets::Assets;
+use crate::build_info::{self, BuildInfo};
+use crate::config::Config;
+use crate::feed;
+use crate::front_matter;
+use crate::headings;
+use crate::highlight;
+use crate::search;
+use crate::template::{context, Context, Templates, Value};
+
+/// Open Graph object type of a page
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum PageType {
+    #[default]
+    Website,
+    Article,
+}
+
+/// Per-page metadata rendered into the document head.
+///
+/// Everything here ends up in publicly readable `<meta>` tags, so for locked entries
+/// only the title, URL and type may be filled in.
+#[derive(Debug, Default)]
+pub struct PageContext {
+    /// Page title, shown before the site title in the tab
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Site path of the page (e.g. `/pub/entries/3.html`), made absolute for the canonical link
+    pub path: Option<String>,
+    pub page_type: PageType,
+    pub published: Option<DateTime<FixedOffset>>,
+    /// Ask crawlers to skip the page (shadow entries)
+    pub noindex: bool,
+    /// Send visitors straight on to this URL (directory index stubs)
+    pub redirect: Option<String>,
+    /// Rendered only by `--drafts` builds; shows the draft banner
+    pub draft: bool,
+    /// Publish date still ahead of the build's `--as-of` time; shows the scheduled banner
+    pub scheduled: Option<DateTime<FixedOffset>>,
+    /// Last modification of the page's content, shown in the footer
+    pub updated: Option<DateTime<FixedOffset>>,
+}
+
+impl PageContext {
+    /// The `page` template variable
+    fn value(&self, config: &Config) -> Value {
+        let tab_title = match &self.title {
+            Some(title) => format!("{title} | {}", config.title),
+            None => config.title.clone(),
+        };
+        let canonical = match &self.redirect {
+            Some(redirect) if redirect.starts_with('/') => Some(config.absolute_url(redirect)),
+            Some(redirect) => Some(redirect.clone()),
+            None => self.path.as_deref().map(|path| config.absolute_url(path)),
+        };
+        let page_type = match self.page_type {
+            PageType::Website => "website",
+            PageType::Article => "article",
+        };
+        let published = match self.page_type {
+            PageType::Article => self.published.map(|date| date.to_rfc3339()),
+            PageType::Website => None,
         };
 
-        let github_sha = match std::env::var("GITHUB_SHA") {
-            Ok(v) => v,
-            Err(_) => "no GITHUB_SHA variable is found".into(),
+        Value::from([
+            ("title", tab_title.into()),
+            (
+                "og_title",
+                self.title
+                    .clone()
+                    .unwrap_or_else(|| config.title.clone())
+                    .into(),
+            ),
+            ("description", self.description.clone().into()),
+            ("url", canonical.into()),
+            ("type", page_type.into()),
+            ("published", published.into()),
+            ("noindex", self.noindex.into()),
+            ("redirect", self.redirect.clone().into()),
+            ("draft", self.draft.into()),
+            (
+                "scheduled",
+                self.scheduled.map(front_matter::format_timestamp).into(),
+            ),
+            (
+                "updated",
+                self.updated
+                    .map(|date| date.to_utc().format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                    .into(),
+            ),
+        ])
+    }
+}
+
+/// Page templates (`dirs.templates` over the built-in ones) plus the site-wide
+/// variables every page gets
+pub struct Layout<'a> {
+    config: &'a Config,
+    templates: Templates,
+    assets: Assets,
+    build: BuildInfo,
+    fingerprint: String,
+}
+
+impl<'a> Layout<'a> {
+    pub fn load(config: &'a Config, assets: Assets) -> Result<Self, anyhow::Error> {
+        let templates = Templates::load(&config.dirs.templates)?;
+        let build = build_info::detect(config);
+        let config_hash = Sha256::digest(format!("{config:?}"));
+        // Build metadata is in the key as every footer shows it: a new build rewrites
+        // every page, so pages never name different builds
+        let fingerprint = format!(
+            "{}:{config_hash:x}:{}:{build:?}",
+            assets.fingerprint(),
+            templates.fingerprint()
+        );
+        Ok(Self {
+            config,
+            templates,
+            assets,
+            build,
+            fingerprint,
+        })
+    }
+
+    pub fn config(&self) -> &'a Config {
+        self.config
+    }
+
+    /// Combined digest of the asset versions, site settings, templates and build metadata
+    /// every page depends on.
+    /// Used as part of the build cache key so any of them changing invalidates generated HTML.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Like `render`, with rendered markdown `html` as the `content` of `template`.
+    /// Asset paths in its code samples stay as written.
+    pub fn render_markdown(
+        &self,
+        template: &str,
+        page: &PageContext,
+        html: &str,
+        mut vars: Context,
+    ) -> Result<String, anyhow::Error> {
+        // Stands in for the markdown while the templates are rendered and rewritten
+        const PLACEHOLDER: &str = "\u{0}markdown\u{0}";
+
+        vars.insert("content".to_string(), PLACEHOLDER.into());
+        let page = self.render(template, page, vars)?;
+        Ok(page.replace(PLACEHOLDER, &self.assets.rewrite_markdown(html)))
+    }
+
+    /// Render the content template `template` with `vars` and wrap it in `base.html`.
+    /// Both templates also see `site` and `page`; links to static assets are versioned.
+    pub fn render(
+        &self,
+        template: &str,
+        page: &PageContext,
+        mut vars: Context,
+    ) -> Result<String, anyhow::Error> {
+        let site = self.site();
+        let page = page.value(self.config);
+        vars.insert("site".to_string(), site.clone());
+        vars.insert("page".to_string(), page.clone());
+        let content = self.templates.render(template, &vars)?;
+
+        let html = self.templates.render(
+            "base.html",
+            &context([
+                ("site", site),
+                ("page", page),
+                ("build", self.build_info()),
+                ("content", content.into()),
+            ]),
+        )?;
+        Ok(self.assets.rewrite_html(&html))
+    }
+
+    fn site(&self) -> Value {
+        let config = self.config;
+        Value::from([
+            ("title", config.title.as_str().into()),
+            ("author", config.author.as_str().into()),
+            ("base_url", config.base_url.as_str().into()),
+            ("junkyard_url", config.junkyard_url().into()),
+            ("tags_url", config.tags_url().into()),
+            ("atom_url", config.public_url(feed::ATOM).into()),
+            ("rss_url", config.public_url(feed::RSS).into()),
+            ("json_url", config.public_url(feed::JSON).into()),
+            ("search_url", config.public_url(search::INDEX).into()),
+        ])
+    }
+
+    fn build_info(&self) -> Value {
+        Value::from([
+            ("number", self.build.number.clone().into()),
+            ("sha", self.build.sha.clone().into()),
+        ])
+    }
+}
+
+/// Site path of a generated file (relative to the site root); `dir/index.html` becomes `dir/`
+pub fn site_path(output: &Path) -> String {
+    let path = format!("/{}", output.display());
+    match path.strip_suffix("index.html") {
+        Some(dir) => dir.to_string(),
+        None => path,
+    }
+}
+
+/// Resolve `.` and `..` without touching the filesystem; `..` never leaves the root
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(part) => normalized.push(part),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+/// Whether two paths name the same file or directory, compared lexically once made
+/// absolute: `./in/entries`, `in/entries/` and `/site/in/entries` all match.
+/// An empty path is the current directory.
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    let absolute = |path: &Path| {
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
         };
+        std::path::absolute(path).map(|path| normalize(&path))
+    };
+    matches!((absolute(a), absolute(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Render markdown to an HTML fragment with every pulldown-cmark extension enabled.
+/// Fenced code blocks are syntax highlighted and headings get ids and permalinks.
+/// A table of contents replaces `<!-- toc -->`; with `toc` set and no marker, it goes
+/// before the first section heading.
+pub fn markdown_to_html(markdown: &str, toc: bool) -> String {
+    let headings = headings::collect(markdown);
+    let toc_html = headings::toc_html(&headings::tree(headings.clone()));
+    let mut ids = headings.into_iter().map(|heading| heading.id);
+    let mut toc_pending = toc
+        && !markdown
+            .lines()
+            .any(|line| line.trim() == headings::TOC_MARKER);
+
+    let mut events = Vec::new();
+    // Info string and text of the fenced code block being collected
+    let mut fence: Option<(String, String)> = None;
+    // Id of the heading being rendered
+    let mut heading_id = String::new();
+    for event in Parser::new_ext(markdown, Options::all()) {
+        match (event, &mut fence) {
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), None) => {
+                fence = Some((info.to_string(), String::new()));
+            }
+            (Event::Text(text), Some((_, code))) => code.push_str(&text),
+            (Event::End(Tag::CodeBlock(_)), Some(_)) => {
+                let (info, code) = fence.take().unwrap_or_default();
+                events.push(Event::Html(highlight::code_block(&info, &code).into()));
+            }
+            (Event::Html(html), None) if html.trim() == headings::TOC_MARKER => {
+                events.push(Event::Html(toc_html.clone().into()));
+            }
+            (Event::Start(Tag::Heading(level, _, classes)), None) => {
+                if toc_pending && level != HeadingLevel::H1 {
+                    events.push(Event::Html(toc_html.clone().into()));
+                    toc_pending = false;
+                }
+                heading_id = escape_html(&ids.next().unwrap_or_default());
+                let mut tag = format!("<{level} id=\"{heading_id}\"");
+                if !classes.is_empty() {
+                    let _ = write!(tag, " class=\"{}\"", escape_html(&classes.join(" ")));
+                }
+                tag.push('>');
+                events.push(Event::Html(tag.into()));
+            }
+            (Event::End(Tag::Heading(level, ..)), None) => {
+                events.push(Event::Html(
+                    format!(
+                        " <a class=\"heading-anchor\" href=\"#{heading_id}\" aria-label=\"Permalink\">#</a></{level}>\n"
+                    )
+                    .into(),
+                ));
+            }
+            (event, _) => events.push(event),
+        }
+    }
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    html
+}
+
+/// Escape text for use inside HTML element content or attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(context: &PageContext) -> String {
+        let config = Config::default();
+        let layout = Layout::load(&config, Assets::default()).unwrap();
+        layout
+            .render(
+                "404.html",
+                context,
+                crate::template::context([("message", "gone".into())]),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_head_metadata_for_article() {
+        let html = render(&PageContext {
+            title: Some("a \"quoted\" title".to_string()),
+            description: Some("about it".to_string()),
+            path: Some("/pub/entries/3.html".to_string()),
+            page_type: PageType::Article,
+            published: DateTime::parse_from_rfc3339("2024-05-24T13:05:00+02:00").ok(),
+            ..PageContext::default()
+        });
+
+        assert!(html.contains(
+            r#"<link rel="canonical" href="https://enkron.github.io/pub/entries/3.html">"#
+        ));
+        assert!(html.contains(r#"<meta property="og:title" content="a &quot;quoted&quot; title">"#));
+        assert!(html.contains(r#"<meta property="og:type" content="article">"#));
+        assert!(html.contains(r#"<meta name="description" content="about it">"#));
+        assert!(html.contains("article:published_time"));
+        assert!(html.contains("<title>a &quot;quoted&quot; title | enk junkyard</title>"));
+        assert!(html.contains("<p>gone</p>"));
+        assert!(!html.contains("noindex"));
+    }
+
+    #[test]
+    fn test_head_metadata_minimal_context() {
+        let html = render(&PageContext {
+            noindex: true,
+            ..PageContext::default()
+        });
+
+        assert!(html.contains(r#"<meta name="robots" content="noindex">"#));
+        assert!(html.contains(r#"<meta property="og:title" content="enk junkyard">"#));
+        assert!(html.contains("<title>enk junkyard</title>"));
+        assert!(!html.contains("canonical"));
+        assert!(!html.contains(r#"name="description""#));
+    }
+
+    #[test]
+    fn test_markdown_headings_and_toc() {
+        let md = "# Title\n\nintro\n\n## First step\n\ntext\n\n### Detail\n";
+        let html = markdown_to_html(md, false);
+        assert!(html.contains(
+            "<h2 id=\"first-step\">First step <a class=\"heading-anchor\" href=\"#first-step\""
+        ));
+        assert!(!html.contains("class=\"toc\""));
+
+        let html = markdown_to_html(md, true);
+        let toc = html.find("<nav class=\"toc\">").unwrap();
+        assert!(html.find("intro").unwrap() < toc);
+        assert!(toc < html.find("<h2").unwrap());
+
+        let html = markdown_to_html(&md.replace("intro", "<!-- toc -->"), false);
+        assert!(html.contains("<li><a href=\"#detail\">Detail</a></li>"));
+    }
+
+    #[test]
+    fn test_site_path() {
+        assert_eq!(site_path(Path::new("index.html")), "/");
+        assert_eq!(site_path(Path::new("pub/tags/index.html")), "/pub/tags/");
+        assert_eq!(site_path(Path::new("cv.html")), "/cv.html");
+    }
 
-        format!(
-            r#"
-            <footer>
-                <div class="footer">
-                    <p>build {}: {}</p>
-                    <p>updated: {}</p>
-                </div>
-            </footer>
-            </div>
-            <script type="module">
-                import init from '/web/pkg/enkronio.js';
-                init();
-            </script>
-            </html>"#,
-            github_run_id,
-            github_sha,
-            chrono::offset::Utc::now(),
-        )
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("pub/entries/../x.html")),
+            PathBuf::from("pub/x.html")
+        );
+        assert_eq!(
+            normalize(Path::new("../../x.html")),
+            PathBuf::from("x.html")
+        );
     }
 }
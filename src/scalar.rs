@@ -0,0 +1,47 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Quoted scalars of front matter blocks.
+//!
+//! Shared by the site generator and the WASM module: the browser reads the title of
+//! a decrypted entry without a TOML or YAML parser, and has to unquote it the same
+//! way the build does.
+
+/// Syntax of a front matter block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// `+++` delimited
+    #[allow(dead_code)] // the generator reads TOML with the `toml` crate
+    Toml,
+    /// `---` delimited
+    Yaml,
+}
+
+/// `value` with its quotes removed. `"double"` quotes take `\"`, `\\`, `\n` and `\t`
+/// escapes in either syntax; `'single'` quotes are literal in TOML and take `''` for a
+/// quote in YAML. Plain values are only trimmed.
+pub fn unquote(value: &str, syntax: Syntax) -> String {
+    let value = value.trim();
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        return match syntax {
+            Syntax::Toml => inner.to_string(),
+            Syntax::Yaml => inner.replace("''", "'"),
+        };
+    }
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_string();
+    };
+
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        unescaped.push(match ch {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(escaped) => escaped,
+                None => '\\',
+            },
+            ch => ch,
+        });
+    }
+    unescaped
+}
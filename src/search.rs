@@ -0,0 +1,181 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Search index for the client-side full-text search.
+//!
+//! The index is a JSON file with one document per public entry (URL, title, date and
+//! plain text for snippets) and an inverted index from each token to the documents
+//! containing it, weighted by where the token appears. The WASM module in `src/lib.rs`
+//! fetches it on first use and ranks results from the weights alone.
+//!
+//! Only listed, plaintext entries are indexed: shadow entries, locked entries and
+//! anything unpublished never reach the file.
+
+use crate::cache::{BuildCache, CacheKey};
+use crate::config::Config;
+use crate::entries::Entry;
+use crate::front_matter;
+use crate::headings;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Index file written to `dirs.public`
+pub const INDEX: &str = "search.json";
+
+/// Weight of a token in the title, a heading and the body text
+const TITLE_WEIGHT: u32 = 10;
+const HEADING_WEIGHT: u32 = 5;
+const BODY_WEIGHT: u32 = 1;
+
+#[derive(Debug, Serialize)]
+struct Index {
+    docs: Vec<Doc>,
+    /// Token → `[document, weight]` pairs, by document
+    terms: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Doc {
+    url: String,
+    title: String,
+    date: Option<String>,
+    /// Plain text of the body, for result snippets
+    text: String,
+}
+
+/// Write the search index of `entries`
+pub fn generate(
+    config: &Config,
+    entries: &[Entry],
+    cache: &mut BuildCache,
+    generator: &str,
+) -> Result<(), anyhow::Error> {
+    let path = PathBuf::from(&config.dirs.public).join(INDEX);
+    let content = serde_json::to_string(&index(config, entries))?;
+
+    let key = CacheKey::new().with(generator).with(&content).finish();
+    if cache.write(&path, key, content)? {
+        eprintln!("Generated: {}", path.display());
+    }
+    Ok(())
+}
+
+fn index(config: &Config, entries: &[Entry]) -> Index {
+    let mut index = Index {
+        docs: Vec::new(),
+        terms: BTreeMap::new(),
+    };
+
+    let indexed = entries
+        .iter()
+        .filter(|entry| entry.is_listed() && !entry.locked);
+    for (number, entry) in (0..).zip(indexed) {
+        let markdown = entry.markdown.as_deref().unwrap_or_default();
+        let title = entry.title();
+        let text = plain_text(markdown);
+
+        let mut weights: BTreeMap<String, u32> = BTreeMap::new();
+        let mut add = |text: &str, weight: u32| {
+            for token in tokenize(text) {
+                *weights.entry(token).or_default() += weight;
+            }
+        };
+        add(&title, TITLE_WEIGHT);
+        for heading in headings::collect(markdown) {
+            add(&heading.title, HEADING_WEIGHT);
+        }
+        add(&text, BODY_WEIGHT);
+
+        for (token, weight) in weights {
+            index.terms.entry(token).or_default().push((number, weight));
+        }
+        index.docs.push(Doc {
+            url: entry.url(config),
+            title,
+            date: entry.meta.date.map(front_matter::format_date),
+            text,
+        });
+    }
+
+    index
+}
+
+/// Text and code of `markdown` with whitespace collapsed; raw HTML is left out
+fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Text(part) | Event::Code(part) => {
+                text.push_str(&part);
+            }
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::Item | Tag::CodeBlock(_)) => {
+                text.push(' ');
+            }
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lowercase alphanumeric runs of at least two characters.
+/// Must match `tokenize` in src/lib.rs, which splits search queries.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().nth(1).is_some())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::FrontMatter;
+
+    fn entry(number: u32, shadow: bool, locked: bool, markdown: &str) -> Entry {
+        Entry {
+            number,
+            shadow,
+            locked,
+            scheduled: false,
+            meta: FrontMatter {
+                title: Some(format!("NAT title {number}")),
+                ..FrontMatter::default()
+            },
+            markdown: (!locked).then(|| markdown.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens =
+            tokenize("Setting up NAT-based IPv6 (a ULA) for libvirt_1").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            ["setting", "up", "nat", "based", "ipv6", "ula", "for", "libvirt"]
+        );
+    }
+
+    #[test]
+    fn test_index_weights_and_privacy() {
+        let entries = [
+            entry(
+                3,
+                false,
+                false,
+                "# heading\n\n## nat setup\n\nuse `virsh` <b>x</b> with nat\n",
+            ),
+            entry(2, true, false, "secret shadow text"),
+            entry(1, false, true, ""),
+        ];
+        let index = index(&Config::default(), &entries);
+
+        assert_eq!(index.docs.len(), 1);
+        assert_eq!(index.docs[0].url, "/pub/entries/3.html");
+        assert_eq!(index.docs[0].text, "heading nat setup use virsh x with nat");
+        assert_eq!(index.terms["nat"], [(0, TITLE_WEIGHT + HEADING_WEIGHT + 2)]);
+        assert_eq!(index.terms["virsh"], [(0, BODY_WEIGHT)]);
+        assert!(!index.terms.contains_key("secret"));
+        assert!(!index.terms.contains_key("b"));
+    }
+}
@@ -0,0 +1,386 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Local preview server.
+//!
+//! Serves the repository root over plain HTTP on localhost, the way GitHub Pages
+//! serves it: directories redirect to their `index.html`, misses get `404.html`.
+//! Watched source directories are polled for changes; every change rebuilds the site
+//! and tells open pages to reload over a server-sent events stream, whose client
+//! script is injected into HTML responses only (the generated files are untouched).
+//! Nothing is fetched from the network, so it works offline.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// Path of the server-sent events stream that announces rebuilds
+const EVENTS_PATH: &str = "/__enkronio/events";
+/// Injected before `</body>` of every HTML response
+const RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__enkronio/events\").onmessage = () => location.reload();</script>\n";
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Pages waiting for the next reload
+type Clients = Arc<Mutex<Vec<TcpStream>>>;
+
+/// Serve `root` on `localhost:port` and run `build` whenever a file under `watch`
+/// changes. Runs until interrupted.
+pub fn run(
+    root: &Path,
+    port: u16,
+    watch: &[&Path],
+    build: impl Fn() -> Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    if let Err(e) = build() {
+        eprintln!("Build failed: {e:#}");
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .map_err(|e| anyhow::anyhow!("Cannot listen on localhost:{port}: {e}"))?;
+    let clients = Clients::default();
+    eprintln!("Serving {} at http://localhost:{port}/", root.display());
+    eprintln!("Watching {} (Ctrl-C to stop)", display_list(watch));
+
+    let server_root = root.to_path_buf();
+    let server_clients = Arc::clone(&clients);
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(Result::ok) {
+            let root = server_root.clone();
+            let clients = Arc::clone(&server_clients);
+            thread::spawn(move || {
+                if let Err(e) = handle(&root, &clients, stream) {
+                    eprintln!("Warning: request failed: {e}");
+                }
+            });
+        }
+    });
+
+    let mut snapshot = snapshot(watch);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let current = snapshot_settled(watch);
+        let Some(changed) = first_change(&snapshot, &current) else {
+            continue;
+        };
+        snapshot = current;
+
+        eprintln!("Changed: {}", changed.display());
+        match build() {
+            Ok(()) => reload(&clients),
+            Err(e) => eprintln!("Build failed: {e:#}"),
+        }
+    }
+}
+
+fn display_list(paths: &[&Path]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Modification time and size of every file under `dirs`
+fn snapshot(dirs: &[&Path]) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+    dirs.iter()
+        .flat_map(|dir| WalkDir::new(dir).into_iter().filter_map(Result::ok))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(fs::Metadata::is_file)?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.into_path(), (modified, metadata.len())))
+        })
+        .collect()
+}
+
+/// Snapshot taken once files stop changing, so an editor's or `wasm-pack`'s burst of
+/// writes triggers a single rebuild
+fn snapshot_settled(dirs: &[&Path]) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+    let mut current = snapshot(dirs);
+    loop {
+        thread::sleep(POLL_INTERVAL / 3);
+        let next = snapshot(dirs);
+        if next == current {
+            return current;
+        }
+        current = next;
+    }
+}
+
+/// A file added, changed or removed between two snapshots
+fn first_change(
+    before: &BTreeMap<PathBuf, (SystemTime, u64)>,
+    after: &BTreeMap<PathBuf, (SystemTime, u64)>,
+) -> Option<PathBuf> {
+    after
+        .iter()
+        .find(|(path, stamp)| before.get(*path) != Some(stamp))
+        .map(|(path, _)| path)
+        .or_else(|| before.keys().find(|path| !after.contains_key(*path)))
+        .cloned()
+}
+
+/// Tell every open page to reload, forgetting the ones that went away
+fn reload(clients: &Clients) {
+    let mut clients = clients.lock().expect("clients lock poisoned");
+    clients.retain_mut(|stream| stream.write_all(b"data: reload\n\n").is_ok());
+    if !clients.is_empty() {
+        eprintln!("Reloaded {} page(s)", clients.len());
+    }
+}
+
+fn handle(root: &Path, clients: &Clients, mut stream: TcpStream) -> Result<(), anyhow::Error> {
+    let mut request_line = String::new();
+    {
+        let mut reader = BufReader::new(&stream);
+        reader.read_line(&mut request_line)?;
+        // Drain the headers; nothing in them changes the response
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return respond(
+            &mut stream,
+            "400 Bad Request",
+            "text/plain",
+            b"Bad request\n",
+            false,
+        );
+    };
+    let head = method == "HEAD";
+    if method != "GET" && !head {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method not allowed\n",
+            head,
+        );
+    }
+
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    if path == EVENTS_PATH {
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n",
+        )?;
+        clients.lock().expect("clients lock poisoned").push(stream);
+        return Ok(());
+    }
+
+    let Some(relative) = decode_path(path) else {
+        return respond(
+            &mut stream,
+            "400 Bad Request",
+            "text/plain",
+            b"Bad request\n",
+            head,
+        );
+    };
+    let file = root.join(&relative);
+    if file.is_dir() && !path.ends_with('/') {
+        let location = format!("{path}/");
+        let response = format!(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        stream.write_all(response.as_bytes())?;
+        return Ok(());
+    }
+    let file = if file.is_dir() {
+        file.join("index.html")
+    } else {
+        file
+    };
+
+    if let Ok(body) = fs::read(&file) {
+        respond(&mut stream, "200 OK", content_type(&file), &body, head)
+    } else {
+        let body = fs::read(root.join("404.html")).unwrap_or_else(|_| b"Not found\n".to_vec());
+        respond(&mut stream, "404 Not Found", "text/html", &body, head)
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    head: bool,
+) -> Result<(), anyhow::Error> {
+    let body = if content_type == "text/html" {
+        inject_reload(body)
+    } else {
+        body.to_vec()
+    };
+    let charset = if content_type.starts_with("text/")
+        || matches!(content_type, "application/json" | "application/xml")
+    {
+        "; charset=utf-8"
+    } else {
+        ""
+    };
+    let header = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}{charset}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(header.as_bytes())?;
+    if !head {
+        stream.write_all(&body)?;
+    }
+    Ok(())
+}
+
+/// `body` with the live reload script before its last `</body>`, or appended
+fn inject_reload(body: &[u8]) -> Vec<u8> {
+    let html = String::from_utf8_lossy(body);
+    let at = html.rfind("</body>").unwrap_or(html.len());
+    let mut injected = String::with_capacity(html.len() + RELOAD_SCRIPT.len());
+    injected.push_str(&html[..at]);
+    injected.push_str(RELOAD_SCRIPT);
+    injected.push_str(&html[at..]);
+    injected.into_bytes()
+}
+
+/// Percent-decode a request path into a path relative to the served root.
+/// `None` for malformed paths and anything trying to climb out of the root.
+fn decode_path(path: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    let decoded = String::from_utf8(bytes).ok()?;
+
+    let mut relative = PathBuf::new();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(relative)
+}
+
+/// MIME type by file extension; browsers refuse to compile WASM served as anything
+/// but `application/wasm`
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js" | "mjs") => "text/javascript",
+        Some("wasm") => "application/wasm",
+        Some("json" | "map") => "application/json",
+        Some("webmanifest") => "application/manifest+json",
+        Some("xml") => "application/xml",
+        Some("txt" | "md") => "text/plain",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_decode_path() {
+        assert_eq!(
+            decode_path("/pub/entries/3.html"),
+            Some(PathBuf::from("pub/entries/3.html"))
+        );
+        assert_eq!(decode_path("/a%20b/./c"), Some(PathBuf::from("a b/c")));
+        assert_eq!(decode_path("/"), Some(PathBuf::new()));
+        assert_eq!(decode_path("/../etc/passwd"), None);
+        assert_eq!(decode_path("/%2e%2e/x"), None);
+        assert_eq!(decode_path("/%zz"), None);
+    }
+
+    #[test]
+    fn test_content_types() {
+        assert_eq!(
+            content_type(Path::new("web/pkg/enkronio_bg.wasm")),
+            "application/wasm"
+        );
+        assert_eq!(
+            content_type(Path::new("web/pkg/enkronio.js")),
+            "text/javascript"
+        );
+        assert_eq!(
+            content_type(Path::new("favicon/site.webmanifest")),
+            "application/manifest+json"
+        );
+        assert_eq!(
+            content_type(Path::new("download/cv.PDF")),
+            "application/pdf"
+        );
+        assert_eq!(
+            content_type(Path::new("LICENSE")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_serves_files_and_404() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("pub")).unwrap();
+        fs::write(root.join("pub/index.html"), "<body>hi</body>").unwrap();
+        fs::write(root.join("404.html"), "<body>missing</body>").unwrap();
+        fs::write(root.join("x.wasm"), b"\0asm").unwrap();
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server_root = root.to_path_buf();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(4) {
+                handle(&server_root, &Clients::default(), stream.unwrap()).unwrap();
+            }
+        });
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let page = get("/pub/");
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(page.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(page.ends_with(&format!("hi{RELOAD_SCRIPT}</body>")));
+        assert!(get("/pub").contains("Location: /pub/\r\n"));
+        assert!(get("/x.wasm?v=1").contains("Content-Type: application/wasm\r\n"));
+        let missing = get("/nope.html");
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(missing.contains("missing"));
+    }
+}
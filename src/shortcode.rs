@@ -0,0 +1,304 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Shortcodes: `{{name}}` and `{{name: key="value", ...}}` markers in markdown sources.
+//!
+//! Every shortcode implements [`Shortcode`] and is registered by name in a [`Registry`].
+//! Sources are expanded before their front matter is split off, so pages, entries and
+//! PDF exports all see the same output and errors point at the line of the source file.
+//! Markers inside code spans and code blocks are left alone, so a post can show the
+//! syntax itself, and so are markers with an unregistered name.
+
+use crate::config::Config;
+use crate::{include, work_period};
+use chrono::NaiveDate;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// `{{name}}` or `{{name: args}}`; quoted argument values may contain `}` and `\"`
+static MARKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*(?::((?:[^"}]|"(?:[^"\\]|\\.)*")*))?\}\}"#)
+        .expect("Invalid regex")
+});
+
+/// One `key="value"` argument, optionally followed by a comma
+static ARG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*([A-Za-z_][A-Za-z0-9_]*)\s*=\s*"((?:[^"\\]|\\.)*)"\s*,?"#)
+        .expect("Invalid regex")
+});
+
+/// A named marker expanded to markdown
+pub trait Shortcode: Send + Sync {
+    /// Name used in markers
+    fn name(&self) -> &'static str;
+
+    /// Names of the arguments the shortcode accepts; any other argument is an error
+    fn params(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Markdown replacing one marker of `doc`
+    fn expand(&self, args: &Args, doc: &Document) -> Result<String, anyhow::Error>;
+}
+
+/// Arguments of a marker
+#[derive(Debug, Default)]
+pub struct Args {
+    values: BTreeMap<String, String>,
+}
+
+impl Args {
+    /// Parse `key="value", ...`; values may escape `"` and `\` with a backslash
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut values = BTreeMap::new();
+        let mut rest = text;
+        while !rest.trim().is_empty() {
+            let caps = ARG
+                .captures(rest)
+                .ok_or_else(|| anyhow::anyhow!("malformed arguments '{}'", rest.trim()))?;
+            let value = caps[2].replace("\\\"", "\"").replace("\\\\", "\\");
+            if values.insert(caps[1].to_string(), value).is_some() {
+                return Err(anyhow::anyhow!("duplicate argument `{}`", &caps[1]));
+            }
+            rest = &rest[caps[0].len()..];
+        }
+        Ok(Self { values })
+    }
+
+    /// Required argument `key`, parsed as `T`
+    pub fn get<T>(&self, key: &str) -> Result<T, anyhow::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get_opt(key)?
+            .ok_or_else(|| anyhow::anyhow!("missing argument `{key}`"))
+    }
+
+    /// Optional argument `key`, parsed as `T`
+    pub fn get_opt<T>(&self, key: &str) -> Result<Option<T>, anyhow::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.values
+            .get(key)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("invalid `{key}` '{value}': {e}"))
+            })
+            .transpose()
+    }
+}
+
+/// A marker found in a source
+#[derive(Debug)]
+pub struct Marker<'a> {
+    pub name: &'a str,
+    /// Unparsed arguments, see `Args::parse`
+    pub args: &'a str,
+    /// 1-based line of the marker's start
+    pub line: usize,
+}
+
+/// Every marker of `source` outside code, registered or not, in order
+pub fn markers(source: &str) -> impl Iterator<Item = (Range<usize>, Marker<'_>)> {
+    let code = code_ranges(source);
+    MARKER
+        .captures_iter(source)
+        .filter(move |caps| {
+            let start = caps.get(0).expect("group 0 always matches").start();
+            !code.iter().any(|range| range.contains(&start))
+        })
+        .map(move |caps| {
+            let whole = caps.get(0).expect("group 0 always matches");
+            let marker = Marker {
+                name: caps.get(1).map_or("", |m| m.as_str()),
+                args: caps.get(2).map_or("", |m| m.as_str()),
+                line: source[..whole.start()].matches('\n').count() + 1,
+            };
+            (whole.range(), marker)
+        })
+}
+
+/// Byte ranges of the code spans and code blocks of markdown `source`
+fn code_ranges(source: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(source, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The source being expanded
+pub struct Document<'a> {
+    pub path: &'a Path,
+    pub source: &'a str,
+}
+
+impl Document<'_> {
+    /// Markers named `name` in this document
+    pub fn markers<'s>(&'s self, name: &'s str) -> impl Iterator<Item = Marker<'s>> + 's {
+        markers(self.source)
+            .map(|(_, marker)| marker)
+            .filter(move |marker| marker.name == name)
+    }
+}
+
+/// Shortcodes by name
+pub struct Registry {
+    shortcodes: BTreeMap<&'static str, Box<dyn Shortcode>>,
+}
+
+impl Registry {
+    /// Registry of the built-in shortcodes; `today` is the build date
+    pub fn new(config: &Config, today: NaiveDate) -> Self {
+        let mut registry = Self {
+            shortcodes: BTreeMap::new(),
+        };
+        registry.register(work_period::WorkPeriod::new(today));
+        registry.register(work_period::TotalWorkPeriod::new(config, today));
+        registry.register(include::Include::new(config));
+        registry
+    }
+
+    /// Add `shortcode`, replacing any registered under the same name
+    pub fn register(&mut self, shortcode: impl Shortcode + 'static) {
+        self.shortcodes
+            .insert(shortcode.name(), Box::new(shortcode));
+    }
+
+    /// Expand every registered marker of `source`, read from `path`.
+    /// All failing markers are reported together as `path:line: name: error`.
+    pub fn expand(&self, path: &Path, source: &str) -> Result<String, anyhow::Error> {
+        let doc = Document { path, source };
+        let mut expanded = String::with_capacity(source.len());
+        let mut errors = Vec::new();
+        let mut pos = 0;
+
+        for (range, marker) in markers(source) {
+            let Some(shortcode) = self.shortcodes.get(marker.name) else {
+                continue;
+            };
+            match Self::expand_marker(shortcode.as_ref(), &marker, &doc) {
+                Ok(text) => {
+                    expanded.push_str(&source[pos..range.start]);
+                    expanded.push_str(&text);
+                    pos = range.end;
+                }
+                Err(e) => errors.push(format!(
+                    "{}:{}: {}: {e:#}",
+                    path.display(),
+                    marker.line,
+                    marker.name
+                )),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow::anyhow!(errors.join("\n")));
+        }
+        expanded.push_str(&source[pos..]);
+        Ok(expanded)
+    }
+
+    fn expand_marker(
+        shortcode: &dyn Shortcode,
+        marker: &Marker,
+        doc: &Document,
+    ) -> Result<String, anyhow::Error> {
+        let args = Args::parse(marker.args)?;
+        if let Some(unknown) = args
+            .values
+            .keys()
+            .find(|key| !shortcode.params().contains(&key.as_str()))
+        {
+            return Err(anyhow::anyhow!("unknown argument `{unknown}`"));
+        }
+        shortcode.expand(&args, doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl Shortcode for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn params(&self) -> &'static [&'static str] {
+            &["text", "times"]
+        }
+
+        fn expand(&self, args: &Args, _doc: &Document) -> Result<String, anyhow::Error> {
+            let text: String = args.get("text")?;
+            let times: usize = args.get_opt("times")?.unwrap_or(1);
+            Ok(text.repeat(times))
+        }
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry {
+            shortcodes: BTreeMap::new(),
+        };
+        registry.register(Echo);
+        registry
+    }
+
+    #[test]
+    fn test_args_parse() {
+        let args = Args::parse(r#" a="1", b = "x \"}}\" y" c="""#).unwrap();
+        assert_eq!(args.get::<u32>("a").unwrap(), 1);
+        assert_eq!(args.get::<String>("b").unwrap(), "x \"}}\" y");
+        assert_eq!(args.get::<String>("c").unwrap(), "");
+        assert!(args.get_opt::<String>("d").unwrap().is_none());
+        assert!(args.get::<u32>("b").is_err());
+
+        assert!(Args::parse("a=1").is_err());
+        assert!(Args::parse(r#"a="1", a="2""#).is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let source =
+            "x {{echo: text=\"ab\", times=\"2\"}} {{ echo : text=\"}}\" }}\n{{other}} {{ x }}";
+        let expanded = registry().expand(Path::new("page.md"), source).unwrap();
+        assert_eq!(expanded, "x abab }}\n{{other}} {{ x }}");
+    }
+
+    #[test]
+    fn test_expand_skips_code() {
+        let source = "`{{echo: text=\"a\"}}` {{echo: text=\"b\"}}\n\n\
+                      ```markdown\n{{echo: text=\"c\"}}\n```\n\n    {{echo}}\n";
+        let expanded = registry().expand(Path::new("page.md"), source).unwrap();
+        assert_eq!(
+            expanded,
+            "`{{echo: text=\"a\"}}` b\n\n```markdown\n{{echo: text=\"c\"}}\n```\n\n    {{echo}}\n"
+        );
+    }
+
+    #[test]
+    fn test_expand_reports_file_and_line() {
+        let source = "{{echo: text=\"a\"}}\n\n{{echo}}\n{{echo: text=\"a\", size=\"1\"}}\n";
+        let err = registry()
+            .expand(Path::new("in/page.md"), source)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "in/page.md:3: echo: missing argument `text`\n\
+             in/page.md:4: echo: unknown argument `size`"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! `sitemap.xml` and `robots.txt`.
+//!
+//! The sitemap lists public pages and PDFs only; shadow pages live under
+//! `dirs.private`, which `robots.txt` disallows and which are marked `noindex`.
+
+use crate::cache::{BuildCache, CacheKey};
+use crate::config::Config;
+use crate::front_matter::Page;
+use crate::rend::{escape_html, normalize, site_path};
+use chrono::{DateTime, FixedOffset};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Sitemap file written to the site root
+pub const SITEMAP: &str = "sitemap.xml";
+/// Crawler rules written to the site root
+pub const ROBOTS: &str = "robots.txt";
+
+/// A page or document listed in the sitemap
+pub struct Url {
+    /// Output file relative to the site root, e.g. `pub/entries/3.html`
+    pub output: PathBuf,
+    pub lastmod: Option<DateTime<FixedOffset>>,
+}
+
+/// Last modification date of a source file: front matter `updated`/`date` when it
+/// has them, otherwise its last commit
+pub fn source_lastmod(source: &Path, commits: &LastCommits) -> DateTime<FixedOffset> {
+    let front_matter = fs::read_to_string(source)
+        .ok()
+        .and_then(|content| Page::parse(&content).ok())
+        .and_then(|page| page.meta.updated.or(page.meta.date));
+
+    front_matter.unwrap_or_else(|| commits.get(source))
+}
+
+/// Time of the last commit that touched each file of the working directory, read
+/// with a single `git log` per build.
+///
+/// Unlike the files' modification times this is the same in every checkout of a
+/// commit, as long as the checkout has the history (not a shallow clone).
+pub struct LastCommits {
+    /// Keyed by path relative to the working directory
+    times: HashMap<PathBuf, DateTime<FixedOffset>>,
+    as_of: DateTime<FixedOffset>,
+}
+
+impl LastCommits {
+    /// Read the history; outside a git checkout every file falls back to `as_of`
+    pub fn load(as_of: DateTime<FixedOffset>) -> Self {
+        let log = Command::new("git")
+            .args(["-c", "core.quotePath=false", "log", "--relative"])
+            .args(["--name-only", "--format=%x00%cI"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .unwrap_or_default();
+
+        // Newest commit first, so the first time a file shows up is its last commit
+        let mut times = HashMap::new();
+        let mut committed = None;
+        for line in log.lines() {
+            if let Some(date) = line.strip_prefix('\0') {
+                committed = DateTime::parse_from_rfc3339(date).ok();
+            } else if let Some(committed) = committed.filter(|_| !line.is_empty()) {
+                times.entry(PathBuf::from(line)).or_insert(committed);
+            }
+        }
+        Self { times, as_of }
+    }
+
+    /// Time of the last commit that touched `path`, clamped to the build's `as_of`
+    /// time; `as_of` for files git doesn't know about
+    pub fn get(&self, path: &Path) -> DateTime<FixedOffset> {
+        let relative = || {
+            let cwd = normalize(&std::env::current_dir().ok()?);
+            let path = normalize(&std::path::absolute(path).ok()?);
+            path.strip_prefix(cwd).ok().map(Path::to_path_buf)
+        };
+        relative()
+            .and_then(|path| self.times.get(&path))
+            .map_or(self.as_of, |committed| (*committed).min(self.as_of))
+    }
+}
+
+/// Write `sitemap.xml` and `robots.txt` to the site root
+pub fn generate(
+    config: &Config,
+    urls: &[Url],
+    cache: &mut BuildCache,
+    generator: &str,
+) -> Result<(), anyhow::Error> {
+    let outputs = [(SITEMAP, sitemap(config, urls)), (ROBOTS, robots(config))];
+
+    for (file, content) in outputs {
+        let path = Path::new(file);
+        let key = CacheKey::new().with(generator).with(&content).finish();
+        if cache.write(path, key, content)? {
+            eprintln!("Generated: {file}");
+        }
+    }
+
+    Ok(())
+}
+
+fn sitemap(config: &Config, urls: &[Url]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+
+    for url in urls {
+        let _ = writeln!(xml, "  <url>");
+        let _ = writeln!(
+            xml,
+            "    <loc>{}</loc>",
+            escape_html(&config.absolute_url(&site_path(&url.output)))
+        );
+        if let Some(lastmod) = url.lastmod {
+            let _ = writeln!(xml, "    <lastmod>{}</lastmod>", lastmod.format("%Y-%m-%d"));
+        }
+        let _ = writeln!(xml, "  </url>");
+    }
+
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn robots(config: &Config) -> String {
+    format!(
+        "User-agent: *\nDisallow: /{}/\n\nSitemap: {}\n",
+        config.dirs.private.trim_matches('/'),
+        config.absolute_url(&format!("/{SITEMAP}"))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter;
+
+    #[test]
+    fn test_sitemap_lists_urls_with_lastmod() {
+        let config = Config::default();
+        let urls = [
+            Url {
+                output: PathBuf::from("index.html"),
+                lastmod: None,
+            },
+            Url {
+                output: PathBuf::from("pub/entries/3.html"),
+                lastmod: front_matter::parse_date("2024-05-24T13:05:00+02:00").ok(),
+            },
+        ];
+
+        let xml = sitemap(&config, &urls);
+        assert!(xml.contains("<loc>https://enkron.github.io/</loc>"));
+        assert!(xml.contains("<loc>https://enkron.github.io/pub/entries/3.html</loc>"));
+        assert!(xml.contains("<lastmod>2024-05-24</lastmod>"));
+        assert_eq!(xml.matches("<lastmod>").count(), 1);
+    }
+
+    #[test]
+    fn test_last_commits_fall_back_to_as_of() {
+        let as_of = front_matter::parse_date("2024-05-24").unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let untracked = dir.path().join("page.md");
+        fs::write(&untracked, "text").unwrap();
+        assert_eq!(LastCommits::load(as_of).get(&untracked), as_of);
+
+        let now = chrono::Utc::now().fixed_offset();
+        let commits = LastCommits::load(now);
+        assert!(commits.get(Path::new("Cargo.toml")) <= now);
+        assert_eq!(
+            commits.get(Path::new("./src/../Cargo.toml")),
+            commits.get(Path::new("Cargo.toml"))
+        );
+    }
+
+    #[test]
+    fn test_robots_disallows_private_dir() {
+        let robots = robots(&Config::default());
+        assert!(robots.contains("Disallow: /priv/"));
+        assert!(robots.contains("Sitemap: https://enkron.github.io/sitemap.xml"));
+    }
+}
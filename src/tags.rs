@@ -0,0 +1,238 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Tag listing pages.
+//!
+//! Every tag used by a public entry gets `pub/tags/<slug>.html`, and `pub/tags/index.html`
+//! lists all tags. Shadow entries are never listed. Locked entries are listed by title
+//! only: their date and description would leak more than the stub page already does.
+
+use crate::cache::{BuildCache, CacheKey};
+use crate::config::Config;
+use crate::entries::Entry;
+use crate::front_matter;
+use crate::rend::{site_path, Layout, PageContext};
+use crate::template::{self, Value};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// URL-safe tag name: lowercase alphanumerics separated by single dashes
+pub fn slug(tag: &str) -> String {
+    tag.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// URL of the listing page for `tag`
+pub fn url(config: &Config, tag: &str) -> String {
+    format!("{}{}.html", config.tags_url(), slug(tag))
+}
+
+/// A tag and the listed entries carrying it
+pub struct Tag<'a> {
+    pub slug: String,
+    /// Spelling of the first entry using the tag
+    pub name: &'a str,
+    /// Entries in the order given to `collect` (newest first)
+    pub entries: Vec<&'a Entry>,
+}
+
+/// Group listed entries by tag, sorted by slug.
+/// Tags are matched by slug so "IPv6" and "ipv6" share a page.
+pub fn collect(entries: &[Entry]) -> Vec<Tag<'_>> {
+    let mut tags: BTreeMap<String, Tag> = BTreeMap::new();
+    for entry in entries.iter().filter(|entry| entry.is_listed()) {
+        for name in &entry.meta.tags {
+            let slug = slug(name);
+            if slug.is_empty() {
+                continue;
+            }
+            let tag = tags.entry(slug.clone()).or_insert_with(|| Tag {
+                slug,
+                name,
+                entries: Vec::new(),
+            });
+            if !tag.entries.iter().any(|e| e.number == entry.number) {
+                tag.entries.push(entry);
+            }
+        }
+    }
+    tags.into_values().collect()
+}
+
+/// Tag links shown under an entry (the `tags` variable of `entry.html`)
+pub fn values(config: &Config, tags: &[String]) -> Vec<Value> {
+    tags.iter()
+        .map(|tag| {
+            Value::from([
+                ("name", tag.as_str().into()),
+                ("url", url(config, tag).into()),
+            ])
+        })
+        .collect()
+}
+
+/// Generate tag listing pages and the tag index for all listed entries.
+/// `entries` are expected newest first, as returned by `entries::collect`.
+pub fn generate(
+    layout: &Layout,
+    entries: &[Entry],
+    cache: &mut BuildCache,
+    generator: &str,
+) -> Result<(), anyhow::Error> {
+    let config = layout.config();
+    let tags = collect(entries);
+    let dir = config.tags_output_dir();
+    let mut pages = Vec::new();
+    for tag in &tags {
+        let items = tag
+            .entries
+            .iter()
+            .map(|entry| list_item(config, entry))
+            .collect::<Vec<_>>();
+        pages.push((
+            dir.join(format!("{}.html", tag.slug)),
+            tag.name.to_string(),
+            format!("Entries tagged #{}", tag.name),
+            "tag.html",
+            template::context([("tag", tag.name.into()), ("entries", items.into())]),
+        ));
+    }
+
+    let index_items = tags
+        .iter()
+        .map(|tag| {
+            Value::from([
+                (
+                    "url",
+                    format!("{}{}.html", config.tags_url(), tag.slug).into(),
+                ),
+                ("name", tag.name.into()),
+                ("count", tag.entries.len().to_string().into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    pages.push((
+        dir.join("index.html"),
+        "tags".to_string(),
+        "All tags".to_string(),
+        "tags.html",
+        template::context([("tags", index_items.into())]),
+    ));
+
+    fs::create_dir_all(&dir)?;
+    for (path, title, description, template, vars) in pages {
+        let context = PageContext {
+            title: Some(title),
+            description: Some(description),
+            path: Some(site_path(&path)),
+            ..PageContext::default()
+        };
+        let key = CacheKey::new()
+            .with(generator)
+            .with(layout.fingerprint())
+            .with(format!("{context:?}"))
+            .with(format!("{vars:?}"))
+            .finish();
+        let html = layout.render(template, &context, vars)?;
+        if cache.write(&path, key, html)? {
+            eprintln!("Generated: {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// One item of a tag listing
+fn list_item(config: &Config, entry: &Entry) -> Value {
+    if entry.locked {
+        return Value::from([
+            ("locked", true.into()),
+            ("url", entry.url(config).into()),
+            ("title", entry.title().into()),
+        ]);
+    }
+
+    Value::from([
+        ("locked", false.into()),
+        ("url", entry.url(config).into()),
+        ("title", entry.title().into()),
+        (
+            "date",
+            entry.meta.date.map(front_matter::format_date).into(),
+        ),
+        ("description", entry.meta.description.as_deref().into()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::Assets;
+    use crate::front_matter::FrontMatter;
+
+    fn entry(number: u32, shadow: bool, locked: bool, tags: &[&str]) -> Entry {
+        Entry {
+            number,
+            shadow,
+            locked,
+            scheduled: false,
+            meta: FrontMatter {
+                title: Some(format!("title {number}")),
+                date: front_matter::parse_date("2024-05-24").ok(),
+                description: Some(format!("about {number}")),
+                tags: tags.iter().map(ToString::to_string).collect(),
+                ..FrontMatter::default()
+            },
+            markdown: None,
+        }
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("IPv6"), "ipv6");
+        assert_eq!(slug("home lab / networking"), "home-lab-networking");
+        assert_eq!(slug("--"), "");
+    }
+
+    #[test]
+    fn test_locked_entry_listed_by_title_only() {
+        let config = Config::default();
+        assert_eq!(
+            list_item(&config, &entry(4, false, true, &[])),
+            Value::from([
+                ("locked", true.into()),
+                ("url", "/pub/entries/4.html".into()),
+                ("title", "title 4".into()),
+            ])
+        );
+
+        let Value::Map(item) = list_item(&config, &entry(3, false, false, &[])) else {
+            panic!("list item is not a map");
+        };
+        assert_eq!(item["date"], Value::from("24.V.2024"));
+        assert_eq!(item["description"], Value::from("about 3"));
+    }
+
+    #[test]
+    fn test_generate_skips_shadow_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir = dir.path();
+        let config = Config::parse(&format!("[dirs]\npublic = \"{}\"", dir.display())).unwrap();
+        let entries = [
+            entry(2, false, false, &["Networking"]),
+            entry(1, true, false, &["networking", "secret"]),
+        ];
+
+        let mut cache = BuildCache::load("/nonexistent/.enkronio-cache", false);
+        let layout = Layout::load(&config, Assets::default()).unwrap();
+        generate(&layout, &entries, &mut cache, "test").unwrap();
+
+        let page = fs::read_to_string(dir.join("tags/networking.html")).unwrap();
+        assert!(page.contains("title 2"));
+        assert!(!page.contains("title 1"));
+        assert!(!dir.join("tags/secret.html").exists());
+        let index = fs::read_to_string(dir.join("tags/index.html")).unwrap();
+        assert!(index.contains("#Networking</a> (1)"));
+    }
+}
@@ -0,0 +1,634 @@
+#![warn(clippy::all, clippy::pedantic)]
+//! Minimal HTML template engine.
+//!
+//! Templates are read from `dirs.templates`; any template missing there falls back to
+//! the copy embedded in the binary, so an empty or absent directory renders the stock
+//! site. Supported syntax:
+//!
+//! ```text
+//! {{ page.title }}              escaped variable (dotted paths into maps)
+//! {{ content | safe }}          unescaped variable
+//! {% if page.description %}…{% else %}…{% endif %}   `not` negates
+//! {% for tag in tags %}{{ tag.name }}{% endfor %}
+//! {% include "partial.html" %}
+//! {# comment #}
+//! ```
+//!
+//! Referencing an undefined variable in `{{ }}` is an error (it's almost always a
+//! typo); in `{% if %}` it's simply false.
+
+use anyhow::{anyhow, bail, Context as _};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Built-in templates, used for every name not overridden in the templates directory
+const BUILTIN: &[(&str, &str)] = &[
+    ("base.html", include_str!("../templates/base.html")),
+    ("entry.html", include_str!("../templates/entry.html")),
+    (
+        "entry-nav.html",
+        include_str!("../templates/entry-nav.html"),
+    ),
+    ("locked.html", include_str!("../templates/locked.html")),
+    ("404.html", include_str!("../templates/404.html")),
+    ("index.html", include_str!("../templates/index.html")),
+    ("tag.html", include_str!("../templates/tag.html")),
+    ("tags.html", include_str!("../templates/tags.html")),
+];
+
+/// Includes nested deeper than this are assumed to be recursive
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A value visible to templates
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Bool(bool),
+    List(Vec<Value>),
+    Map(Context),
+}
+
+/// Variables available to a template
+pub type Context = BTreeMap<String, Value>;
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match self {
+            Self::Str(s) => !s.is_empty(),
+            Self::Bool(b) => *b,
+            Self::List(items) => !items.is_empty(),
+            Self::Map(map) => !map.is_empty(),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    /// Missing values render as an empty (falsy) string
+    fn from(value: Option<T>) -> Self {
+        value.map_or_else(|| Self::Str(String::new()), Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Self::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<const N: usize> From<[(&str, Value); N]> for Value {
+    fn from(fields: [(&str, Value); N]) -> Self {
+        Self::Map(context(fields))
+    }
+}
+
+/// Build a context from `(name, value)` pairs
+pub fn context<const N: usize>(fields: [(&str, Value); N]) -> Context {
+    fields
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var {
+        path: Vec<String>,
+        safe: bool,
+        line: usize,
+    },
+    If {
+        negate: bool,
+        path: Vec<String>,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        name: String,
+        path: Vec<String>,
+        body: Vec<Node>,
+        line: usize,
+    },
+    Include {
+        name: String,
+        line: usize,
+    },
+}
+
+/// Parsed templates, keyed by file name
+pub struct Templates {
+    templates: BTreeMap<String, Template>,
+}
+
+struct Template {
+    /// Where the template came from, for error messages
+    origin: String,
+    source: String,
+    nodes: Vec<Node>,
+}
+
+impl Templates {
+    /// Load every `.html` file in `dir` on top of the built-in templates.
+    /// A missing directory just means the built-ins are used.
+    pub fn load(dir: &str) -> Result<Self, anyhow::Error> {
+        let mut sources = BUILTIN
+            .iter()
+            .map(|(name, source)| {
+                (
+                    (*name).to_string(),
+                    (format!("<built-in {name}>"), (*source).to_string()),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        if Path::new(dir).is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("html") {
+                    continue;
+                }
+                let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let source = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read template {}", path.display()))?;
+                sources.insert(name.to_string(), (path.display().to_string(), source));
+            }
+        }
+
+        let mut templates = BTreeMap::new();
+        for (name, (origin, source)) in sources {
+            let nodes = parse(&source).map_err(|e| anyhow!("{origin}:{e}"))?;
+            templates.insert(
+                name,
+                Template {
+                    origin,
+                    source,
+                    nodes,
+                },
+            );
+        }
+        Ok(Self { templates })
+    }
+
+    /// Digest of all template sources, for build cache keys
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (name, template) in &self.templates {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(template.source.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Render template `name` with `context`
+    pub fn render(&self, name: &str, context: &Context) -> Result<String, anyhow::Error> {
+        let mut out = String::new();
+        self.render_into(&mut out, name, context, 0)?;
+        Ok(out)
+    }
+
+    fn render_into(
+        &self,
+        out: &mut String,
+        name: &str,
+        context: &Context,
+        depth: usize,
+    ) -> Result<(), anyhow::Error> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| anyhow!("Unknown template {name:?}"))?;
+        let mut scopes = vec![context.clone()];
+        self.render_nodes(out, template, &template.nodes, &mut scopes, depth)
+    }
+
+    fn render_nodes(
+        &self,
+        out: &mut String,
+        template: &Template,
+        nodes: &[Node],
+        scopes: &mut Vec<Context>,
+        depth: usize,
+    ) -> Result<(), anyhow::Error> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Var { path, safe, line } => {
+                    let value = lookup(scopes, path).ok_or_else(|| {
+                        anyhow!(
+                            "{}:{line}: undefined variable `{}`",
+                            template.origin,
+                            path.join(".")
+                        )
+                    })?;
+                    let text = match value {
+                        Value::Str(s) => s.clone(),
+                        Value::Bool(b) => b.to_string(),
+                        Value::List(_) | Value::Map(_) => bail!(
+                            "{}:{line}: `{}` is not a string",
+                            template.origin,
+                            path.join(".")
+                        ),
+                    };
+                    if *safe {
+                        out.push_str(&text);
+                    } else {
+                        out.push_str(&crate::rend::escape_html(&text));
+                    }
+                }
+                Node::If {
+                    negate,
+                    path,
+                    then,
+                    otherwise,
+                } => {
+                    let truthy = lookup(scopes, path).is_some_and(Value::is_truthy);
+                    let branch = if truthy == *negate { otherwise } else { then };
+                    self.render_nodes(out, template, branch, scopes, depth)?;
+                }
+                Node::For {
+                    name,
+                    path,
+                    body,
+                    line,
+                } => {
+                    let items = match lookup(scopes, path) {
+                        Some(Value::List(items)) => items.clone(),
+                        None => Vec::new(),
+                        Some(_) => bail!(
+                            "{}:{line}: `{}` is not a list",
+                            template.origin,
+                            path.join(".")
+                        ),
+                    };
+                    for item in items {
+                        scopes.push(Context::from([(name.clone(), item)]));
+                        let result = self.render_nodes(out, template, body, scopes, depth);
+                        scopes.pop();
+                        result?;
+                    }
+                }
+                Node::Include { name, line } => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        bail!("{}:{line}: includes nested too deeply", template.origin);
+                    }
+                    let context =
+                        scopes
+                            .iter()
+                            .flatten()
+                            .fold(Context::new(), |mut ctx, (k, v)| {
+                                ctx.insert(k.clone(), v.clone());
+                                ctx
+                            });
+                    self.render_into(out, name, &context, depth + 1)
+                        .with_context(|| format!("{}:{line}: in include", template.origin))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolve a dotted path, innermost scope first
+fn lookup<'a>(scopes: &'a [Context], path: &[String]) -> Option<&'a Value> {
+    let (first, rest) = path.split_first()?;
+    let mut value = scopes.iter().rev().find_map(|scope| scope.get(first))?;
+    for key in rest {
+        match value {
+            Value::Map(map) => value = map.get(key)?,
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Block tags that are still open while parsing
+enum Open {
+    If {
+        negate: bool,
+        path: Vec<String>,
+        then: Option<Vec<Node>>,
+        line: usize,
+    },
+    For {
+        name: String,
+        path: Vec<String>,
+        line: usize,
+    },
+}
+
+/// Parse template source into nodes. Errors are prefixed with the line number.
+fn parse(source: &str) -> Result<Vec<Node>, anyhow::Error> {
+    // Completed nodes for each open block, innermost last
+    let mut stack: Vec<(Option<Open>, Vec<Node>)> = vec![(None, Vec::new())];
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        let Some(start) = find_tag(rest) else {
+            push_node(&mut stack, Node::Text(rest.to_string()));
+            break;
+        };
+        let offset = source.len() - rest.len() + start;
+        let line = source[..offset].matches('\n').count() + 1;
+        if start > 0 {
+            push_node(&mut stack, Node::Text(rest[..start].to_string()));
+        }
+
+        let tag = &rest[start..start + 2];
+        let close = match tag {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let body_start = start + 2;
+        let Some(len) = rest[body_start..].find(close) else {
+            bail!("{line}: unclosed `{tag}`");
+        };
+        let inner = rest[body_start..body_start + len].trim();
+        rest = &rest[body_start + len + 2..];
+
+        match tag {
+            "{{" => {
+                let (expr, safe) = match inner.split_once('|') {
+                    Some((expr, "safe")) => (expr.trim(), true),
+                    Some((expr, filter)) if filter.trim() == "safe" => (expr.trim(), true),
+                    Some((_, filter)) => bail!("{line}: unknown filter `{}`", filter.trim()),
+                    None => (inner, false),
+                };
+                push_node(
+                    &mut stack,
+                    Node::Var {
+                        path: parse_path(expr, line)?,
+                        safe,
+                        line,
+                    },
+                );
+            }
+            "{%" => parse_statement(&mut stack, inner, line)?,
+            _ => {} // comment
+        }
+    }
+
+    let (open, nodes) = stack.pop().expect("root scope");
+    match open {
+        None => Ok(nodes),
+        Some(Open::If { line, .. }) => bail!("{line}: `if` without `endif`"),
+        Some(Open::For { line, .. }) => bail!("{line}: `for` without `endfor`"),
+    }
+}
+
+/// Offset of the next `{{`, `{%` or `{#`
+fn find_tag(text: &str) -> Option<usize> {
+    text.match_indices('{')
+        .map(|(i, _)| i)
+        .find(|&i| matches!(text.as_bytes().get(i + 1), Some(b'{' | b'%' | b'#')))
+}
+
+fn push_node(stack: &mut [(Option<Open>, Vec<Node>)], node: Node) {
+    stack.last_mut().expect("root scope").1.push(node);
+}
+
+fn close_if(stack: &mut Vec<(Option<Open>, Vec<Node>)>, line: usize) -> Result<(), anyhow::Error> {
+    let (open, nodes) = stack.pop().expect("root scope");
+    let Some(Open::If {
+        negate, path, then, ..
+    }) = open
+    else {
+        bail!("{line}: `endif` without `if`");
+    };
+    let (then, otherwise) = match then {
+        Some(then) => (then, nodes),
+        None => (nodes, Vec::new()),
+    };
+    push_node(
+        stack,
+        Node::If {
+            negate,
+            path,
+            then,
+            otherwise,
+        },
+    );
+    Ok(())
+}
+
+fn parse_statement(
+    stack: &mut Vec<(Option<Open>, Vec<Node>)>,
+    statement: &str,
+    line: usize,
+) -> Result<(), anyhow::Error> {
+    let mut words = statement.split_whitespace();
+    match words.next() {
+        Some("if") => {
+            let (negate, expr) = match words.next() {
+                Some("not") => (true, words.next()),
+                expr => (false, expr),
+            };
+            let expr = expr.ok_or_else(|| anyhow!("{line}: `if` needs a condition"))?;
+            ensure_end(words, line)?;
+            stack.push((
+                Some(Open::If {
+                    negate,
+                    path: parse_path(expr, line)?,
+                    then: None,
+                    line,
+                }),
+                Vec::new(),
+            ));
+        }
+        Some("else") => {
+            ensure_end(words, line)?;
+            match stack.last_mut() {
+                Some((
+                    Some(Open::If {
+                        then: then @ None, ..
+                    }),
+                    nodes,
+                )) => {
+                    *then = Some(std::mem::take(nodes));
+                }
+                _ => bail!("{line}: `else` outside `if`"),
+            }
+        }
+        Some("endif") => {
+            ensure_end(words, line)?;
+            close_if(stack, line)?;
+        }
+        Some("for") => {
+            let (Some(name), Some("in"), Some(expr)) = (words.next(), words.next(), words.next())
+            else {
+                bail!("{line}: expected `for <name> in <list>`");
+            };
+            ensure_end(words, line)?;
+            stack.push((
+                Some(Open::For {
+                    name: name.to_string(),
+                    path: parse_path(expr, line)?,
+                    line,
+                }),
+                Vec::new(),
+            ));
+        }
+        Some("endfor") => {
+            ensure_end(words, line)?;
+            let (open, body) = stack.pop().expect("root scope");
+            let Some(Open::For { name, path, line }) = open else {
+                bail!("{line}: `endfor` without `for`");
+            };
+            push_node(
+                stack,
+                Node::For {
+                    name,
+                    path,
+                    body,
+                    line,
+                },
+            );
+        }
+        Some("include") => {
+            let name = statement["include".len()..].trim();
+            let name = name
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+                .ok_or_else(|| anyhow!("{line}: expected `include \"name.html\"`"))?;
+            push_node(
+                stack,
+                Node::Include {
+                    name: name.to_string(),
+                    line,
+                },
+            );
+        }
+        Some(other) => bail!("{line}: unknown tag `{other}`"),
+        None => bail!("{line}: empty tag"),
+    }
+
+    Ok(())
+}
+
+fn ensure_end<'a>(
+    mut words: impl Iterator<Item = &'a str>,
+    line: usize,
+) -> Result<(), anyhow::Error> {
+    match words.next() {
+        Some(word) => bail!("{line}: unexpected `{word}`"),
+        None => Ok(()),
+    }
+}
+
+fn parse_path(expr: &str, line: usize) -> Result<Vec<String>, anyhow::Error> {
+    let path = expr.split('.').map(str::to_string).collect::<Vec<_>>();
+    let valid = |part: &String| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !path.iter().all(valid) {
+        bail!("{line}: invalid variable `{expr}`");
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: &Context) -> Result<String, anyhow::Error> {
+        let templates = Templates {
+            templates: BTreeMap::from([(
+                "test.html".to_string(),
+                Template {
+                    origin: "test.html".to_string(),
+                    source: source.to_string(),
+                    nodes: parse(source)?,
+                },
+            )]),
+        };
+        templates.render("test.html", context)
+    }
+
+    #[test]
+    fn test_variables_are_escaped_unless_safe() {
+        let ctx = context([
+            ("title", "<b>".into()),
+            ("page", [("body", Value::from("<p>x</p>"))].into()),
+        ]);
+        assert_eq!(
+            render("{{ title }} {{ page.body | safe }}", &ctx).unwrap(),
+            "&lt;b&gt; <p>x</p>"
+        );
+    }
+
+    #[test]
+    fn test_conditionals_and_loops() {
+        let ctx = context([
+            ("empty", "".into()),
+            (
+                "tags",
+                vec![
+                    Value::from([("name", "a".into())]),
+                    Value::from([("name", "b".into())]),
+                ]
+                .into(),
+            ),
+        ]);
+        let out = render(
+            "{% if empty %}yes{% else %}no{% endif %}|{% if not missing %}none{% endif %}|\
+             {% for tag in tags %}[{{ tag.name }}]{% endfor %}{# ignored #}",
+            &ctx,
+        )
+        .unwrap();
+        assert_eq!(out, "no|none|[a][b]");
+    }
+
+    #[test]
+    fn test_errors_carry_line_numbers() {
+        let err = render("line one\n{{ missing }}", &Context::new()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("test.html:2: undefined variable `missing`"));
+
+        let err = parse("a\n{% if x %}\nb").unwrap_err();
+        assert!(err.to_string().starts_with("2: `if` without `endif`"));
+        assert!(parse("{% endfor %}").is_err());
+        assert!(parse("{{ unclosed").is_err());
+    }
+
+    #[test]
+    fn test_builtin_templates_parse_and_include() {
+        let templates = Templates::load("/nonexistent/templates").unwrap();
+        assert!(templates.templates.contains_key("base.html"));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir = dir.path();
+        fs::write(dir.join("part.html"), "<i>{{ name }}</i>").unwrap();
+        fs::write(dir.join("outer.html"), "{% include \"part.html\" %}!").unwrap();
+        fs::write(dir.join("loop.html"), "{% include \"loop.html\" %}").unwrap();
+
+        let templates = Templates::load(dir.to_str().unwrap()).unwrap();
+        let ctx = context([("name", "x".into())]);
+        assert_eq!(templates.render("outer.html", &ctx).unwrap(), "<i>x</i>!");
+        assert!(templates.render("loop.html", &ctx).is_err());
+    }
+}
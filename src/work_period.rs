@@ -1,103 +1,144 @@
 #![warn(clippy::all, clippy::pedantic)]
+//! `work_period` and `total_work_period` shortcodes.
+//!
+//! Syntax:
+//! - `{{work_period: start="YYYY-MM", end="present"}}` → "2 years, 10 months"
+//! - `{{work_period: start="YYYY-MM", end="YYYY-MM"}}` → "3 years, 5 months"
+//! - `{{total_work_period}}` → sum of all `work_period` markers (reads from cv.md if needed)
+//!
+//! Example:
+//! ```text
+//! {{work_period: start="2022-12", end="present"}}
+//! {{work_period: start="2018-07", end="2021-11"}}
+//! Total: {{total_work_period}}
+//! ```
+
+use crate::config::Config;
+use crate::shortcode::{Args, Document, Shortcode};
 use chrono::{Datelike, NaiveDate};
-use regex::Regex;
 use std::convert::TryFrom;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-/// Processes work period markers in markdown and replaces them with calculated durations.
-///
-/// Syntax:
-/// - `{{work_period: start="YYYY-MM", end="present"}}` → "2 years, 10 months"
-/// - `{{work_period: start="YYYY-MM", end="YYYY-MM"}}` → "3 years, 5 months"
-/// - `{{total_work_period}}` → sum of all `work_period` markers (reads from cv.md if needed)
-///
-/// Example:
-/// ```
-/// {{work_period: start="2022-12", end="present"}}
-/// {{work_period: start="2018-07", end="2021-11"}}
-/// Total: {{total_work_period}}
-/// ```
-pub fn process(markdown: &str) -> String {
-    let mut durations = Vec::new();
-
-    // First pass: replace individual work_period markers and collect durations
-    let re = Regex::new(r#"\{\{work_period:\s*start="([^"]+)",?\s*end="([^"]+)"\}\}"#)
-        .expect("Invalid regex");
-
-    let after_work_periods = re
-        .replace_all(markdown, |caps: &regex::Captures| {
-            let start = &caps[1];
-            let end = &caps[2];
-
-            match calculate_duration_parts(start, end) {
-                Ok((years, months)) => {
-                    durations.push((years, months));
-                    format_duration(years, months)
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Warning: Failed to parse work period (start={start}, end={end}): {e}"
-                    );
-                    format!("{{{{work_period: start=\"{start}\", end=\"{end}\"}}}}")
-                }
-            }
-        })
-        .to_string();
-
-    // Second pass: replace total_work_period with sum (years only, rounded)
-    // If no work periods found in current file but total_work_period exists, read from cv.md
-    if durations.is_empty() && after_work_periods.contains("{{total_work_period}}") {
-        durations = extract_durations_from_cv();
-    }
-
-    let total = sum_durations(&durations);
-    after_work_periods.replace(
-        "{{total_work_period}}",
-        &format_duration_years_only(total.0, total.1),
-    )
+/// `{{work_period: start="YYYY-MM", end="YYYY-MM"|"present"}}`
+pub struct WorkPeriod {
+    /// Date `present` stands for: the build date, not the wall clock, so a rebuild
+    /// with the same `SOURCE_DATE_EPOCH` renders the same durations
+    today: NaiveDate,
 }
 
-/// Extracts work period durations from cv.md file.
-fn extract_durations_from_cv() -> Vec<(i32, i32)> {
-    let cv_path = "in/cv.md";
-    match fs::read_to_string(cv_path) {
-        Ok(cv_content) => {
-            let mut durations = Vec::new();
-            let re = Regex::new(r#"\{\{work_period:\s*start="([^"]+)",?\s*end="([^"]+)"\}\}"#)
-                .expect("Invalid regex");
-
-            for caps in re.captures_iter(&cv_content) {
-                let start = &caps[1];
-                let end = &caps[2];
-
-                if let Ok((years, months)) = calculate_duration_parts(start, end) {
-                    durations.push((years, months));
-                }
-            }
-            durations
+impl WorkPeriod {
+    pub fn new(today: NaiveDate) -> Self {
+        Self { today }
+    }
+
+    fn duration(&self, args: &Args) -> Result<(i32, i32), anyhow::Error> {
+        let start: YearMonth = args.get("start")?;
+        let end: PeriodEnd = args.get("end")?;
+        let end = match end {
+            PeriodEnd::Present => self.today,
+            PeriodEnd::Date(YearMonth(date)) => date,
+        };
+        if end < start.0 {
+            return Err(anyhow::anyhow!("`end` is before `start`"));
         }
-        Err(e) => {
-            eprintln!("Warning: Failed to read cv.md for total_work_period: {e}");
-            Vec::new()
+        Ok(months_between(start.0, end))
+    }
+}
+
+impl Shortcode for WorkPeriod {
+    fn name(&self) -> &'static str {
+        "work_period"
+    }
+
+    fn params(&self) -> &'static [&'static str] {
+        &["start", "end"]
+    }
+
+    fn expand(&self, args: &Args, _doc: &Document) -> Result<String, anyhow::Error> {
+        let (years, months) = self.duration(args)?;
+        Ok(format_duration(years, months))
+    }
+}
+
+/// `{{total_work_period}}`: sum of the document's `work_period` markers, or of the
+/// CV's when the document has none
+pub struct TotalWorkPeriod {
+    cv: PathBuf,
+    period: WorkPeriod,
+}
+
+impl TotalWorkPeriod {
+    pub fn new(config: &Config, today: NaiveDate) -> Self {
+        Self {
+            cv: Path::new(&config.dirs.content).join("cv.md"),
+            period: WorkPeriod::new(today),
+        }
+    }
+
+    /// Durations of every `work_period` marker in `source`
+    fn durations(&self, path: &Path, source: &str) -> Result<Vec<(i32, i32)>, anyhow::Error> {
+        let doc = Document { path, source };
+        doc.markers(self.period.name())
+            .map(|marker| {
+                Args::parse(marker.args)
+                    .and_then(|args| self.period.duration(&args))
+                    .map_err(|e| {
+                        anyhow::anyhow!("{}:{}: work_period: {e:#}", path.display(), marker.line)
+                    })
+            })
+            .collect()
+    }
+}
+
+impl Shortcode for TotalWorkPeriod {
+    fn name(&self) -> &'static str {
+        "total_work_period"
+    }
+
+    fn expand(&self, _args: &Args, doc: &Document) -> Result<String, anyhow::Error> {
+        let mut durations = self.durations(doc.path, doc.source)?;
+        if durations.is_empty() {
+            let cv = fs::read_to_string(&self.cv)
+                .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", self.cv.display()))?;
+            durations = self.durations(&self.cv, &cv)?;
         }
+
+        let (years, months) = sum_durations(&durations);
+        Ok(format_duration_years_only(years, months))
+    }
+}
+
+/// A `YYYY-MM` month, as the first day of the month
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct YearMonth(NaiveDate);
+
+impl FromStr for YearMonth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_year_month(s).map(Self)
     }
 }
 
-/// Calculates duration between two dates and returns (years, months).
-///
-/// If `end` is "present", uses current date.
-fn calculate_duration_parts(
-    start: &str,
-    end: &str,
-) -> Result<(i32, i32), Box<dyn std::error::Error>> {
-    let start_date = parse_year_month(start)?;
-    let end_date = if end.to_lowercase() == "present" {
-        chrono::Local::now().date_naive()
-    } else {
-        parse_year_month(end)?
-    };
-
-    Ok(months_between(start_date, end_date))
+/// End of a work period: a month or `present` (the build date)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PeriodEnd {
+    Present,
+    Date(YearMonth),
+}
+
+impl FromStr for PeriodEnd {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("present") {
+            Ok(Self::Present)
+        } else {
+            s.parse().map(Self::Date)
+        }
+    }
 }
 
 /// Sums multiple durations (years, months) into a single total duration.
@@ -109,17 +150,13 @@ fn sum_durations(durations: &[(i32, i32)]) -> (i32, i32) {
 }
 
 /// Parses "YYYY-MM" string into `NaiveDate` (first day of the month).
-fn parse_year_month(date_str: &str) -> Result<NaiveDate, Box<dyn std::error::Error>> {
-    let parts: Vec<&str> = date_str.split('-').collect();
-    if parts.len() != 2 {
-        return Err(format!("Invalid date format: {date_str}. Expected YYYY-MM").into());
-    }
-
-    let year: i32 = parts[0].parse()?;
-    let month: u32 = parts[1].parse()?;
+fn parse_year_month(date_str: &str) -> Result<NaiveDate, String> {
+    let invalid = || format!("expected YYYY-MM, got '{date_str}'");
+    let (year, month) = date_str.split_once('-').ok_or_else(invalid)?;
+    let year: i32 = year.parse().map_err(|_| invalid())?;
+    let month: u32 = month.parse().map_err(|_| invalid())?;
 
-    NaiveDate::from_ymd_opt(year, month, 1)
-        .ok_or_else(|| format!("Invalid date: {date_str}").into())
+    NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(|| format!("invalid month '{date_str}'"))
 }
 
 /// Calculates years and months between two dates.
@@ -175,6 +212,12 @@ fn format_duration_years_only(years: i32, months: i32) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shortcode::Registry;
+
+    fn process(input: &str) -> Result<String, anyhow::Error> {
+        let today = NaiveDate::from_ymd_opt(2025, 10, 16).unwrap();
+        Registry::new(&Config::default(), today).expand(Path::new("in/cv.md"), input)
+    }
 
     #[test]
     fn test_parse_year_month() {
@@ -207,10 +250,27 @@ mod tests {
     #[test]
     fn test_process() {
         let input = r#"Started {{work_period: start="2022-12", end="2023-03"}} ago"#;
-        let output = process(input);
+        let output = process(input).unwrap();
         assert_eq!(output, "Started 3 months ago");
     }
 
+    #[test]
+    fn test_present_is_the_build_date() {
+        let input = r#"{{work_period: start="2022-12", end="present"}}"#;
+        assert_eq!(process(input).unwrap(), "2 years, 10 months");
+    }
+
+    #[test]
+    fn test_process_reports_invalid_periods() {
+        let input = "a\n{{work_period: start=\"2022-13\", end=\"present\"}}\n{{work_period: start=\"2022-12\"}}";
+        let err = process(input).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "in/cv.md:2: work_period: invalid `start` '2022-13': invalid month '2022-13'\n\
+             in/cv.md:3: work_period: missing argument `end`"
+        );
+    }
+
     #[test]
     fn test_sum_durations() {
         let durations = vec![(2, 10), (3, 5), (2, 2)];
@@ -237,7 +297,7 @@ Exp 1: {{work_period: start="2022-12", end="2023-03"}}
 Exp 2: {{work_period: start="2020-01", end="2020-07"}}
 Total: {{total_work_period}}
 "#;
-        let output = process(input);
+        let output = process(input).unwrap();
         // 3 months + 6 months = 9 months, rounds up to 1 year
         assert!(output.contains("Total: 1 year"));
     }
@@ -1,7 +1,7 @@
 /// Integration tests for CLI functionality
 ///
 /// Tests the complete workflow of adding blog entries via CLI,
-/// including file creation, junkyard updates, and entry numbering.
+/// including file creation, the generated junkyard, and entry numbering.
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
@@ -18,7 +18,7 @@ fn setup_test_env() -> TempDir {
 
     // Create initial junkyard.md
     let junkyard_path = temp_dir.path().join("in/junkyard.md");
-    let junkyard_content = "# index\n\n## recent posts\n\n";
+    let junkyard_content = "# index\n\n## recent posts\n\n<!-- entries -->\n";
     fs::write(&junkyard_path, junkyard_content).expect("Failed to create junkyard.md");
 
     temp_dir
@@ -51,6 +51,7 @@ fn test_cli_add_help() {
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("Add a new blog entry"));
     assert!(stdout.contains("TITLE"));
+    assert!(stdout.contains("--draft"));
 }
 
 /// Tests site build without errors.
@@ -96,6 +97,11 @@ fn test_generated_html_structure() {
     let cv_content = fs::read_to_string("cv.html").expect("Failed to read cv.html");
     assert!(cv_content.contains("<!DOCTYPE html>"));
     assert!(cv_content.contains("<html lang=\"en-US\">"));
+
+    // The junkyard lists entries in place of its placeholder
+    let junkyard = fs::read_to_string("pub/junkyard.html").expect("Failed to read junkyard.html");
+    assert!(junkyard.contains("<a href=\"/pub/entries/1.html\">init</a>"));
+    assert!(!junkyard.contains("<!-- entries -->"));
 }
 
 /// Tests that PDF files are generated.
@@ -169,8 +175,8 @@ fn test_theme_toggle_in_html() {
     assert!(index_content.contains("/web/pkg/enkronio.js"));
 }
 
-/// Tests that CSS files have cache-busting query strings.
-/// Verifies hash-based versioning for static assets.
+/// Tests that static assets have cache-busting query strings.
+/// Verifies hash-based versioning of stylesheets, favicons and fonts.
 #[test]
 fn test_css_cache_busting() {
     // Ensure site is built
@@ -182,5 +188,235 @@ fn test_css_cache_busting() {
     let index_content = fs::read_to_string("index.html").expect("Failed to read index.html");
     // Check for version query strings on CSS links
     assert!(index_content.contains("/css/main.css?v="));
-    assert!(index_content.contains("/web/hack.css?v="));
+    assert!(index_content.contains("/pub/web/hack.css?v="));
+    assert!(index_content.contains("/favicon/favicon.ico?v="));
+
+    let hack_css = fs::read_to_string("pub/web/hack.css").expect("Failed to read hack.css");
+    assert!(hack_css.contains("url('/web/fonts/hack-regular.woff2?v="));
+    assert!(PathBuf::from("pub/assets.json").exists());
+}
+
+/// Tests that `--force` bypasses the build cache.
+/// Verifies a forced build regenerates outputs even when nothing changed.
+#[test]
+fn test_site_build_force() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--", "--force"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(
+        output.status.success(),
+        "Forced site build failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Up to date"));
+    assert!(PathBuf::from(".enkronio-cache").exists());
+}
+
+/// Tests that an explicitly requested config file must exist.
+/// Verifies `--config` with a missing path fails instead of silently using defaults.
+#[test]
+fn test_missing_config_file_fails() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--", "--config", "no-such-config.toml"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no-such-config.toml"));
+}
+
+/// Tests feed generation.
+/// Verifies Atom, RSS and JSON feeds are written and list public entries.
+#[test]
+fn test_feed_generation() {
+    // Ensure site is built
+    Command::new("cargo")
+        .args(["run", "--release"])
+        .output()
+        .expect("Failed to build site");
+
+    let atom = fs::read_to_string("pub/atom.xml").expect("Failed to read atom.xml");
+    assert!(atom.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    assert!(atom.contains("/pub/entries/1.html"));
+
+    let rss = fs::read_to_string("pub/rss.xml").expect("Failed to read rss.xml");
+    assert!(rss.contains("<rss version=\"2.0\""));
+
+    let json = fs::read_to_string("pub/feed.json").expect("Failed to read feed.json");
+    assert!(json.contains("https://jsonfeed.org/version/1.1"));
+}
+
+/// Tests search index generation.
+/// Verifies public entries are indexed and pages load the index lazily from the search box.
+#[test]
+fn test_search_index_generation() {
+    // Ensure site is built
+    Command::new("cargo")
+        .args(["run", "--release"])
+        .output()
+        .expect("Failed to build site");
+
+    let index = fs::read_to_string("pub/search.json").expect("Failed to read search.json");
+    assert!(index.contains("\"/pub/entries/1.html\""));
+    assert!(!index.contains("/priv/"));
+
+    let index_content = fs::read_to_string("index.html").expect("Failed to read index.html");
+    assert!(index_content.contains("data-index=\"/pub/search.json\""));
+}
+
+/// Tests the link checker.
+/// Verifies that every internal link of the generated site resolves.
+#[test]
+fn test_check_command() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--", "check"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "link check failed:\n{stderr}");
+    assert!(stderr.contains("no broken links"));
+}
+
+/// Tests the `--as-of` build option.
+/// Verifies that an unparseable publish time is rejected before anything is built.
+#[test]
+fn test_build_rejects_invalid_as_of() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--", "--as-of", "next tuesday"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid date 'next tuesday'"));
+}
+
+/// Tests where build options are accepted.
+/// Verifies that options of the bare build are rejected before another command, and
+/// commands that don't build don't take them at all.
+#[test]
+fn test_build_options_only_apply_where_they_build() {
+    for args in [
+        &["--force", "check"][..],
+        &["--verify-reproducible", "check"],
+        &["check", "--verify-reproducible"],
+        &["list", "--dry-run"],
+        &["rename", "1", "x", "--dry-run"],
+    ] {
+        let output = Command::new("cargo")
+            .args(["run", "--release", "--"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(!output.status.success(), "{args:?} was accepted");
+    }
+}
+
+/// Tests reproducible builds.
+/// Verifies two builds with the same `SOURCE_DATE_EPOCH` are identical and pages carry
+/// no placeholder build metadata.
+#[test]
+fn test_verify_reproducible() {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--", "--verify-reproducible"])
+        .env("SOURCE_DATE_EPOCH", "1717200000")
+        .output()
+        .expect("Failed to execute command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "builds differ:\n{stderr}");
+    assert!(stderr.contains("Reproducible:"));
+
+    let entry = fs::read_to_string("pub/entries/1.html").expect("Failed to read entry");
+    assert!(entry.contains("<p>updated: "));
+    assert!(!entry.contains("no GITHUB"));
+}
+
+/// Tests pruning of stale outputs.
+/// Verifies a dry run only lists outputs an earlier build recorded but no longer
+/// produces, and a regular build removes them.
+#[test]
+fn test_build_prunes_stale_outputs() {
+    let cache_dir = TempDir::new().expect("Failed to create temp directory");
+    let cache = cache_dir.path().join("cache.json");
+    let stale = cache_dir.path().join("stale.html");
+    fs::write(&stale, "old").expect("Failed to write stale output");
+    fs::write(
+        &cache,
+        format!(
+            "{{\"version\": \"2\", \"outputs\": {{\"{}\": \"k\"}}}}",
+            stale.display()
+        ),
+    )
+    .expect("Failed to write cache");
+
+    let config = cache_dir.path().join("enkronio.toml");
+    let toml = fs::read_to_string("enkronio.toml").expect("Failed to read enkronio.toml");
+    fs::write(
+        &config,
+        toml.replace(
+            "cache = \".enkronio-cache\"",
+            &format!("cache = \"{}\"", cache.display()),
+        ),
+    )
+    .expect("Failed to write config");
+
+    let build = |args: &[&str]| {
+        let output = Command::new("cargo")
+            .args(["run", "--release", "--", "--config"])
+            .arg(&config)
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "Site build failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    assert!(build(&["--dry-run"]).contains("Would remove:"));
+    assert!(stale.exists());
+    assert!(build(&[]).contains("Removed:"));
+    assert!(!stale.exists());
+}
+
+/// Tests the `list` command.
+/// Verifies that every public entry is listed in the JSON output and that filters apply.
+#[test]
+fn test_list_command() {
+    let list = |args: &[&str]| {
+        let output = Command::new("cargo")
+            .args(["run", "--release", "--", "list"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "list failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let rows: serde_json::Value =
+        serde_json::from_str(&list(&["--json"])).expect("list --json should print JSON");
+    let rows = rows.as_array().expect("list --json should print an array");
+    assert!(rows
+        .iter()
+        .any(|row| row["number"] == 1 && row["shadow"] == false));
+    assert!(rows.iter().all(|row| row["output"].is_string()));
+
+    assert!(!list(&["--shadow"]).contains("pub/entries/"));
+
+    let drafts = list(&["--as-of", "2024-01-01", "--drafts"]);
+    assert!(drafts.contains("scheduled"));
+    assert!(!drafts.contains("26.XII.2023"));
 }